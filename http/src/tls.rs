@@ -0,0 +1,274 @@
+//! TLS configuration for the http client.
+//!
+//! By default the client relies on ureq's TLS setup, which validates server certificates
+//! against the Mozilla root store. This module adds public key pinning, custom root
+//! certificates and an insecure mode for testing.
+
+use crate::{Error, Result};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, OtherError, RootCertStore, SignatureScheme};
+use std::sync::Arc;
+
+/// A root certificate which should be trusted by the client.
+#[derive(Debug, Clone)]
+pub struct Certificate(CertificateDer<'static>);
+
+impl Certificate {
+    /// Create a certificate from DER encoded bytes.
+    #[must_use]
+    pub fn from_der(der: &[u8]) -> Self {
+        Self(CertificateDer::from(der.to_vec()))
+    }
+
+    /// Create a certificate from the first PEM encoded certificate in `pem`.
+    ///
+    /// # Errors
+    /// Returns error if no valid certificate could be found.
+    pub fn from_pem(pem: &[u8]) -> Result<Self> {
+        CertificateDer::from_pem_slice(pem)
+            .map(Self)
+            .map_err(|e| Error::Unexpected(anyhow::anyhow!("Invalid PEM certificate: {e}")))
+    }
+}
+
+/// SHA-256 digest of a certificate's DER encoded `SubjectPublicKeyInfo`.
+type PublicKeyPin = [u8; 32];
+
+/// Decode a base64 encoded SHA-256 SPKI pin.
+pub(crate) fn decode_pin(pin: &str) -> Result<PublicKeyPin> {
+    BASE64_STANDARD
+        .decode(pin)
+        .ok()
+        .and_then(|bytes| PublicKeyPin::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| Error::Unexpected(anyhow::anyhow!("Invalid public key pin: {pin}")))
+}
+
+/// Compute the base64 encoded SHA-256 digest of the certificate's `SubjectPublicKeyInfo`.
+///
+/// This is the same value as produced by
+/// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`
+/// and can be used with [`crate::ClientBuilder::pin_public_key`].
+///
+/// # Errors
+/// Returns error if the certificate could not be parsed.
+pub fn public_key_pin(certificate: &Certificate) -> Result<String> {
+    spki_digest(&certificate.0)
+        .map(|digest| BASE64_STANDARD.encode(digest))
+        .ok_or_else(|| Error::Unexpected(anyhow::anyhow!("Invalid certificate")))
+}
+
+fn spki_digest(cert: &CertificateDer<'_>) -> Option<PublicKeyPin> {
+    let cert = webpki::EndEntityCert::try_from(cert).ok()?;
+    let spki = cert.subject_public_key_info();
+    let digest = ring::digest::digest(&ring::digest::SHA256, spki.as_ref());
+    PublicKeyPin::try_from(digest.as_ref()).ok()
+}
+
+/// Error reported when none of the server's certificates match the pinned public keys.
+#[derive(Debug, thiserror::Error)]
+#[error("No pinned public key matched the certificate chain of {host}")]
+pub(crate) struct PinningError {
+    pub(crate) host: String,
+}
+
+/// TLS options collected by the [`crate::ClientBuilder`].
+#[derive(Debug, Default)]
+pub(crate) struct TlsOptions {
+    pub(crate) pins: Vec<String>,
+    pub(crate) root_certificates: Vec<Certificate>,
+    pub(crate) disable_builtin_roots: bool,
+    pub(crate) accept_invalid_certs: bool,
+}
+
+impl TlsOptions {
+    /// Whether all options match the ureq defaults.
+    fn is_default(&self) -> bool {
+        self.pins.is_empty()
+            && self.root_certificates.is_empty()
+            && !self.disable_builtin_roots
+            && !self.accept_invalid_certs
+    }
+
+    /// Build a rustls configuration if any custom option was set.
+    pub(crate) fn build(&self) -> Result<Option<Arc<rustls::ClientConfig>>> {
+        if self.is_default() {
+            return Ok(None);
+        }
+
+        let pins = self
+            .pins
+            .iter()
+            .map(|pin| decode_pin(pin))
+            .collect::<Result<Vec<_>>>()?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+        let verifier: Arc<dyn ServerCertVerifier> = if self.accept_invalid_certs {
+            Arc::new(NoVerifier {
+                algorithms: provider.signature_verification_algorithms,
+            })
+        } else {
+            let mut roots = RootCertStore::empty();
+            if !self.disable_builtin_roots {
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            }
+            for cert in &self.root_certificates {
+                roots.add(cert.0.clone()).map_err(|e| {
+                    Error::Unexpected(anyhow::anyhow!("Invalid root certificate: {e}"))
+                })?;
+            }
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .map_err(|e| Error::Unexpected(anyhow::anyhow!("Invalid TLS setup: {e}")))?
+        };
+
+        let verifier: Arc<dyn ServerCertVerifier> = if pins.is_empty() {
+            verifier
+        } else {
+            Arc::new(PinningVerifier {
+                inner: verifier,
+                pins,
+            })
+        };
+
+        let config = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| Error::Unexpected(anyhow::anyhow!("Invalid TLS setup: {e}")))?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth();
+
+        Ok(Some(Arc::new(config)))
+    }
+}
+
+/// Find the rustls error which caused `error`, if any.
+pub(crate) fn rustls_error<'a>(
+    error: &'a (dyn std::error::Error + 'static),
+) -> Option<&'a rustls::Error> {
+    crate::error_chain(error).find_map(|e| e.downcast_ref::<rustls::Error>())
+}
+
+/// Check whether `error` was caused by a failed public key pin and return the host name.
+pub(crate) fn pinning_failure(error: &(dyn std::error::Error + 'static)) -> Option<String> {
+    let rustls::Error::InvalidCertificate(CertificateError::Other(other)) = rustls_error(error)?
+    else {
+        return None;
+    };
+    other
+        .0
+        .downcast_ref::<PinningError>()
+        .map(|e| e.host.clone())
+}
+
+/// Verifies the certificate chain with `inner` and then checks that at least one of the
+/// presented certificates matches the pinned public keys.
+#[derive(Debug)]
+struct PinningVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    pins: Vec<PublicKeyPin>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let matched = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(spki_digest)
+            .any(|digest| self.pins.contains(&digest));
+
+        if matched {
+            return Ok(verified);
+        }
+
+        tracing::error!("Public key pinning failed for {}", server_name.to_str());
+        Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+            OtherError(Arc::new(PinningError {
+                host: server_name.to_str().into_owned(),
+            })),
+        )))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Accepts any server certificate. Handshake signatures are still verified.
+#[derive(Debug)]
+struct NoVerifier {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
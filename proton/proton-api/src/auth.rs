@@ -5,6 +5,7 @@ use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{Display, Formatter};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Authentication token for access to protected API endpoints.
 #[derive(Clone)]
@@ -48,6 +49,7 @@ impl<'de> Deserialize<'de> for RefreshToken {
 
 /// Represents a session id.
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
 pub struct Uid(pub String);
 
 impl Display for Uid {
@@ -85,6 +87,45 @@ pub struct Auth {
     pub auth_token: Token,
     /// Refresh token.
     pub refresh_token: RefreshToken,
+    /// Unix timestamp in seconds at which the authentication token expires, if known.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Whether the server rejected the refresh token. The data is only deleted if a later
+    /// refresh finds it unchanged, since another process may have rotated the token.
+    #[serde(default)]
+    pub refresh_rejected: bool,
+}
+
+impl Auth {
+    /// Whether the authentication token expires within `duration`.
+    ///
+    /// Tokens without a known expiry time are never considered to expire.
+    #[must_use]
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| unix_now() + duration.as_secs() >= expires_at)
+    }
+
+    /// Whether this data has the same refresh token as `other`.
+    #[must_use]
+    pub fn has_refresh_token_of(&self, other: &Auth) -> bool {
+        self.uid == other.uid
+            && self.refresh_token.0.expose_secret() == other.refresh_token.0.expose_secret()
+    }
+}
+
+/// Unix timestamp in seconds at which a token which is valid for `expires_in` seconds
+/// expires.
+#[must_use]
+pub fn expiry_timestamp(expires_in: u64) -> u64 {
+    unix_now() + expires_in
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
 
 impl Serialize for Auth {
@@ -92,10 +133,12 @@ impl Serialize for Auth {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("Auth", 3)?;
+        let mut state = serializer.serialize_struct("Auth", 5)?;
         state.serialize_field("uid", self.uid.as_ref())?;
         state.serialize_field("auth_token", self.auth_token.0.expose_secret())?;
         state.serialize_field("refresh_token", self.refresh_token.0.expose_secret())?;
+        state.serialize_field("expires_at", &self.expires_at)?;
+        state.serialize_field("refresh_rejected", &self.refresh_rejected)?;
         state.end()
     }
 }
@@ -133,6 +176,61 @@ pub trait Store {
     /// # Errors
     /// Returns error if writing the data failed.
     fn delete(&mut self) -> Result<(), StoreError>;
+
+    /// Re-read the authentication data before a refresh.
+    ///
+    /// Stores which are shared with other processes should load the latest data, since another
+    /// process may have refreshed it already.
+    ///
+    /// # Errors
+    /// Returns error if reading the data failed.
+    fn reload(&mut self) -> Result<Option<&Auth>, StoreError> {
+        self.get()
+    }
+
+    /// Apply `update` if the stored data still has the refresh token of `current`, which
+    /// was used for the refresh. Returns whether the update was applied.
+    ///
+    /// If another session refreshed the data in the meantime, its data is kept. Stores which
+    /// are shared with other processes should check and write the data atomically.
+    ///
+    /// # Errors
+    /// Returns error if reading or writing the data failed.
+    fn update_if_unchanged(
+        &mut self,
+        current: &Auth,
+        update: AuthUpdate,
+    ) -> Result<bool, StoreError> {
+        let Some(stored) = self
+            .get()?
+            .filter(|auth| auth.has_refresh_token_of(current))
+            .cloned()
+        else {
+            return Ok(false);
+        };
+        match update {
+            AuthUpdate::Keep => {}
+            AuthUpdate::Store(auth) => self.store(auth)?,
+            AuthUpdate::Reject => self.store(Auth {
+                refresh_rejected: true,
+                ..stored
+            })?,
+            AuthUpdate::Delete => self.delete()?,
+        }
+        Ok(true)
+    }
+}
+
+/// Change to apply to the authentication data in [`Store::update_if_unchanged`].
+pub enum AuthUpdate {
+    /// Keep the current data.
+    Keep,
+    /// Replace the data.
+    Store(Auth),
+    /// Keep the data, but mark its refresh token as rejected, see [`Auth::refresh_rejected`].
+    Reject,
+    /// Delete the data.
+    Delete,
 }
 
 /// Provides an in Memory authentication storage.
@@ -1,8 +1,23 @@
 use crate::session::DEFAULT_HOST_URL;
 use http::{Client, ClientBuilder};
 
+/// Public key pins (base64 encoded SHA-256 of the `SubjectPublicKeyInfo`) of the Proton API
+/// certificates, as used by the official clients.
+pub const PROTON_PINNED_KEYS: &[&str] = &[
+    // mail.proton.me
+    "CT56BhOTmj5ZIPgb/xD5mH8rY3BLo/MlhP7oPyJUEDo=",
+    "35Dx28/uzN3LeltkCBQ8RHK0tlNSa2kCpCRGNp34Gxc=",
+    "qYIukVc63DEITct8sFT7ebIq5qsWmuscaIKeJx+5J5A=",
+    // Alternative routing hosts.
+    "EU6TS9MO0L/GsDHvVc9D5fChYLNy5JdGYpJw0ccgetM=",
+    "iKPIHPnDNqdkvOnTClQ8zQAIKG0XavaPkcEo0LBAABA=",
+    "MSlVrBCdL0hKyczvgYVSRNm88RicyY04Q2y5qrBt0xA=",
+    "C2UxW0T1Ckl9s+8cXfjXxlEqwAfPM4HiW2y3UdtBeCw=",
+];
+
 pub trait ProtonExtension {
-    /// Prepare a client builder for the default proton server.
+    /// Prepare a client builder for the default proton server with the Proton public keys
+    /// pinned.
     fn proton_client() -> ClientBuilder;
 }
 
@@ -10,6 +25,6 @@ impl ProtonExtension for Client {
     fn proton_client() -> ClientBuilder {
         // This should never fail.
         let base_url = http::url::Url::parse(DEFAULT_HOST_URL).unwrap();
-        Client::builder(base_url)
+        Client::builder(base_url).pin_public_keys(PROTON_PINNED_KEYS.iter().copied())
     }
 }
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use serde_repr::Deserialize_repr;
+use std::fmt::{Display, Formatter};
+
+/// Address API ID.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Hash, Clone)]
+pub struct Id(pub String);
+
+impl Display for Id {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Status of an address.
+#[derive(Debug, Deserialize_repr, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "mocks", derive(serde_repr::Serialize_repr))]
+#[repr(u8)]
+pub enum Status {
+    Disabled = 0,
+    Enabled = 1,
+    Deleting = 2,
+}
+
+/// Represents an email address of the user.
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct Address {
+    #[serde(rename = "ID")]
+    pub id: Id,
+    pub email: String,
+    #[serde(default)]
+    pub display_name: String,
+    pub status: Status,
+    #[serde(default)]
+    pub order: i32,
+}
@@ -0,0 +1,54 @@
+use crate::domain::label::Id as LabelId;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Conversation API ID.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Hash, Clone)]
+pub struct Id(pub String);
+
+impl Display for Id {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Sender or recipient of a message.
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct Contact {
+    #[serde(default)]
+    pub name: String,
+    pub address: String,
+}
+
+/// Message statistics of a conversation within a label.
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct ConversationLabel {
+    #[serde(rename = "ID")]
+    pub id: LabelId,
+    #[serde(default)]
+    pub context_num_messages: u32,
+    #[serde(default)]
+    pub context_num_unread: u32,
+}
+
+/// Represents a group of messages which belong to the same thread.
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct Conversation {
+    #[serde(rename = "ID")]
+    pub id: Id,
+    pub subject: String,
+    #[serde(default)]
+    pub senders: Vec<Contact>,
+    pub num_messages: u32,
+    pub num_unread: u32,
+    /// Unix timestamp in seconds of the latest message in the conversation.
+    pub time: i64,
+    #[serde(default)]
+    pub labels: Vec<ConversationLabel>,
+}
@@ -7,7 +7,43 @@ use thiserror::Error;
 use tracing::error;
 
 pub const OPERATION_SUCCESS: u32 = 1000;
+const INVALID_VALUE: u32 = 2001;
+const APP_VERSION_MISSING: u32 = 5001;
+const APP_VERSION_BAD: u32 = 5003;
+const PASSWORD_WRONG: u32 = 8002;
 const HUMAN_VERIFICATION_REQUESTED: u32 = 9001;
+const ACCOUNT_DELETED: u32 = 10002;
+const ACCOUNT_DISABLED: u32 = 10003;
+const REFRESH_TOKEN_INVALID: u32 = 10013;
+const HTTP_TOO_MANY_REQUESTS: u16 = 429;
+
+/// Well known categories of errors returned by the proton server.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// One of the request values was rejected.
+    InvalidValue,
+    /// The request did not include an app version.
+    AppVersionMissing,
+    /// The app version is no longer supported and the client needs to be updated.
+    AppVersionBad,
+    /// The login credentials are not correct.
+    ///
+    /// The server also reports rejected second factor codes or security keys this way, the
+    /// login sequence returns those as [`crate::login::Error::TwoFactorFailed`].
+    WrongPassword,
+    /// The request needs to be repeated with human verification.
+    HumanVerificationRequired,
+    /// The account has been deleted.
+    AccountDeleted,
+    /// The account has been disabled.
+    AccountDisabled,
+    /// The refresh token is no longer valid and the session has ended.
+    InvalidRefreshToken,
+    /// Too many requests were made, the request should be retried later.
+    RateLimited,
+    /// Any other error.
+    Unknown,
+}
 
 /// Error status and details returned from proton server.
 #[derive(Debug, Deserialize)]
@@ -46,7 +82,24 @@ impl APIError {
     /// Check whether this error is a request to perform HV.
     #[must_use]
     pub fn is_human_verification_request(&self) -> bool {
-        self.api_code == HUMAN_VERIFICATION_REQUESTED
+        self.kind() == ErrorKind::HumanVerificationRequired
+    }
+
+    /// Categorize this error based on the api and http codes.
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        match self.api_code {
+            INVALID_VALUE => ErrorKind::InvalidValue,
+            APP_VERSION_MISSING => ErrorKind::AppVersionMissing,
+            APP_VERSION_BAD => ErrorKind::AppVersionBad,
+            PASSWORD_WRONG => ErrorKind::WrongPassword,
+            HUMAN_VERIFICATION_REQUESTED => ErrorKind::HumanVerificationRequired,
+            ACCOUNT_DELETED => ErrorKind::AccountDeleted,
+            ACCOUNT_DISABLED => ErrorKind::AccountDisabled,
+            REFRESH_TOKEN_INVALID => ErrorKind::InvalidRefreshToken,
+            _ if self.http_code == HTTP_TOO_MANY_REQUESTS => ErrorKind::RateLimited,
+            _ => ErrorKind::Unknown,
+        }
     }
 
     /// Attempt to decode the HV verification details.
@@ -128,6 +181,20 @@ impl APIError {
         }
     }
 
+    /// Create a new instance based of status code and the already read response `body`.
+    ///
+    /// Note that if we fail to parse the response json only the http status code is returned.
+    #[must_use]
+    pub fn with_status_and_body(status: u16, body: &str) -> Self {
+        match serde_json::from_str::<APIErrorDesc>(body) {
+            Ok(desc) => Self::with_desc(status, desc),
+            Err(e) => {
+                error!("Failed to decode API error string: {e}");
+                Self::new(status)
+            }
+        }
+    }
+
     /// Create a new instance with an `http_status` code.
     #[must_use]
     pub fn new(http_status: u16) -> Self {
@@ -1,3 +1,5 @@
+use crate::domain::message::MessageCount;
+use crate::domain::user::User;
 use serde::{Deserialize, Serialize};
 use serde_repr::Deserialize_repr;
 #[cfg(feature = "mocks")]
@@ -22,6 +24,31 @@ pub enum MoreEvents {
     Yes = 1,
 }
 
+/// Resources which need to be synchronized again, since they can't be reconstructed from the
+/// event stream.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Copy, Clone, Default)]
+#[serde(transparent)]
+pub struct RefreshFlags(pub u8);
+
+impl RefreshFlags {
+    pub const NONE: RefreshFlags = RefreshFlags(0);
+    pub const MAIL: RefreshFlags = RefreshFlags(1);
+    pub const CONTACTS: RefreshFlags = RefreshFlags(2);
+    pub const ALL: RefreshFlags = RefreshFlags(255);
+
+    /// Whether all the resources in `other` need to be refreshed.
+    #[must_use]
+    pub fn contains(self, other: RefreshFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether mail resources such as labels and messages need to be refreshed.
+    #[must_use]
+    pub fn is_mail(self) -> bool {
+        self.contains(Self::MAIL)
+    }
+}
+
 #[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
 #[serde(rename_all = "PascalCase")]
 #[cfg_attr(feature = "mocks", derive(Serialize))]
@@ -29,8 +56,17 @@ pub struct Event {
     #[serde(rename = "EventID")]
     pub event_id: Id,
     pub more: MoreEvents,
+    #[serde(default)]
+    pub refresh: RefreshFlags,
     pub messages: Option<Vec<Message>>,
     pub labels: Option<Vec<Label>>,
+    /// Updated message counts of the labels which changed.
+    pub message_counts: Option<Vec<MessageCount>>,
+    /// Updated user information.
+    pub user: Option<User>,
+    pub addresses: Option<Vec<Address>>,
+    /// Updated amount of storage used by the user in bytes.
+    pub used_space: Option<i64>,
 }
 
 #[derive(Debug, Deserialize_repr, Eq, PartialEq, Copy, Clone)]
@@ -54,6 +90,17 @@ pub struct Message {
     pub message: Option<crate::domain::message::Message>,
 }
 
+/// Event data related to an Address event.
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct Address {
+    #[serde(rename = "ID")]
+    pub id: crate::domain::address::Id,
+    pub action: Action,
+    pub address: Option<crate::domain::address::Address>,
+}
+
 /// Event data related to a Label event
 #[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
 #[cfg_attr(feature = "mocks", derive(Serialize))]
@@ -3,19 +3,21 @@
 //! When you encounter an [`APIError`] check if [`APIError::is_human_verification_request()`]
 //! returns true. If so retrieve the human verification data and perform the actions
 //! specific to the type of human verification.
-use serde::Deserialize;
+//!
+//! Captcha verification requires a web browser/view. Email and SMS verification can be
+//! completed without one by requesting a code with [`crate::login::Sequence::request_verification_code`]
+//! and submitting it with [`LoginData::with_code`].
+use serde::{Deserialize, Serialize};
 
 /// Human Verification Type return by API.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum VerificationType {
     /// User needs to solve a Captcha, use [`crate::captcha_get`] to retrieve the token, solve in a web
     /// browser/view and retrieve the token posted via an `HVCaptchaMessage`.
     Captcha,
-    /// User needs to verify via a token send via an email. Note: Request for this
-    /// verification is not yet implemented.
+    /// User needs to verify via a code sent to an email address.
     Email,
-    /// User needs to verify via a token send via sms. Note: Request for this verification is not
-    /// yet inmplemented.
+    /// User needs to verify via a code sent via sms to a phone number.
     Sms,
 }
 
@@ -46,6 +48,15 @@ pub struct LoginData {
 }
 
 impl LoginData {
+    /// Create HV login data from the verification `code` which was sent to `destination`.
+    #[must_use]
+    pub fn with_code(destination: &VerificationDestination, code: &str) -> Self {
+        Self {
+            hv_type: destination.verification_type(),
+            token: format!("{}:{}", destination.value(), code.trim()),
+        }
+    }
+
     /// Create HV login data from webview `data`.
     ///
     /// # Errors
@@ -56,8 +67,36 @@ impl LoginData {
     }
 }
 
+/// Where to send the verification code for email and sms Human Verification.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VerificationDestination {
+    /// Send the code to this email address.
+    Email(String),
+    /// Send the code via sms to this phone number.
+    Sms(String),
+}
+
+impl VerificationDestination {
+    /// Type of human verification performed with this destination.
+    #[must_use]
+    pub fn verification_type(&self) -> VerificationType {
+        match self {
+            VerificationDestination::Email(_) => VerificationType::Email,
+            VerificationDestination::Sms(_) => VerificationType::Sms,
+        }
+    }
+
+    /// The email address or phone number.
+    #[must_use]
+    pub fn value(&self) -> &str {
+        match self {
+            VerificationDestination::Email(v) | VerificationDestination::Sms(v) => v,
+        }
+    }
+}
+
 /// Information for the Human Verification request.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HumanVerification {
     /// Types of supported verification.
     pub methods: Vec<VerificationType>,
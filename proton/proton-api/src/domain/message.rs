@@ -1,4 +1,5 @@
 use crate::domain::Boolean;
+use crate::domain::conversation::Id as ConversationId;
 use crate::domain::label::Id as LabelId;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
@@ -26,4 +27,20 @@ pub struct Message {
     pub sender_address: String,
     pub sender_name: Option<String>,
     pub unread: Boolean,
+    #[serde(rename = "ConversationID", default)]
+    pub conversation_id: Option<ConversationId>,
+    /// Unix timestamp in seconds at which the message was received.
+    #[serde(default)]
+    pub time: i64,
+}
+
+/// Number of messages with a label.
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageCount {
+    #[serde(rename = "LabelID")]
+    pub label_id: LabelId,
+    pub total: u32,
+    pub unread: u32,
 }
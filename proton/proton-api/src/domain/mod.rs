@@ -1,10 +1,13 @@
 //! Domain Types.
 
+pub mod address;
+pub mod conversation;
 pub mod errors;
 pub mod event;
 pub mod human_verification;
 pub mod label;
 pub mod message;
+pub mod session;
 pub mod user;
 
 use serde_repr::Deserialize_repr;
@@ -31,6 +34,21 @@ impl Display for TwoFactorAuth {
     }
 }
 
+/// `WebAuthn` assertion produced by a security key for the FIDO2 login challenge.
+///
+/// See [`crate::login::Sequence::fido2_authentication_options`] for the challenge.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fido2Assertion {
+    /// Raw `clientDataJSON` of the assertion.
+    pub client_data: Vec<u8>,
+    /// Raw authenticator data of the assertion.
+    pub authenticator_data: Vec<u8>,
+    /// Signature over the authenticator data and the client data hash.
+    pub signature: Vec<u8>,
+    /// Id of the credential which produced the assertion.
+    pub credential_id: Vec<u8>,
+}
+
 #[derive(Debug, Deserialize_repr, Eq, PartialEq, Copy, Clone)]
 #[cfg_attr(feature = "mocks", derive(serde_repr::Serialize_repr))]
 #[repr(u8)]
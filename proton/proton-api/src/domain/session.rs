@@ -0,0 +1,24 @@
+use crate::auth::Uid;
+use crate::domain::Boolean;
+use serde::Deserialize;
+
+/// Represents an authenticated session of the user, e.g. one for each logged in device.
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct AuthSession {
+    #[serde(rename = "UID")]
+    pub uid: Uid,
+    /// Unix timestamp in seconds at which the session was created.
+    pub create_time: i64,
+    #[serde(rename = "ClientID")]
+    pub client_id: String,
+    /// Human readable name of the client which created the session.
+    #[serde(default)]
+    pub localized_client_name: String,
+    #[serde(rename = "MemberID", default)]
+    pub member_id: Option<String>,
+    /// Whether the session can be revoked by the user.
+    #[serde(default)]
+    pub revocable: Boolean,
+}
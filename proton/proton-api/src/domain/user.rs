@@ -4,6 +4,7 @@ use std::fmt::{Display, Formatter};
 
 /// Represents an API User ID.
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
 pub struct Id(pub(crate) String);
 
 impl AsRef<str> for Id {
@@ -20,6 +21,7 @@ impl Display for Id {
 
 /// Represent an user's API key ID.
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
 pub struct KeyId(pub(crate) String);
 
 impl Display for KeyId {
@@ -29,7 +31,8 @@ impl Display for KeyId {
 }
 
 /// Represents an API user
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
 pub struct User {
     #[serde(rename = "ID")]
@@ -45,7 +48,8 @@ pub struct User {
     pub keys: Vec<Key>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
 pub struct Key {
     #[serde(rename = "ID")]
@@ -54,13 +58,26 @@ pub struct Key {
     pub token: Option<String>,
     pub signature: Option<String>,
     #[serde(deserialize_with = "bool_from_integer")]
+    #[cfg_attr(feature = "mocks", serde(serialize_with = "bool_to_integer"))]
     pub primary: bool,
     #[serde(deserialize_with = "bool_from_integer")]
+    #[cfg_attr(feature = "mocks", serde(serialize_with = "bool_to_integer"))]
     pub active: bool,
     pub flags: Option<KeyState>,
 }
 
+/// Salt used to derive the passphrase of a user key from the mailbox password.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct KeySalt {
+    #[serde(rename = "ID")]
+    pub id: KeyId,
+    /// Base64 encoded salt, missing for legacy keys.
+    pub key_salt: Option<String>,
+}
+
 #[derive(Deserialize_repr, Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "mocks", derive(serde_repr::Serialize_repr))]
 #[repr(u8)]
 pub enum KeyState {
     None = 0,
@@ -79,3 +96,13 @@ where
         Ok(true)
     }
 }
+
+/// Serialize bool as integer
+#[cfg(feature = "mocks")]
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn bool_to_integer<S>(value: &bool, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_u8(u8::from(*value))
+}
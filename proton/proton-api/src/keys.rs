@@ -0,0 +1,52 @@
+//! Verification of the mailbox password of accounts in two password mode.
+//!
+//! The mailbox password is never sent to the server. The passphrase of the user's keys is
+//! derived from it with [`key_passphrase`] and [`unlock`] checks whether that passphrase
+//! unlocks an armored private key.
+
+use pgp::types::SecretKeyTrait;
+use pgp::{Deserializable, SignedSecretKey};
+use secrecy::SecretString;
+
+/// Length of the `$2y$10$` prefix followed by the encoded salt in a bcrypt hash.
+const BCRYPT_PREFIX_LEN: usize = 29;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Key salt is not valid base64")]
+    InvalidSalt,
+    #[error("Failed to derive key passphrase: {0}")]
+    Passphrase(#[from] proton_srp::MailboxHashError),
+    #[error("Invalid private key: {0}")]
+    InvalidKey(#[source] pgp::errors::Error),
+    #[error("Passphrase does not unlock the private key: {0}")]
+    WrongPassphrase(#[source] pgp::errors::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Derive the passphrase of a user key from the `mailbox_password` and the key's `salt`, see
+/// [`crate::requests::GetKeySaltsRequest`].
+///
+/// Legacy keys have no salt, their passphrase is the mailbox password itself.
+///
+/// # Errors
+/// Returns error if the salt is not valid.
+pub fn key_passphrase(mailbox_password: &str, salt: &[u8]) -> Result<SecretString> {
+    let hash = proton_srp::mailbox_password_hash(mailbox_password, salt)?;
+    let hash = String::from_utf8_lossy(hash.expose_secret());
+    Ok(SecretString::from(
+        hash.get(BCRYPT_PREFIX_LEN..).unwrap_or_default(),
+    ))
+}
+
+/// Check whether the armored `private_key` can be unlocked with `passphrase`.
+///
+/// # Errors
+/// Returns [`Error::WrongPassphrase`] if the passphrase does not unlock the key and
+/// [`Error::InvalidKey`] if the key could not be parsed.
+pub fn unlock(private_key: &str, passphrase: &str) -> Result<()> {
+    let (key, _) = SignedSecretKey::from_string(private_key).map_err(Error::InvalidKey)?;
+    key.unlock(|| passphrase.to_owned(), |_| Ok(()))
+        .map_err(Error::WrongPassphrase)
+}
@@ -2,8 +2,10 @@
 pub mod auth;
 pub mod client;
 pub mod domain;
+pub mod keys;
 pub mod login;
 pub mod requests;
+pub mod routing;
 pub mod session;
 
 #[cfg(feature = "mocks")]
@@ -1,11 +1,21 @@
-use crate::auth::{Auth, StoreError};
-use crate::domain::TwoFactorAuth;
-use crate::domain::errors::APIError;
-use crate::domain::human_verification::{HumanVerification, LoginData, VerificationType};
+use crate::auth::{Auth, StoreError, expiry_timestamp};
+use crate::domain::errors::{APIError, ErrorKind};
+use crate::domain::human_verification::{
+    HumanVerification, LoginData, VerificationDestination, VerificationType,
+};
 use crate::domain::user::User;
-use crate::requests::{PostAuthInfoRequest, PostAuthRequest, PostTOTPRequest, TFAStatus};
+use crate::domain::{Fido2Assertion, TwoFactorAuth};
+use crate::keys;
+use crate::requests::{
+    GetKeySaltsRequest, PasswordMode, PostAuthInfoRequest, PostAuthRequest, PostFIDO2Request,
+    PostTOTPRequest, PostVerificationCodeRequest, TFAStatus,
+};
 use crate::session::Session;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
 use proton_srp::{SRPAuth, SRPError, SRPProofB64};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
 use tracing::{Level, error};
 
 #[derive(Debug, thiserror::Error)]
@@ -28,36 +38,83 @@ pub enum Error {
     HumanVerificationTypeNotSupported(VerificationType),
     #[error("Auth Store:{0}")]
     AuthStore(#[from] StoreError),
+    #[error("Mailbox password is not valid")]
+    InvalidMailboxPassword,
+    #[error("Second factor authentication failed: {0}")]
+    TwoFactorFailed(APIError),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Get the kind of server error, if this error was returned by the server.
+    #[must_use]
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            Error::Api(e) | Error::TwoFactorFailed(e) => Some(e.kind()),
+            Error::HumanVerificationRequired(_) => Some(ErrorKind::HumanVerificationRequired),
+            _ => None,
+        }
+    }
+
+    /// Convert credential errors returned while submitting the second factor into
+    /// [`Error::TwoFactorFailed`].
+    fn from_second_factor(error: http::Error) -> Self {
+        match Self::from(error) {
+            Error::Api(e) if e.kind() == ErrorKind::WrongPassword => Error::TwoFactorFailed(e),
+            e => e,
+        }
+    }
+}
+
 impl From<http::Error> for Error {
     fn from(value: http::Error) -> Self {
         match value {
             http::Error::Http(code, response) => {
-                let api_err = APIError::with_status_and_response(code, response);
-                if let Ok(hv) = api_err.try_get_human_verification_details() {
-                    Self::HumanVerificationRequired(hv)
-                } else {
-                    Self::Api(api_err)
-                }
+                APIError::with_status_and_response(code, response).into()
             }
+            http::Error::Status(code, body) => APIError::with_status_and_body(code, &body).into(),
             _ => Self::Http(value),
         }
     }
 }
 
+impl From<APIError> for Error {
+    fn from(value: APIError) -> Self {
+        if let Ok(hv) = value.try_get_human_verification_details() {
+            Self::HumanVerificationRequired(hv)
+        } else {
+            Self::Api(value)
+        }
+    }
+}
+
 /// Guides the user through the login sequence for a proton account.
 ///
 /// The accounts start of with the usual email and password exchange.
 ///
-/// If enabled, the next step is 2FA.
+/// If enabled, the next step is 2FA with either a totp code or a FIDO2 security key.
+///
+/// Finally, accounts in two password mode need to provide their mailbox password.
 pub struct Sequence {
     session: Session,
     state: State,
     skip_server_proof: bool,
     user: Option<User>,
+    human_verification: Option<HumanVerification>,
+    two_password_mode: bool,
+}
+
+/// Serializable state of an in progress [`Sequence`], see [`Sequence::snapshot`].
+///
+/// The snapshot contains the authentication tokens of the partially logged in session and
+/// should only be persisted encrypted.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    state: State,
+    auth: Option<Auth>,
+    human_verification: Option<HumanVerification>,
+    two_password_mode: bool,
 }
 
 impl Sequence {
@@ -69,6 +126,8 @@ impl Sequence {
             state: State::LoggedOut,
             skip_server_proof: false,
             user: None,
+            human_verification: None,
+            two_password_mode: false,
         }
     }
 
@@ -85,13 +144,99 @@ impl Sequence {
             state: State::LoggedOut,
             skip_server_proof: true,
             user: None,
+            human_verification: None,
+            two_password_mode: false,
+        }
+    }
+
+    /// Restore a sequence from a `snapshot` taken with [`Self::snapshot`] on a fresh `session`.
+    ///
+    /// The authentication data of the snapshot is written into the session's auth store.
+    ///
+    /// # Errors
+    /// Returns error if the auth store failed.
+    pub fn resume(session: Session, snapshot: Snapshot) -> Result<Self> {
+        if let Some(auth) = snapshot.auth {
+            session.auth_store().write().store(auth).map_err(|e| {
+                error!("Failed to write authentication data to store: {e}");
+                e
+            })?;
         }
+
+        Ok(Self {
+            session,
+            state: snapshot.state,
+            skip_server_proof: false,
+            user: None,
+            human_verification: snapshot.human_verification,
+            two_password_mode: snapshot.two_password_mode,
+        })
+    }
+
+    /// Capture the current state of the sequence so it can be resumed later with
+    /// [`Self::resume`], e.g. after the process was killed while waiting on the 2FA code.
+    ///
+    /// # Errors
+    /// Returns error if the auth store failed.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let auth = self
+            .session
+            .auth_store()
+            .read()
+            .get()
+            .map_err(|e| {
+                error!("Failed to read authentication data from store: {e}");
+                e
+            })?
+            .cloned();
+
+        Ok(Snapshot {
+            state: self.state.clone(),
+            auth,
+            human_verification: self.human_verification.clone(),
+            two_password_mode: self.two_password_mode,
+        })
     }
 
     /// Whether the account is waiting on totp code.
     #[must_use]
     pub fn is_awaiting_totp(&self) -> bool {
-        matches!(self.state, State::AwaitingTotp)
+        matches!(
+            self.state,
+            State::AwaitingTotp
+                | State::AwaitingFido2 {
+                    allow_totp: true,
+                    ..
+                }
+        )
+    }
+
+    /// Whether the account is waiting on a FIDO2 assertion.
+    ///
+    /// Accounts which have both totp and FIDO2 enabled can complete the login with either
+    /// [`Self::submit_totp`] or [`Self::submit_fido2`].
+    #[must_use]
+    pub fn is_awaiting_fido2(&self) -> bool {
+        matches!(self.state, State::AwaitingFido2 { .. })
+    }
+
+    /// `WebAuthn` assertion options (`PublicKeyCredentialRequestOptions`) which need to be
+    /// passed on to the authenticator when waiting on a FIDO2 assertion.
+    #[must_use]
+    pub fn fido2_authentication_options(&self) -> Option<&serde_json::Value> {
+        match &self.state {
+            State::AwaitingFido2 {
+                authentication_options,
+                ..
+            } => Some(authentication_options),
+            _ => None,
+        }
+    }
+
+    /// Whether the account is waiting on the mailbox password.
+    #[must_use]
+    pub fn is_awaiting_mailbox_password(&self) -> bool {
+        matches!(self.state, State::AwaitingMailboxPassword)
     }
 
     /// Whether the account is logged out.
@@ -109,7 +254,9 @@ impl Sequence {
     /// Login with `email` and `password`.
     ///
     /// If [`Error::HumanVerificationRequired`] is returned, you need to resolve the challenge
-    /// and retry again with the resulting value for `human_verification_login_data`.
+    /// and retry again with the resulting value for `human_verification_login_data`. Email and
+    /// sms challenges can be resolved without a web view, see
+    /// [`Self::request_verification_code`].
     ///
     /// # Errors
     /// Returns error if the request or the auth store failed, 2FA method is not supported
@@ -127,12 +274,6 @@ impl Sequence {
             return Err(Error::InvalidState);
         };
 
-        if let Some(hv) = human_verification_login_data {
-            if hv.hv_type != VerificationType::Captcha {
-                return Err(Error::HumanVerificationTypeNotSupported(hv.hv_type));
-            }
-        }
-
         self.catch_captcha(|this| {
             let auth_info_response = this
                 .session
@@ -180,14 +321,31 @@ impl Sequence {
                 ));
             }
 
+            this.two_password_mode = auth_response.password_mode == PasswordMode::Two;
             match auth_response.tfa.enabled {
                 TFAStatus::None => {
-                    this.state = State::LoggedIn;
+                    this.state = this.state_after_second_factor();
                 }
-                TFAStatus::Totp | TFAStatus::TotpOrFIDO2 => {
+                TFAStatus::Totp => {
                     this.state = State::AwaitingTotp;
                 }
-                TFAStatus::FIDO2 => return Err(Error::Unsupported2FA(TwoFactorAuth::FIDO2)),
+                TFAStatus::FIDO2 | TFAStatus::TotpOrFIDO2 => {
+                    let allow_totp = auth_response.tfa.enabled == TFAStatus::TotpOrFIDO2;
+                    let authentication_options =
+                        auth_response.tfa.fido2_info.authentication_options;
+                    this.state = if authentication_options.is_null() {
+                        if !allow_totp {
+                            error!("Account requires FIDO2, but no options were provided");
+                            return Err(Error::Unsupported2FA(TwoFactorAuth::FIDO2));
+                        }
+                        State::AwaitingTotp
+                    } else {
+                        State::AwaitingFido2 {
+                            authentication_options,
+                            allow_totp,
+                        }
+                    };
+                }
             }
 
             let mut guard = this.session.auth_store().write();
@@ -196,6 +354,8 @@ impl Sequence {
                     uid: auth_response.uid,
                     auth_token: auth_response.access_token,
                     refresh_token: auth_response.refresh_token,
+                    expires_at: auth_response.expires_in.map(expiry_timestamp),
+                    refresh_rejected: false,
                 })
                 .map_err(|e| {
                     error!("Failed to write authentication data to store: {e}");
@@ -208,6 +368,42 @@ impl Sequence {
         })
     }
 
+    /// Request a human verification code to be sent to `destination`.
+    ///
+    /// Once received, retry [`Self::login`] with [`LoginData::with_code`].
+    ///
+    /// # Errors
+    /// Returns error if the request failed.
+    ///
+    /// [`Error::InvalidState`] is returned if no human verification was requested by the last
+    /// login attempt and [`Error::HumanVerificationTypeNotSupported`] if the type of
+    /// `destination` was not one of the offered methods.
+    #[tracing::instrument(level=Level::DEBUG,skip(self, destination))]
+    pub fn request_verification_code(&self, destination: &VerificationDestination) -> Result<()> {
+        let Some(hv) = &self.human_verification else {
+            return Err(Error::InvalidState);
+        };
+
+        let hv_type = destination.verification_type();
+        if !hv.methods.contains(&hv_type) {
+            return Err(Error::HumanVerificationTypeNotSupported(hv_type));
+        }
+
+        self.session
+            .execute(PostVerificationCodeRequest::new(destination))
+            .map_err(|e| {
+                error!("Failed to request {hv_type} verification code: {e}");
+                e
+            })?;
+        Ok(())
+    }
+
+    /// Human verification requested by the last login attempt, if any.
+    #[must_use]
+    pub fn human_verification(&self) -> Option<&HumanVerification> {
+        self.human_verification.as_ref()
+    }
+
     /// Submit `totp` 2FA Code
     ///
     /// To check if the sequence needs a totp 2fa user [`is_awaiting_totp()`].
@@ -215,32 +411,133 @@ impl Sequence {
     /// # Errors
     /// Returns error if the request failed.
     ///
-    /// [`Error::InvalidState`] is returned if the sequence is not in a logged out state.
+    /// [`Error::InvalidState`] is returned if the sequence is not in a logged out state and
+    /// [`Error::TwoFactorFailed`] if the code was rejected.
     #[tracing::instrument(level=Level::DEBUG,skip(self, totp))]
     pub fn submit_totp(&mut self, totp: &str) -> Result<()> {
         self.catch_captcha(|this| {
-            if !matches!(this.state, State::AwaitingTotp) {
+            if !this.is_awaiting_totp() {
                 return Err(Error::InvalidState);
             };
             this.session
                 .execute_with_auth(PostTOTPRequest::new(totp))
                 .map_err(|e| {
                     error!("Failed to submit totp code: {e}");
-                    e
+                    Error::from_second_factor(e)
                 })?;
 
-            this.state = State::LoggedIn;
+            this.state = this.state_after_second_factor();
             this.next()?;
             Ok(())
         })
     }
 
+    /// Submit the FIDO2 `assertion` produced by the security key for the challenge in
+    /// [`Self::fido2_authentication_options`].
+    ///
+    /// To check if the sequence needs a FIDO2 assertion use [`Self::is_awaiting_fido2`].
+    ///
+    /// # Errors
+    /// Returns error if the request failed.
+    ///
+    /// [`Error::InvalidState`] is returned if the sequence is not waiting on a FIDO2 assertion
+    /// and [`Error::TwoFactorFailed`] if the assertion was rejected.
+    #[tracing::instrument(level=Level::DEBUG,skip(self, assertion))]
+    pub fn submit_fido2(&mut self, assertion: &Fido2Assertion) -> Result<()> {
+        self.catch_captcha(|this| {
+            let Some(authentication_options) = this.fido2_authentication_options() else {
+                return Err(Error::InvalidState);
+            };
+            this.session
+                .execute_with_auth(PostFIDO2Request::new(authentication_options, assertion))
+                .map_err(|e| {
+                    error!("Failed to submit fido2 assertion: {e}");
+                    Error::from_second_factor(e)
+                })?;
+
+            this.state = this.state_after_second_factor();
+            this.next()?;
+            Ok(())
+        })
+    }
+
+    /// Submit the mailbox `password` of an account in two password mode.
+    ///
+    /// The password is verified by unlocking at least one of the user's active keys with the
+    /// passphrase derived from it, see [`keys::key_passphrase`].
+    ///
+    /// To check if the sequence needs a mailbox password use
+    /// [`Self::is_awaiting_mailbox_password`].
+    ///
+    /// # Errors
+    /// Returns error if the key salts could not be retrieved.
+    ///
+    /// [`Error::InvalidMailboxPassword`] is returned if none of the keys could be unlocked
+    /// and [`Error::InvalidState`] if the sequence is not waiting on the mailbox password.
+    #[tracing::instrument(level=Level::DEBUG,skip(self, password))]
+    pub fn submit_mailbox_password(&mut self, password: &str) -> Result<()> {
+        if !self.is_awaiting_mailbox_password() {
+            return Err(Error::InvalidState);
+        }
+        // The user is not available yet if the sequence was resumed.
+        self.next()?;
+        let Some(user) = &self.user else {
+            return Err(Error::InvalidState);
+        };
+
+        let salts = self
+            .session
+            .execute_with_auth(GetKeySaltsRequest {})
+            .map_err(|e| {
+                error!("Failed to get key salts: {e}");
+                e
+            })?
+            .key_salts;
+
+        let verified = user.keys.iter().filter(|key| key.active).any(|key| {
+            let salt = salts
+                .iter()
+                .find(|salt| salt.id == key.id)
+                .and_then(|salt| salt.key_salt.as_deref());
+            let result = match salt {
+                Some(salt) => BASE64_STANDARD
+                    .decode(salt)
+                    .map_err(|_| keys::Error::InvalidSalt)
+                    .and_then(|salt| keys::key_passphrase(password, &salt))
+                    .and_then(|passphrase| {
+                        keys::unlock(&key.private_key, passphrase.expose_secret())
+                    }),
+                // Legacy keys are protected by the mailbox password itself.
+                None => keys::unlock(&key.private_key, password),
+            };
+            match result {
+                Ok(()) => true,
+                Err(keys::Error::WrongPassphrase(_)) => false,
+                Err(e) => {
+                    error!("Failed to unlock key {}: {e}", key.id);
+                    false
+                }
+            }
+        });
+
+        if !verified {
+            error!("Mailbox password did not unlock any key");
+            return Err(Error::InvalidMailboxPassword);
+        }
+
+        self.state = State::LoggedIn;
+        Ok(())
+    }
+
     /// Abort login by triggering a logout
     ///
     /// # Errors
     /// Returns error if we are not in a valid state or the request failed.
     pub fn logout(&mut self) -> Result<()> {
-        if !matches!(self.state, State::AwaitingTotp) {
+        if !matches!(
+            self.state,
+            State::AwaitingTotp | State::AwaitingFido2 { .. } | State::AwaitingMailboxPassword
+        ) {
             return Err(Error::InvalidState);
         };
 
@@ -260,6 +557,8 @@ impl Sequence {
             return Err(Error::InvalidState);
         }
 
+        // The user is not available yet if the sequence was resumed.
+        self.next()?;
         let Some(user) = self.user.take() else {
             return Err(Error::InvalidState);
         };
@@ -273,8 +572,16 @@ impl Sequence {
         &self.session
     }
 
+    fn state_after_second_factor(&self) -> State {
+        if self.two_password_mode {
+            State::AwaitingMailboxPassword
+        } else {
+            State::LoggedIn
+        }
+    }
+
     fn next(&mut self) -> Result<()> {
-        if self.is_logged_in() && self.user.is_none() {
+        if (self.is_logged_in() || self.is_awaiting_mailbox_password()) && self.user.is_none() {
             let user = self.session.user_info().map_err(|e| {
                 error!("Failed to fetch user info: {e}");
                 e
@@ -287,18 +594,26 @@ impl Sequence {
 
     fn catch_captcha(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
         if let Err(e) = f(self) {
-            if matches!(&e, Error::HumanVerificationRequired(_)) {
+            if let Error::HumanVerificationRequired(hv) = &e {
                 self.state = State::LoggedOut;
+                self.human_verification = Some(hv.clone());
             }
 
             return Err(e);
         }
+        self.human_verification = None;
         Ok(())
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 enum State {
     LoggedOut,
     AwaitingTotp,
+    AwaitingFido2 {
+        authentication_options: serde_json::Value,
+        allow_totp: bool,
+    },
+    AwaitingMailboxPassword,
     LoggedIn,
 }
@@ -1,11 +1,15 @@
-use crate::domain::{label, message};
+use crate::domain::conversation::Conversation;
+use crate::domain::{conversation, label, message};
 use crate::mocks::auth::MatchExtension;
 use crate::requests::{
-    PutLabelMessageRequest, PutLabelMessageResponse, PutMarkMessageReadRequest,
-    PutMarkMessageReadResponse,
+    GetConversationsRequest, GetConversationsResponse, GetMessageCountsRequest,
+    GetMessageCountsResponse, GetMessageRequest, GetMessageResponse, GetMessagesRequest,
+    GetMessagesResponse, PutLabelConversationRequest, PutLabelConversationResponse,
+    PutLabelMessageRequest, PutLabelMessageResponse, PutMarkConversationReadRequest,
+    PutMarkConversationReadResponse, PutMarkMessageReadRequest, PutMarkMessageReadResponse,
 };
 use http::Request;
-use mockito::{Mock, Server};
+use mockito::{Matcher, Mock, Server};
 
 /// Mock marking message as read with the given `ids` returning the given `response`.
 pub fn mark_message_read(
@@ -41,3 +45,121 @@ pub fn label_message(
         .with_body(serde_json::to_vec(response).unwrap())
         .create()
 }
+
+/// Mock marking the conversations with `ids` as read returning the given `response`.
+pub fn mark_conversation_read(
+    server: &mut Server,
+    ids: Vec<conversation::Id>,
+    response: &PutMarkConversationReadResponse,
+) -> Mock {
+    let request = PutMarkConversationReadRequest::new(ids);
+    server
+        .mock("PUT", format!("/{}", request.url()).as_str())
+        .match_body(serde_json::to_vec(&request).unwrap())
+        .match_auth()
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_vec(response).unwrap())
+        .create()
+}
+
+/// Mock labeling the conversations with `ids` with `label_id` returning the given `response`.
+pub fn label_conversation(
+    server: &mut Server,
+    label_id: label::Id,
+    ids: Vec<conversation::Id>,
+    response: &PutLabelConversationResponse,
+) -> Mock {
+    let request = PutLabelConversationRequest::new(label_id, ids);
+    server
+        .mock("PUT", format!("/{}", request.url()).as_str())
+        .match_body(serde_json::to_vec(&request).unwrap())
+        .match_auth()
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_vec(response).unwrap())
+        .create()
+}
+
+/// Mock retrieving the metadata of `message`.
+pub fn get_message(server: &mut Server, message: &message::Message) -> Mock {
+    let url = GetMessageRequest::new(&message.id).url();
+    let response = GetMessageResponse {
+        message: message.clone(),
+    };
+    server
+        .mock("GET", format!("/{url}").as_str())
+        .match_auth()
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_vec(&response).unwrap())
+        .create()
+}
+
+/// Mock listing the messages of `request` returning `messages` out of `total`.
+pub fn get_messages(
+    server: &mut Server,
+    request: &GetMessagesRequest,
+    messages: &[message::Message],
+    total: u64,
+) -> Mock {
+    let response = GetMessagesResponse {
+        total,
+        messages: messages.to_owned(),
+    };
+    server
+        .mock("GET", format!("/{}", request.url()).as_str())
+        .match_query(query_matcher(request.query()))
+        .match_auth()
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_vec(&response).unwrap())
+        .create()
+}
+
+/// Mock listing the conversations of `request` returning `conversations` out of `total`.
+pub fn get_conversations(
+    server: &mut Server,
+    request: &GetConversationsRequest,
+    conversations: &[Conversation],
+    total: u64,
+) -> Mock {
+    let response = GetConversationsResponse {
+        total,
+        conversations: conversations.to_owned(),
+    };
+    server
+        .mock("GET", format!("/{}", request.url()).as_str())
+        .match_query(query_matcher(request.query()))
+        .match_auth()
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_vec(&response).unwrap())
+        .create()
+}
+
+/// Mock retrieving the message `counts` of all labels.
+pub fn get_message_counts(server: &mut Server, counts: &[message::MessageCount]) -> Mock {
+    let response = GetMessageCountsResponse {
+        counts: counts.to_owned(),
+    };
+    server
+        .mock(
+            "GET",
+            format!("/{}", GetMessageCountsRequest {}.url()).as_str(),
+        )
+        .match_auth()
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(serde_json::to_vec(&response).unwrap())
+        .create()
+}
+
+fn query_matcher(query: Vec<(&'static str, String)>) -> Matcher {
+    Matcher::AllOf(
+        query
+            .into_iter()
+            .map(|(key, value)| Matcher::UrlEncoded(key.to_owned(), value))
+            .collect(),
+    )
+}
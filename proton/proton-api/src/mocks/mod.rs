@@ -4,6 +4,7 @@ pub mod auth;
 pub mod events;
 pub mod labels;
 pub mod message;
+pub mod routing;
 
 pub use mockito;
 use mockito::{Server, ServerOpts};
@@ -0,0 +1,30 @@
+use crate::routing::DEFAULT_QUERY_NAME;
+use mockito::{Matcher, Mock, Server};
+
+/// Mock a JSON DNS-over-HTTPS resolver at `/dns-query` which answers the alternative
+/// routing TXT query with `routes`.
+pub fn doh_resolver(server: &mut Server, routes: &[&str]) -> Mock {
+    let answer = routes
+        .iter()
+        .map(|route| {
+            serde_json::json!({
+                "name": DEFAULT_QUERY_NAME,
+                "type": 16,
+                "TTL": 60,
+                "data": format!("\"{route}\""),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    server
+        .mock("GET", "/dns-query")
+        .match_header("Accept", "application/dns-json")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("name".into(), DEFAULT_QUERY_NAME.into()),
+            Matcher::UrlEncoded("type".into(), "TXT".into()),
+        ]))
+        .with_status(200)
+        .with_header("Content-Type", "application/dns-json")
+        .with_body(serde_json::json!({"Status": 0, "Answer": answer}).to_string())
+        .create()
+}
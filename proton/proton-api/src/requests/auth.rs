@@ -1,5 +1,9 @@
 use crate::auth::{RefreshToken, Token, Uid};
-use crate::domain::human_verification::LoginData;
+use crate::domain::Fido2Assertion;
+use crate::domain::human_verification::{LoginData, VerificationDestination};
+use crate::domain::session::AuthSession;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
 use http::{Method, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use serde_repr::Deserialize_repr;
@@ -83,6 +87,9 @@ pub struct PostAuthResponse {
     pub token_type: Option<String>,
     pub access_token: Token,
     pub refresh_token: RefreshToken,
+    /// Number of seconds until the access token expires.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
     pub server_proof: String,
     pub scope: String,
     #[serde(rename = "2FA")]
@@ -150,7 +157,7 @@ pub struct TFAAuthData<'a> {
 pub struct FIDO2AuthData<'a> {
     pub authentication_options: serde_json::Value,
     pub client_data: &'a str,
-    pub authentication_data: &'a str,
+    pub authenticator_data: &'a str,
     pub signature: &'a str,
     #[serde(rename = "CredentialID")]
     pub credential_id: &'a [i32],
@@ -162,7 +169,7 @@ impl FIDO2AuthData<'_> {
         FIDO2AuthData {
             authentication_options: serde_json::Value::Null,
             client_data: "",
-            authentication_data: "",
+            authenticator_data: "",
             signature: "",
             credential_id: &[],
         }
@@ -196,6 +203,56 @@ impl http::Request for PostTOTPRequest<'_> {
     }
 }
 
+pub struct PostFIDO2Request<'a> {
+    authentication_options: &'a serde_json::Value,
+    client_data: String,
+    authenticator_data: String,
+    signature: String,
+    credential_id: Vec<i32>,
+}
+
+impl<'a> PostFIDO2Request<'a> {
+    /// Create a new request which answers the `authentication_options` challenge with
+    /// `assertion`.
+    #[must_use]
+    pub fn new(authentication_options: &'a serde_json::Value, assertion: &Fido2Assertion) -> Self {
+        Self {
+            authentication_options,
+            client_data: BASE64_STANDARD.encode(&assertion.client_data),
+            authenticator_data: BASE64_STANDARD.encode(&assertion.authenticator_data),
+            signature: BASE64_STANDARD.encode(&assertion.signature),
+            credential_id: assertion
+                .credential_id
+                .iter()
+                .copied()
+                .map(i32::from)
+                .collect(),
+        }
+    }
+}
+
+impl http::Request for PostFIDO2Request<'_> {
+    type Response = http::NoResponse;
+    const METHOD: Method = Method::Post;
+
+    fn url(&self) -> String {
+        "auth/v4/2fa".to_owned()
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        Ok(builder.json(TFAAuthData {
+            two_factor_code: "",
+            fido2: FIDO2AuthData {
+                authentication_options: self.authentication_options.clone(),
+                client_data: &self.client_data,
+                authenticator_data: &self.authenticator_data,
+                signature: &self.signature,
+                credential_id: &self.credential_id,
+            },
+        }))
+    }
+}
+
 #[doc(hidden)]
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -218,6 +275,9 @@ pub struct PostAuthRefreshResponse {
     pub token_type: Option<String>,
     pub access_token: Token,
     pub refresh_token: RefreshToken,
+    /// Number of seconds until the access token expires.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
     pub scope: String,
 }
 
@@ -268,6 +328,47 @@ impl http::Request for LogoutRequest {
     }
 }
 
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+pub struct GetAuthSessionsResponse {
+    #[serde(rename = "Sessions")]
+    pub sessions: Vec<AuthSession>,
+}
+
+#[derive(Copy, Clone)]
+pub struct GetAuthSessionsRequest {}
+
+impl http::Request for GetAuthSessionsRequest {
+    type Response = http::JsonResponse<GetAuthSessionsResponse>;
+    const METHOD: Method = Method::Get;
+
+    fn url(&self) -> String {
+        "auth/v4/sessions".to_owned()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct DeleteAuthSessionRequest<'a> {
+    uid: &'a Uid,
+}
+
+impl<'a> DeleteAuthSessionRequest<'a> {
+    #[must_use]
+    pub fn new(uid: &'a Uid) -> Self {
+        Self { uid }
+    }
+}
+
+impl http::Request for DeleteAuthSessionRequest<'_> {
+    type Response = http::NoResponse;
+    const METHOD: Method = Method::Delete;
+
+    fn url(&self) -> String {
+        format!("auth/v4/sessions/{}", self.uid)
+    }
+}
+
 pub struct GetCaptchaRequest<'a> {
     token: &'a str,
     force_web: bool,
@@ -297,5 +398,61 @@ impl http::Request for GetCaptchaRequest<'_> {
     }
 }
 
-const X_PM_HUMAN_VERIFICATION_TOKEN: &str = "X-Pm-Human-Verification-Token";
-const X_PM_HUMAN_VERIFICATION_TOKEN_TYPE: &str = "X-Pm-Human-Verification-Token-Type";
+/// Request a human verification code to be sent to an email address or phone number.
+pub struct PostVerificationCodeRequest<'a> {
+    destination: &'a VerificationDestination,
+}
+
+impl<'a> PostVerificationCodeRequest<'a> {
+    #[must_use]
+    pub fn new(destination: &'a VerificationDestination) -> Self {
+        Self { destination }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct VerificationCodeDestination<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phone: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct VerificationCodeBody<'a> {
+    #[serde(rename = "Type")]
+    code_type: &'a str,
+    destination: VerificationCodeDestination<'a>,
+}
+
+impl http::Request for PostVerificationCodeRequest<'_> {
+    type Response = http::NoResponse;
+    const METHOD: Method = Method::Post;
+
+    fn url(&self) -> String {
+        "core/v4/users/code".to_owned()
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        let destination = match self.destination {
+            VerificationDestination::Email(address) => VerificationCodeDestination {
+                address: Some(address.as_str()),
+                phone: None,
+            },
+            VerificationDestination::Sms(phone) => VerificationCodeDestination {
+                address: None,
+                phone: Some(phone.as_str()),
+            },
+        };
+
+        Ok(builder.json(VerificationCodeBody {
+            code_type: self.destination.verification_type().as_str(),
+            destination,
+        }))
+    }
+}
+
+pub(crate) const X_PM_HUMAN_VERIFICATION_TOKEN: &str = "X-Pm-Human-Verification-Token";
+pub(crate) const X_PM_HUMAN_VERIFICATION_TOKEN_TYPE: &str = "X-Pm-Human-Verification-Token-Type";
@@ -0,0 +1,129 @@
+use crate::domain::conversation::{Conversation, Id};
+use crate::domain::label;
+use crate::requests::{MessageFilter, OperationResponse};
+use http::{Method, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+/// List the conversations matching a [`MessageFilter`], one page at a time.
+///
+/// Pages start at 0.
+pub struct GetConversationsRequest {
+    filter: MessageFilter,
+    page: u32,
+    page_size: u32,
+}
+
+impl GetConversationsRequest {
+    #[must_use]
+    pub fn new(filter: MessageFilter, page: u32, page_size: u32) -> Self {
+        Self {
+            filter,
+            page,
+            page_size,
+        }
+    }
+
+    /// Query parameters of this request.
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        self.filter.query(self.page, self.page_size)
+    }
+}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct GetConversationsResponse {
+    /// Total number of conversations matching the filter, across all pages.
+    pub total: u64,
+    pub conversations: Vec<Conversation>,
+}
+
+impl http::Request for GetConversationsRequest {
+    type Response = http::JsonResponse<GetConversationsResponse>;
+    const METHOD: Method = Method::Get;
+
+    fn url(&self) -> String {
+        "mail/v4/conversations".to_owned()
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        Ok(self
+            .query()
+            .into_iter()
+            .fold(builder, |builder, (key, value)| builder.query(key, value)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct PutMarkConversationReadResponse {
+    pub responses: Vec<OperationResponse<Id>>,
+}
+
+/// Mark all the messages of the given conversation ids as read.
+#[derive(Debug, Serialize)]
+pub struct PutMarkConversationReadRequest {
+    #[serde(rename = "IDs")]
+    pub ids: Vec<Id>,
+}
+
+impl PutMarkConversationReadRequest {
+    pub fn new(ids: impl IntoIterator<Item = Id>) -> Self {
+        Self {
+            ids: ids.into_iter().collect(),
+        }
+    }
+}
+
+impl http::Request for PutMarkConversationReadRequest {
+    type Response = http::JsonResponse<PutMarkConversationReadResponse>;
+    const METHOD: Method = Method::Put;
+
+    fn url(&self) -> String {
+        "mail/v4/conversations/read".to_owned()
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        Ok(builder.json(self))
+    }
+}
+
+/// Apply a label to all the messages of the given conversation ids.
+#[derive(Debug, Serialize)]
+pub struct PutLabelConversationRequest {
+    #[serde(rename = "IDs")]
+    pub ids: Vec<Id>,
+    #[serde(rename = "LabelID")]
+    pub label_id: label::Id,
+}
+
+impl PutLabelConversationRequest {
+    pub fn new(label_id: label::Id, ids: impl IntoIterator<Item = Id>) -> Self {
+        Self {
+            ids: ids.into_iter().collect(),
+            label_id,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "mocks", derive(serde::Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct PutLabelConversationResponse {
+    pub responses: Vec<OperationResponse<Id>>,
+}
+
+impl http::Request for PutLabelConversationRequest {
+    type Response = http::JsonResponse<PutLabelConversationResponse>;
+    const METHOD: Method = Method::Put;
+
+    fn url(&self) -> String {
+        "mail/v4/conversations/label".to_owned()
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        Ok(builder.json(self))
+    }
+}
@@ -1,23 +1,160 @@
 use crate::domain::errors::{APIError, APIErrorDesc, OPERATION_SUCCESS};
 use crate::domain::label;
-use crate::domain::message::Id;
+use crate::domain::message::{Id, Message, MessageCount};
 use http::{Method, RequestBuilder};
 use serde::{Deserialize, Serialize};
 
-/// Response items returned for message operations.
+/// Maximum number of items which can be requested in a single page.
+pub const MAX_PAGE_SIZE: u32 = 150;
+
+/// Criteria to select messages or conversations in paginated listings.
+///
+/// Results are always sorted by time, newest first.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MessageFilter {
+    /// Only include items with this label.
+    pub label_id: Option<label::Id>,
+    /// Only include unread (`true`) or read (`false`) items.
+    pub unread: Option<bool>,
+    /// Only include items sent from this address.
+    pub sender: Option<String>,
+    /// Only include items received at or after this unix timestamp in seconds.
+    pub begin: Option<i64>,
+    /// Only include items received at or before this unix timestamp in seconds.
+    pub end: Option<i64>,
+}
+
+impl MessageFilter {
+    /// Create a filter which only includes items with `label_id`.
+    #[must_use]
+    pub fn with_label(label_id: label::Id) -> Self {
+        Self {
+            label_id: Some(label_id),
+            ..Self::default()
+        }
+    }
+
+    /// Query parameters for the filter and the page with `page_size` items.
+    pub(crate) fn query(&self, page: u32, page_size: u32) -> Vec<(&'static str, String)> {
+        let mut query = vec![
+            ("Page", page.to_string()),
+            ("PageSize", page_size.min(MAX_PAGE_SIZE).to_string()),
+            ("Sort", "Time".to_owned()),
+            ("Desc", "1".to_owned()),
+        ];
+        if let Some(label_id) = &self.label_id {
+            query.push(("LabelID", label_id.0.clone()));
+        }
+        if let Some(unread) = self.unread {
+            query.push(("Unread", u8::from(unread).to_string()));
+        }
+        if let Some(sender) = &self.sender {
+            query.push(("From", sender.clone()));
+        }
+        if let Some(begin) = self.begin {
+            query.push(("Begin", begin.to_string()));
+        }
+        if let Some(end) = self.end {
+            query.push(("End", end.to_string()));
+        }
+        query
+    }
+}
+
+/// Get the metadata of a single message. The message body is not decoded.
+pub struct GetMessageRequest<'a> {
+    id: &'a Id,
+}
+
+impl<'a> GetMessageRequest<'a> {
+    #[must_use]
+    pub fn new(id: &'a Id) -> Self {
+        Self { id }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct GetMessageResponse {
+    pub message: Message,
+}
+
+impl http::Request for GetMessageRequest<'_> {
+    type Response = http::JsonResponse<GetMessageResponse>;
+    const METHOD: Method = Method::Get;
+
+    fn url(&self) -> String {
+        format!("mail/v4/messages/{}", self.id)
+    }
+}
+
+/// List the metadata of the messages matching a [`MessageFilter`], one page at a time.
+///
+/// Pages start at 0.
+pub struct GetMessagesRequest {
+    filter: MessageFilter,
+    page: u32,
+    page_size: u32,
+}
+
+impl GetMessagesRequest {
+    #[must_use]
+    pub fn new(filter: MessageFilter, page: u32, page_size: u32) -> Self {
+        Self {
+            filter,
+            page,
+            page_size,
+        }
+    }
+
+    /// Query parameters of this request.
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        self.filter.query(self.page, self.page_size)
+    }
+}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct GetMessagesResponse {
+    /// Total number of messages matching the filter, across all pages.
+    pub total: u64,
+    pub messages: Vec<Message>,
+}
+
+impl http::Request for GetMessagesRequest {
+    type Response = http::JsonResponse<GetMessagesResponse>;
+    const METHOD: Method = Method::Get;
+
+    fn url(&self) -> String {
+        "mail/v4/messages".to_owned()
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        Ok(self
+            .query()
+            .into_iter()
+            .fold(builder, |builder, (key, value)| builder.query(key, value)))
+    }
+}
+
+/// Response items returned for message and conversation operations.
 #[derive(Debug, Deserialize)]
 #[cfg_attr(feature = "mocks", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
-pub struct OperationResponse {
+pub struct OperationResponse<T = Id> {
     #[serde(rename = "ID")]
-    pub id: Id,
+    pub id: T,
     pub response: APIErrorDesc,
 }
 
-impl OperationResponse {
+impl<T> OperationResponse<T> {
     /// Create new operation success response
     #[must_use]
-    pub fn ok(id: Id) -> Self {
+    pub fn ok(id: T) -> Self {
         Self {
             id,
             response: APIErrorDesc {
@@ -48,6 +185,27 @@ impl OperationResponse {
     }
 }
 
+/// Get the number of messages for every label.
+#[derive(Copy, Clone)]
+pub struct GetMessageCountsRequest {}
+
+#[doc(hidden)]
+#[derive(Deserialize)]
+#[cfg_attr(feature = "mocks", derive(Serialize))]
+#[serde(rename_all = "PascalCase")]
+pub struct GetMessageCountsResponse {
+    pub counts: Vec<MessageCount>,
+}
+
+impl http::Request for GetMessageCountsRequest {
+    type Response = http::JsonResponse<GetMessageCountsResponse>;
+    const METHOD: Method = Method::Get;
+
+    fn url(&self) -> String {
+        "mail/v4/messages/count".to_owned()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[cfg_attr(feature = "mocks", derive(serde::Serialize))]
 #[serde(rename_all = "PascalCase")]
@@ -1,6 +1,7 @@
 //! Representation of all the JSON data types that need to be submitted.
 
 mod auth;
+mod conversation;
 mod event;
 mod labels;
 mod message;
@@ -8,6 +9,7 @@ mod tests;
 mod user;
 
 pub use auth::*;
+pub use conversation::*;
 pub use event::*;
 pub use labels::*;
 pub use message::*;
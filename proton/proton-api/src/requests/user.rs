@@ -1,4 +1,4 @@
-use crate::domain::user::User;
+use crate::domain::user::{KeySalt, User};
 use http::Method;
 use serde::Deserialize;
 
@@ -19,3 +19,21 @@ impl http::Request for GetUserInfoRequest {
         "core/v4/users".to_owned()
     }
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GetKeySaltsResponse {
+    pub key_salts: Vec<KeySalt>,
+}
+
+#[derive(Copy, Clone)]
+pub struct GetKeySaltsRequest {}
+
+impl http::Request for GetKeySaltsRequest {
+    type Response = http::JsonResponse<GetKeySaltsResponse>;
+    const METHOD: Method = Method::Get;
+
+    fn url(&self) -> String {
+        "core/v4/keys/salts".to_owned()
+    }
+}
@@ -0,0 +1,162 @@
+//! Alternative routing for when the Proton API can not be reached directly.
+//!
+//! Like the official clients, alternative API hosts are discovered by querying a TXT record
+//! through DNS-over-HTTPS resolvers, which are much harder to block than the API host itself.
+
+use http::url::Url;
+use http::{Client, JsonResponse, Method, Proxy, Request, RequestBuilder};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{debug, error};
+
+/// DNS-over-HTTPS resolvers queried by default.
+pub const DEFAULT_DOH_RESOLVERS: &[&str] = &[
+    "https://dns.google/resolve",
+    "https://cloudflare-dns.com/dns-query",
+];
+
+/// TXT record which lists the alternative hosts of the Proton API.
+pub const DEFAULT_QUERY_NAME: &str = "dMFYGSLTQOJXXI33ONVQWS3BOMNUA.protonpro.xyz";
+
+const DNS_TYPE_TXT: u16 = 16;
+const DNS_STATUS_NO_ERROR: u32 = 0;
+
+/// Resolves alternative base urls for the Proton API.
+#[derive(Debug, Clone)]
+pub struct AlternativeRouting {
+    resolvers: Vec<Url>,
+    query_name: String,
+}
+
+impl Default for AlternativeRouting {
+    fn default() -> Self {
+        Self {
+            // These urls are static and should never fail to parse.
+            resolvers: DEFAULT_DOH_RESOLVERS
+                .iter()
+                .map(|url| Url::parse(url).unwrap())
+                .collect(),
+            query_name: DEFAULT_QUERY_NAME.to_owned(),
+        }
+    }
+}
+
+impl AlternativeRouting {
+    /// Create a new instance which queries the TXT record `query_name` with the JSON
+    /// DNS-over-HTTPS `resolvers`.
+    #[must_use]
+    pub fn new(resolvers: Vec<Url>, query_name: impl Into<String>) -> Self {
+        Self {
+            resolvers,
+            query_name: query_name.into(),
+        }
+    }
+
+    /// Query the resolvers in order and return the alternative base urls of the first
+    /// resolver that provided any.
+    ///
+    /// The resolvers are contacted through `proxy` if set.
+    #[must_use]
+    pub fn resolve(&self, proxy: Option<&Proxy>) -> Vec<Url> {
+        for resolver in &self.resolvers {
+            match self.query(resolver, proxy) {
+                Ok(urls) if !urls.is_empty() => {
+                    debug!("Found {} alternative routes via {resolver}", urls.len());
+                    return urls;
+                }
+                Ok(_) => debug!("No alternative routes found via {resolver}"),
+                Err(e) => error!("Failed to query alternative routes via {resolver}: {e}"),
+            }
+        }
+
+        Vec::new()
+    }
+
+    fn query(&self, resolver: &Url, proxy: Option<&Proxy>) -> http::Result<Vec<Url>> {
+        let mut builder = Client::builder(resolver.clone())
+            .connect_timeout(Duration::from_secs(10))
+            .request_timeout(Duration::from_secs(30));
+        if resolver.scheme() == "http" {
+            builder = builder.allow_http();
+        }
+        if let Some(proxy) = proxy {
+            builder = builder.with_proxy(proxy.clone());
+        }
+
+        let response = builder.build()?.execute(&DnsTxtQuery {
+            name: &self.query_name,
+        })?;
+
+        if response.status != DNS_STATUS_NO_ERROR {
+            return Err(http::Error::Unexpected(anyhow::anyhow!(
+                "DNS query failed with status {}",
+                response.status
+            )));
+        }
+
+        Ok(response
+            .answer
+            .into_iter()
+            .filter(|answer| answer.record_type == DNS_TYPE_TXT)
+            .filter_map(|answer| txt_to_url(&answer.data))
+            .collect())
+    }
+}
+
+/// Convert the TXT record data into a base url. Records contain either a host name or a
+/// complete url.
+fn txt_to_url(data: &str) -> Option<Url> {
+    let data = data.trim().trim_matches('"');
+    if data.is_empty() {
+        return None;
+    }
+
+    let url = if data.contains("://") {
+        Url::parse(data)
+    } else {
+        Url::parse(&format!("https://{data}/"))
+    };
+
+    url.inspect_err(|e| error!("Invalid alternative route '{data}': {e}"))
+        .ok()
+}
+
+/// JSON DNS-over-HTTPS query for a TXT record.
+struct DnsTxtQuery<'a> {
+    name: &'a str,
+}
+
+impl Request for DnsTxtQuery<'_> {
+    type Response = JsonResponse<DnsResponse>;
+    const METHOD: Method = Method::Get;
+
+    fn url(&self) -> String {
+        String::new()
+    }
+
+    fn max_body_size(&self) -> u64 {
+        64 * 1024
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        Ok(builder
+            .header("Accept", "application/dns-json")
+            .query("name", self.name)
+            .query("type", "TXT"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DnsResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DnsAnswer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DnsAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
@@ -1,25 +1,74 @@
-use crate::auth::{Auth, InMemoryStore, ThreadSafeStore, new_thread_safe_store};
+use crate::auth::{
+    Auth, AuthUpdate, InMemoryStore, ThreadSafeStore, Uid, expiry_timestamp, new_thread_safe_store,
+};
+use crate::domain::SecretString;
+use crate::domain::errors::{APIError, ErrorKind};
+use crate::domain::session::AuthSession;
 use crate::domain::user::User;
-use crate::requests::{GetUserInfoRequest, LogoutRequest, PostAuthRefreshRequest};
+use crate::requests::{
+    DeleteAuthSessionRequest, GetAuthSessionsRequest, GetUserInfoRequest, LogoutRequest,
+    PostAuthRefreshRequest,
+};
+use crate::routing::AlternativeRouting;
 use anyhow::anyhow;
+use http::url::Url;
 use http::{Client, FromResponse, Method, Request, RequestBuilder};
+use parking_lot::RwLock;
 use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tracing::{error, warn};
+use std::time::Duration;
+use tracing::{debug, error, warn};
 
 /// Authenticated Session from which one can access data/functionality restricted to authenticated
 /// users.
 #[derive(Clone)]
 pub struct Session {
     auth_store: ThreadSafeStore,
-    client: Arc<Client>,
+    // The client is replaced when switching to an alternative route.
+    client: Arc<RwLock<Arc<Client>>>,
+    primary_base_url: Url,
+    routing: Option<Arc<AlternativeRouting>>,
+    identity: Arc<AppIdentity>,
 }
 
-struct ProtonRequest<T: Request> {
+/// Identifies the application to the proton servers.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AppIdentity {
+    /// App version submitted with every request, e.g.: `linux-yhm@1.0.0`.
+    pub app_version: String,
+    /// User agent submitted with every request.
+    pub user_agent: String,
+}
+
+impl AppIdentity {
+    /// Create a new instance with `app_version` and `user_agent`.
+    pub fn new(app_version: impl Into<String>, user_agent: impl Into<String>) -> Self {
+        Self {
+            app_version: app_version.into(),
+            user_agent: user_agent.into(),
+        }
+    }
+
+    fn apply(&self, builder: RequestBuilder) -> RequestBuilder {
+        builder
+            .header(X_PM_APP_VERSION_HEADER, &self.app_version)
+            .header(USER_AGENT_HEADER, &self.user_agent)
+    }
+}
+
+impl Default for AppIdentity {
+    fn default() -> Self {
+        Self::new(DEFAULT_APP_VERSION, DEFAULT_USER_AGENT)
+    }
+}
+
+struct ProtonRequest<'s, T: Request> {
+    session: &'s Session,
     request: T,
 }
 
-impl<T: Request> Request for ProtonRequest<T> {
+impl<T: Request> Request for ProtonRequest<'_, T> {
     type Response = T::Response;
     const METHOD: Method = T::METHOD;
 
@@ -27,9 +76,12 @@ impl<T: Request> Request for ProtonRequest<T> {
         self.request.url()
     }
 
-    fn build(&self, mut builder: RequestBuilder) -> http::Result<RequestBuilder> {
-        builder = builder.header(X_PM_APP_VERSION_HEADER, DEFAULT_APP_VERSION);
-        self.request.build(builder)
+    fn max_body_size(&self) -> u64 {
+        self.request.max_body_size()
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        self.request.build(self.session.identity.apply(builder))
     }
 }
 
@@ -46,6 +98,10 @@ impl<T: Request> Request for ProtonAuthRequest<'_, T> {
         self.request.url()
     }
 
+    fn max_body_size(&self) -> u64 {
+        self.request.max_body_size()
+    }
+
     fn build(&self, mut builder: RequestBuilder) -> http::Result<RequestBuilder> {
         if let Some(auth) = self.session.auth_store.read().get().map_err(|e| {
             http::Error::Unexpected(anyhow::anyhow!("Failed to read authentication data: {e}"))
@@ -55,30 +111,72 @@ impl<T: Request> Request for ProtonAuthRequest<'_, T> {
         } else {
             warn!("Authenticated requested without authentication data");
         }
-        builder = builder.header(X_PM_APP_VERSION_HEADER, DEFAULT_APP_VERSION);
-        self.request.build(builder)
+        self.request.build(self.session.identity.apply(builder))
     }
 }
 
 impl Session {
     /// Create a new instance with a given `client` and `auth_store`.
     pub fn new(client: Arc<Client>, auth_store: ThreadSafeStore) -> Self {
-        Self { auth_store, client }
+        Self {
+            auth_store,
+            primary_base_url: client.base_url().clone(),
+            client: Arc::new(RwLock::new(client)),
+            routing: None,
+            identity: Arc::new(AppIdentity::default()),
+        }
     }
 
     /// Create a new instance with a given `client` and an [`crate::auth::InMemoryStore`].
     #[must_use]
     pub fn with_in_memory_auth_store(client: Arc<Client>) -> Self {
-        Self {
-            auth_store: new_thread_safe_store(InMemoryStore::default()),
-            client,
-        }
+        Self::new(client, new_thread_safe_store(InMemoryStore::default()))
+    }
+
+    /// Enable alternative routing.
+    ///
+    /// When a request fails with a connection error, alternative hosts are resolved with
+    /// `routing` and the request is retried on each of them. The first host that can be
+    /// reached is used for all subsequent requests.
+    #[must_use]
+    pub fn with_alternative_routing(mut self, routing: AlternativeRouting) -> Self {
+        self.routing = Some(Arc::new(routing));
+        self
+    }
+
+    /// Identify the application with `identity` in all requests.
+    #[must_use]
+    pub fn with_identity(mut self, identity: AppIdentity) -> Self {
+        self.identity = Arc::new(identity);
+        self
+    }
+
+    /// Get the identity submitted with every request.
+    #[must_use]
+    pub fn identity(&self) -> &AppIdentity {
+        &self.identity
     }
 
     /// Get http client.
     #[must_use]
-    pub fn client(&self) -> &Arc<Client> {
-        &self.client
+    pub fn client(&self) -> Arc<Client> {
+        Arc::clone(&self.client.read())
+    }
+
+    /// Execute all further requests against `base_url`, e.g.: an alternative route
+    /// discovered by a previous session.
+    pub fn use_base_url(&self, base_url: Url) {
+        let mut guard = self.client.write();
+        if guard.base_url() != &base_url {
+            *guard = guard.with_base_url(base_url);
+        }
+    }
+
+    /// Get the base url of the alternative route in use, if any.
+    #[must_use]
+    pub fn alternative_base_url(&self) -> Option<Url> {
+        let guard = self.client.read();
+        (guard.base_url() != &self.primary_base_url).then(|| guard.base_url().clone())
     }
 
     /// Get the sessions authentication store.
@@ -106,6 +204,34 @@ impl Session {
         })
     }
 
+    /// List the active sessions of the user, including this one.
+    ///
+    /// # Errors
+    /// Returns error if the request failed.
+    pub fn sessions(&self) -> http::Result<Vec<AuthSession>> {
+        Ok(self.execute_with_auth(GetAuthSessionsRequest {})?.sessions)
+    }
+
+    /// Revoke the session with `uid`.
+    ///
+    /// Revoking the session in use by this instance is equivalent to [`Self::logout`].
+    ///
+    /// # Errors
+    /// Returns error if the request failed.
+    pub fn revoke_session(&self, uid: &Uid) -> http::Result<()> {
+        if self.session_uid().as_ref() == Some(uid) {
+            return self.logout();
+        }
+        self.execute_with_auth(DeleteAuthSessionRequest::new(uid))
+    }
+
+    /// Id of the session in use by this instance, if authenticated.
+    #[must_use]
+    pub fn session_uid(&self) -> Option<Uid> {
+        let guard = self.auth_store.read();
+        guard.get().ok().flatten().map(|auth| auth.uid.clone())
+    }
+
     /// Execute a non-authenticate request with this client.
     ///
     /// # Errors
@@ -114,18 +240,22 @@ impl Session {
         &self,
         request: T,
     ) -> http::Result<<T::Response as FromResponse>::Output> {
-        let request = ProtonRequest { request };
+        let request = ProtonRequest {
+            session: self,
+            request,
+        };
 
-        match self.client.execute(&request) {
+        match self.execute_with_routing(&request) {
             Ok(v) => Ok(v),
-            Err(e) => self.handle_error(&request, e),
+            Err(e) => self.handle_error(&request, e, None),
         }
     }
 
     /// Execute an authenticate request with this client.
     ///
     /// Note that the session token is automatically refreshed and the request is retried
-    /// on successful refresh.
+    /// on successful refresh. Tokens which are about to expire are refreshed first, if that
+    /// fails the current token is used until it has expired.
     ///
     /// # Errors
     /// Returns error if the request  or accessing/updating the session token failed.
@@ -138,64 +268,269 @@ impl Session {
             request,
         };
 
-        match self.client.execute(&request) {
+        if self.auth_expires_soon() {
+            debug!("Authentication token is about to expire, refreshing");
+            let error = match self.refresh_auth(self.auth_token().as_ref()) {
+                RefreshOutcome::Missing | RefreshOutcome::Refreshed => None,
+                RefreshOutcome::Failed(e) => Some(*e),
+                RefreshOutcome::StoreFailed(e) => Some(http::Error::Unexpected(e)),
+            };
+            // The current token can still be used until it has actually expired.
+            if let Some(e) = error {
+                if !self.auth_valid() {
+                    return Err(e);
+                }
+                warn!("Failed to refresh token before expiry, using current token: {e}");
+            }
+        }
+
+        let auth_token = self.auth_token();
+        match self.execute_with_routing(&request) {
             Ok(v) => Ok(v),
-            Err(e) => self.handle_error(&request, e),
+            Err(e) => self.handle_error(&request, e, auth_token.as_ref()),
+        }
+    }
+
+    /// Refresh the authentication tokens, e.g. to warm up a session which has been idle.
+    ///
+    /// Tokens are also refreshed automatically when they are about to expire or have been
+    /// rejected by the server.
+    ///
+    /// # Errors
+    /// Returns error if there is no authentication data or the refresh failed. If the server
+    /// rejects the refresh, the authentication data is deleted on the next rejection, see
+    /// [`Auth::refresh_rejected`].
+    pub fn refresh(&self) -> http::Result<()> {
+        match self.refresh_auth(None) {
+            RefreshOutcome::Refreshed => Ok(()),
+            RefreshOutcome::Missing => Err(http::Error::Unexpected(anyhow!(
+                "Session has no authentication data"
+            ))),
+            RefreshOutcome::Failed(e) => Err(*e),
+            RefreshOutcome::StoreFailed(e) => Err(http::Error::Unexpected(e)),
         }
     }
 
     /// Check if this was a session expired error and attempt to auto refresh.
+    ///
+    /// If the server rejects the refresh, the authentication data is marked as rejected or
+    /// deleted and the refresh error is returned. The data is kept if the refresh failed for
+    /// any other reason.
     fn handle_error<T: Request>(
         &self,
         request: &T,
         error: http::Error,
+        auth_token: Option<&SecretString>,
     ) -> http::Result<<T::Response as FromResponse>::Output> {
         let http::Error::Http(401, _) = &error else {
             return Err(error);
         };
 
-        // Attempt refresh
+        match self.refresh_auth(auth_token) {
+            RefreshOutcome::Missing => {
+                error!("Failed to get authentication data");
+                Err(error)
+            }
+            RefreshOutcome::StoreFailed(_) => Err(error),
+            RefreshOutcome::Failed(e) => Err(*e),
+            // Execute the request again.
+            RefreshOutcome::Refreshed => self.execute_with_routing(request),
+        }
+    }
+
+    /// Refresh the authentication data.
+    ///
+    /// If the authentication data no longer has the `auth_token` of the caller, it was already
+    /// refreshed by another session and is not refreshed again.
+    ///
+    /// The store is not locked while waiting for the server, the result is only applied if no
+    /// other process refreshed the data in the meantime, see [`Store::update_if_unchanged`].
+    /// The stored data is never removed because the store failed. A rejected refresh token is
+    /// only deleted if it was already rejected before: the first rejection may be caused by
+    /// another process which rotated the token, but has not stored the new one yet.
+    ///
+    /// [`Store::update_if_unchanged`]: crate::auth::Store::update_if_unchanged
+    fn refresh_auth(&self, auth_token: Option<&SecretString>) -> RefreshOutcome {
+        // Refreshes within this process are serialized by the guard.
         let mut guard = self.auth_store.write();
 
-        let Ok(Some(auth)) = guard.get() else {
-            error!("Failed to get authentication data");
-            return Err(error);
+        let auth = match guard.reload() {
+            Ok(Some(auth)) => auth.clone(),
+            Ok(None) => return RefreshOutcome::Missing,
+            Err(e) => {
+                error!("Failed to load token from auth store: {e}");
+                return RefreshOutcome::StoreFailed(anyhow!("Failed to load token: {e}"));
+            }
         };
 
-        let response = match self.execute(PostAuthRefreshRequest::new(
+        if auth_token.is_some_and(|t| t.expose_secret() != auth.auth_token.0.expose_secret()) {
+            debug!("Authentication data was already refreshed");
+            return RefreshOutcome::Refreshed;
+        }
+
+        let (update, outcome) = match self.execute(PostAuthRefreshRequest::new(
             &auth.uid,
             auth.refresh_token.0.expose_secret(),
         )) {
-            Ok(response) => response,
+            Ok(response) => (
+                AuthUpdate::Store(Auth {
+                    uid: response.uid,
+                    auth_token: response.access_token,
+                    refresh_token: response.refresh_token,
+                    expires_at: response.expires_in.map(expiry_timestamp),
+                    refresh_rejected: false,
+                }),
+                RefreshOutcome::Refreshed,
+            ),
             Err(e) => {
-                if let Err(e) = guard.delete() {
-                    error!("Failed to delete auth data after failed refresh: {e}");
-                }
                 error!("Failed to refresh auth token: {e}");
-                return Err(error);
+                let (rejected, e) = refresh_token_rejected(e);
+                let update = match (rejected, auth.refresh_rejected) {
+                    (true, true) => AuthUpdate::Delete,
+                    (true, false) => AuthUpdate::Reject,
+                    (false, _) => AuthUpdate::Keep,
+                };
+                (update, RefreshOutcome::Failed(Box::new(e)))
             }
         };
 
-        if let Err(e) = guard.store(Auth {
-            uid: response.uid,
-            auth_token: response.access_token,
-            refresh_token: response.refresh_token,
-        }) {
-            error!("Failed to update token in auth store: {e}");
-            if let Err(e) = guard.delete() {
-                error!("Failed to remove token from auth store after update failure: {e}");
+        match guard.update_if_unchanged(&auth, update) {
+            Ok(true) => outcome,
+            Ok(false) => {
+                debug!("Authentication data was refreshed by another session");
+                match guard.get() {
+                    Ok(Some(_)) => RefreshOutcome::Refreshed,
+                    Ok(None) => RefreshOutcome::Missing,
+                    Err(e) => RefreshOutcome::StoreFailed(anyhow!("Failed to load token: {e}")),
+                }
             }
+            Err(e) => {
+                error!("Failed to update token in auth store: {e}");
+                if matches!(outcome, RefreshOutcome::Failed(_)) {
+                    outcome
+                } else {
+                    RefreshOutcome::StoreFailed(anyhow!("Failed to update token: {e}"))
+                }
+            }
+        }
+    }
+
+    /// Whether the authentication token is about to expire.
+    fn auth_expires_soon(&self) -> bool {
+        let guard = self.auth_store.read();
+        matches!(guard.get(), Ok(Some(auth)) if auth.expires_within(REFRESH_MARGIN))
+    }
+
+    /// Whether there is an authentication token which has not expired yet.
+    fn auth_valid(&self) -> bool {
+        let guard = self.auth_store.read();
+        matches!(guard.get(), Ok(Some(auth)) if !auth.expires_within(Duration::ZERO))
+    }
+
+    /// Get the current authentication token, if any.
+    fn auth_token(&self) -> Option<SecretString> {
+        let guard = self.auth_store.read();
+        guard
+            .get()
+            .ok()
+            .flatten()
+            .map(|auth| auth.auth_token.0.clone())
+    }
+
+    /// Execute the request and retry on alternative routes if the current host can not
+    /// be reached.
+    fn execute_with_routing<T: Request>(
+        &self,
+        request: &T,
+    ) -> http::Result<<T::Response as FromResponse>::Output> {
+        let client = self.client();
+        let error = match client.execute(request) {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+
+        let Some(routing) = &self.routing else {
+            return Err(error);
+        };
+
+        if !error.is_connection_error() {
             return Err(error);
         }
 
-        drop(guard);
+        warn!(
+            "Failed to reach {}, trying alternative routes: {error}",
+            client.base_url()
+        );
+
+        // Always try to go back to the primary host first.
+        let mut candidates = Vec::new();
+        if client.base_url() != &self.primary_base_url {
+            candidates.push(self.primary_base_url.clone());
+        }
+        candidates.extend(routing.resolve(client.proxy()));
 
-        // Execute the request again.
-        self.client.execute(request)
+        for base_url in candidates {
+            if &base_url == client.base_url() {
+                continue;
+            }
+
+            let alternative = client.with_base_url(base_url);
+            match alternative.execute(request) {
+                Err(e) if e.is_connection_error() || matches!(e, http::Error::TlsPinning(_)) => {
+                    debug!("Failed to reach {}: {e}", alternative.base_url());
+                }
+                result => {
+                    debug!("Switching to {}", alternative.base_url());
+                    *self.client.write() = alternative;
+                    return result;
+                }
+            }
+        }
+
+        error!("No alternative route available");
+        Err(error)
     }
 }
 
-pub(crate) const DEFAULT_APP_VERSION: &str = "Other";
+/// App version used when none is configured.
+pub const DEFAULT_APP_VERSION: &str = "Other";
+/// User agent used when none is configured.
+pub const DEFAULT_USER_AGENT: &str = "NoClient/0.1.0";
+/// Result of a refresh attempt in [`Session::refresh_auth`].
+enum RefreshOutcome {
+    Missing,
+    Refreshed,
+    Failed(Box<http::Error>),
+    StoreFailed(anyhow::Error),
+}
+
+/// Check whether the server rejected the refresh token of a failed refresh, i.e. the session
+/// has ended and the authentication data should be deleted.
+///
+/// Rate limits, server errors and other failures are temporary, so the data is kept. Reading
+/// the API error consumes the response, so client errors are returned as
+/// [`http::Error::Status`] with the response body.
+fn refresh_token_rejected(error: http::Error) -> (bool, http::Error) {
+    let http::Error::Http(status, response) = error else {
+        return (false, error);
+    };
+    if !(400..500).contains(&status) {
+        return (false, http::Error::Http(status, response));
+    }
+
+    let body = match response.into_string() {
+        Ok(body) => body,
+        Err(e) => return (false, http::Error::IO(e)),
+    };
+    let rejected =
+        APIError::with_status_and_body(status, &body).kind() == ErrorKind::InvalidRefreshToken;
+    (rejected, http::Error::Status(status, body))
+}
+
+/// Tokens which expire within this duration are refreshed before executing a request.
+const REFRESH_MARGIN: Duration = Duration::from_mins(5);
+
 pub(crate) const DEFAULT_HOST_URL: &str = "https://mail.proton.me/api/";
 pub(crate) const X_PM_APP_VERSION_HEADER: &str = "X-Pm-Appversion";
 pub(crate) const X_PM_UID_HEADER: &str = "X-Pm-Uid";
+const USER_AGENT_HEADER: &str = "User-Agent";
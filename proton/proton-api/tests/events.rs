@@ -1,6 +1,6 @@
 use crate::utils::{new_mock_session_and_server, perform_login};
-use proton_api::domain::event;
-use proton_api::domain::event::MoreEvents;
+use proton_api::domain::event::{MoreEvents, RefreshFlags};
+use proton_api::domain::{address, event};
 use proton_api::requests::GetEventRequest;
 
 mod utils;
@@ -20,8 +20,13 @@ fn get_events() {
     let event = event::Event {
         event_id: id.clone(),
         more: MoreEvents::Yes,
+        refresh: RefreshFlags::NONE,
         messages: None,
         labels: None,
+        message_counts: None,
+        user: None,
+        addresses: None,
+        used_space: None,
     };
 
     let _get_event_mock = proton_api::mocks::events::get_event(&mut server, &id, &event);
@@ -30,3 +35,47 @@ fn get_events() {
         .unwrap();
     assert_eq!(remote_event, event);
 }
+
+#[test]
+fn get_events_with_refresh_and_account_updates() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (user, session) = perform_login(
+        client,
+        proton_api::mocks::DEFAULT_USER_EMAIL,
+        proton_api::mocks::DEFAULT_USER_PASSWORD,
+        false,
+    );
+
+    let id = event::Id("foo".to_owned());
+    let address_id = address::Id("address".to_owned());
+    let event = event::Event {
+        event_id: id.clone(),
+        more: MoreEvents::No,
+        refresh: RefreshFlags::MAIL,
+        messages: None,
+        labels: None,
+        message_counts: None,
+        user: Some(user),
+        addresses: Some(vec![event::Address {
+            id: address_id.clone(),
+            action: event::Action::Create,
+            address: Some(address::Address {
+                id: address_id,
+                email: proton_api::mocks::DEFAULT_USER_EMAIL.to_owned(),
+                display_name: "Foo".to_owned(),
+                status: address::Status::Enabled,
+                order: 1,
+            }),
+        }]),
+        used_space: Some(1024),
+    };
+
+    let _get_event_mock = proton_api::mocks::events::get_event(&mut server, &id, &event);
+    let remote_event = session
+        .execute_with_auth(GetEventRequest::new(&id))
+        .unwrap();
+    assert_eq!(remote_event, event);
+    assert!(remote_event.refresh.is_mail());
+    assert!(!remote_event.refresh.contains(RefreshFlags::CONTACTS));
+}
@@ -1,12 +1,24 @@
 mod utils;
 
-use crate::utils::{new_mock_session_and_server, perform_login};
+use crate::utils::{new_mock_session_and_server, new_session, perform_login};
 use mockito::{Mock, Server};
+use proton_api::auth::{
+    Auth, InMemoryStore, RefreshToken, Store, StoreError, Token, Uid, expiry_timestamp,
+    new_thread_safe_store,
+};
+use proton_api::domain::SecretString;
+use proton_api::domain::errors::ErrorKind;
 use proton_api::domain::event;
+use proton_api::domain::human_verification::{
+    LoginData, VerificationDestination, VerificationType,
+};
+use proton_api::login::{self, Sequence};
 use proton_api::mocks::auth::MatchExtension;
 use proton_api::mocks::{DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD};
-use proton_api::requests::{GetLatestEventRequest, GetLatestEventResponse};
+use proton_api::requests::{GetLatestEventRequest, GetLatestEventResponse, Ping};
+use proton_api::session::{AppIdentity, Session};
 use secrecy::ExposeSecret;
+use std::time::Duration;
 
 #[test]
 fn session_login() {
@@ -27,6 +39,194 @@ fn session_login_tfa() {
     assert_eq!(user.id.as_ref(), proton_api::mocks::user_id());
 }
 
+#[test]
+fn session_login_tfa_rejected() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = [
+        proton_api::mocks::auth::auth_info(&mut server),
+        proton_api::mocks::auth::auth_response(&mut server, true),
+        proton_api::mocks::auth::auth_tfa_rejected(&mut server),
+    ];
+    let mut sequence = Sequence::without_server_proof_check(new_session(client));
+    sequence
+        .login(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, None)
+        .unwrap();
+
+    let err = sequence
+        .submit_totp(proton_api::mocks::auth::TFA_CODE)
+        .expect_err("code should be rejected");
+    assert!(matches!(err, login::Error::TwoFactorFailed(_)));
+    assert_eq!(err.kind(), Some(ErrorKind::WrongPassword));
+    assert!(sequence.is_awaiting_totp());
+}
+
+#[test]
+fn session_login_fido2() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow_fido2(&mut server, false);
+    let mut sequence = Sequence::without_server_proof_check(new_session(client));
+    sequence
+        .login(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, None)
+        .unwrap();
+
+    assert!(sequence.is_awaiting_fido2());
+    assert!(!sequence.is_awaiting_totp());
+    assert_eq!(
+        sequence.fido2_authentication_options(),
+        Some(&proton_api::mocks::auth::fido2_authentication_options())
+    );
+    assert!(matches!(
+        sequence.submit_totp(proton_api::mocks::auth::TFA_CODE),
+        Err(login::Error::InvalidState)
+    ));
+
+    sequence
+        .submit_fido2(&proton_api::mocks::auth::fido2_assertion())
+        .unwrap();
+    assert!(sequence.fido2_authentication_options().is_none());
+    let (user, _) = sequence.finish().unwrap();
+    assert_eq!(user.id.as_ref(), proton_api::mocks::user_id());
+}
+
+#[test]
+fn session_login_fido2_or_totp() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = [
+        proton_api::mocks::auth::auth_info(&mut server),
+        proton_api::mocks::auth::auth_response_fido2(&mut server, true),
+        proton_api::mocks::auth::auth_tfa(&mut server),
+        proton_api::mocks::auth::user_info(&mut server),
+    ];
+    let mut sequence = Sequence::without_server_proof_check(new_session(client));
+    sequence
+        .login(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, None)
+        .unwrap();
+
+    assert!(sequence.is_awaiting_fido2());
+    assert!(sequence.is_awaiting_totp());
+
+    sequence
+        .submit_totp(proton_api::mocks::auth::TFA_CODE)
+        .unwrap();
+    let (user, _) = sequence.finish().unwrap();
+    assert_eq!(user.id.as_ref(), proton_api::mocks::user_id());
+}
+
+#[test]
+fn session_login_email_human_verification() {
+    let (client, mut server) = new_mock_session_and_server();
+    let destination = VerificationDestination::Email("foo@bar.com".to_owned());
+    let login_data = LoginData::with_code(&destination, "123456");
+    assert_eq!(login_data.token, "foo@bar.com:123456");
+
+    let _mocks = [
+        proton_api::mocks::auth::auth_info(&mut server).expect(2),
+        proton_api::mocks::auth::auth_response_human_verification(&mut server, &["email"]),
+        proton_api::mocks::auth::verification_code(&mut server, &destination),
+        proton_api::mocks::auth::auth_response_human_verified(&mut server, &login_data),
+        proton_api::mocks::auth::user_info(&mut server),
+    ];
+    let mut sequence = Sequence::without_server_proof_check(new_session(client));
+    assert!(matches!(
+        sequence.request_verification_code(&destination),
+        Err(login::Error::InvalidState)
+    ));
+
+    let Err(login::Error::HumanVerificationRequired(hv)) =
+        sequence.login(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, None)
+    else {
+        panic!("Expected human verification request");
+    };
+    assert_eq!(hv.methods, vec![VerificationType::Email]);
+    assert_eq!(hv.token, proton_api::mocks::auth::HV_TOKEN);
+    assert!(sequence.human_verification().is_some());
+
+    assert!(matches!(
+        sequence.request_verification_code(&VerificationDestination::Sms("+123".to_owned())),
+        Err(login::Error::HumanVerificationTypeNotSupported(
+            VerificationType::Sms
+        ))
+    ));
+    sequence.request_verification_code(&destination).unwrap();
+
+    sequence
+        .login(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, Some(&login_data))
+        .unwrap();
+    assert!(sequence.human_verification().is_none());
+    let (user, _) = sequence.finish().unwrap();
+    assert_eq!(user.id.as_ref(), proton_api::mocks::user_id());
+}
+
+#[test]
+fn session_login_two_password_mode() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = [
+        proton_api::mocks::auth::auth_info(&mut server),
+        proton_api::mocks::auth::auth_response_two_password(&mut server),
+        proton_api::mocks::auth::user_info_two_password(&mut server),
+        proton_api::mocks::auth::key_salts(&mut server).expect(2),
+    ];
+    let mut sequence = Sequence::without_server_proof_check(new_session(client));
+    sequence
+        .login(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, None)
+        .unwrap();
+
+    assert!(sequence.is_awaiting_mailbox_password());
+    assert!(!sequence.is_logged_in());
+    assert!(matches!(sequence.finish(), Err(login::Error::InvalidState)));
+
+    assert!(matches!(
+        sequence.submit_mailbox_password("wrong"),
+        Err(login::Error::InvalidMailboxPassword)
+    ));
+    assert!(sequence.is_awaiting_mailbox_password());
+
+    sequence
+        .submit_mailbox_password(proton_api::mocks::auth::MAILBOX_PASSWORD)
+        .unwrap();
+    let (user, _) = sequence.finish().unwrap();
+    assert_eq!(user.id.as_ref(), proton_api::mocks::user_id());
+}
+
+#[test]
+fn session_login_resume_from_snapshot() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, true);
+    let mut sequence = Sequence::without_server_proof_check(new_session(client.clone()));
+    sequence
+        .login(DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, None)
+        .unwrap();
+    assert!(sequence.is_awaiting_totp());
+
+    let snapshot = serde_json::to_vec(&sequence.snapshot().unwrap()).unwrap();
+    drop(sequence);
+
+    let snapshot = serde_json::from_slice(&snapshot).unwrap();
+    let mut sequence = Sequence::resume(new_session(client), snapshot).unwrap();
+    assert!(sequence.is_awaiting_totp());
+    sequence
+        .submit_totp(proton_api::mocks::auth::TFA_CODE)
+        .unwrap();
+    let (user, _) = sequence.finish().unwrap();
+    assert_eq!(user.id.as_ref(), proton_api::mocks::user_id());
+}
+
+#[test]
+fn session_identity() {
+    let (client, mut server) = new_mock_session_and_server();
+    let identity = AppIdentity::new("linux-test@1.0.0", "Test/1.0.0");
+    let _mock = server
+        .mock("GET", "/tests/ping")
+        .match_header("X-Pm-Appversion", identity.app_version.as_str())
+        .match_header("User-Agent", identity.user_agent.as_str())
+        .with_status(200)
+        .create();
+
+    let session = new_session(client).with_identity(identity.clone());
+    assert_eq!(session.identity(), &identity);
+    session.execute(Ping).unwrap();
+}
+
 #[test]
 fn session_auto_refresh() {
     let (client, mut server) = new_mock_session_and_server();
@@ -69,6 +269,226 @@ fn session_auto_refresh() {
         proton_api::mocks::auth::POST_REFRESH_ACCESS_TOKEN
     );
 }
+
+#[test]
+fn session_refresh_before_expiry() {
+    let (client, mut server) = new_mock_session_and_server();
+    let auth = Auth {
+        uid: Uid(proton_api::mocks::auth::SESSION_UID.to_owned()),
+        auth_token: Token(SecretString::new(
+            proton_api::mocks::auth::ACCESS_TOKEN.to_owned().into(),
+        )),
+        refresh_token: RefreshToken(SecretString::new(
+            proton_api::mocks::auth::REFRESH_TOKEN.to_owned().into(),
+        )),
+        expires_at: Some(expiry_timestamp(60)),
+        refresh_rejected: false,
+    };
+    let session = Session::new(client, new_thread_safe_store(InMemoryStore::with(auth)));
+
+    let event_id = proton_api::domain::event::Id("foo".to_owned());
+    let _auth_refresh_mock = proton_api::mocks::auth::auth_refresh(&mut server);
+    let _event_success_mock = mock_get_latest_event_id_refreshed(&mut server, event_id.clone());
+
+    let remote_event_id = session
+        .execute_with_auth(GetLatestEventRequest {})
+        .expect("Failed to get event id")
+        .event_id;
+    assert_eq!(remote_event_id, event_id);
+
+    let refreshed_auth = session.auth_store().read().get().unwrap().cloned().unwrap();
+    assert!(!refreshed_auth.expires_within(Duration::from_secs(3600)));
+}
+
+#[test]
+fn session_refresh_before_expiry_failure_uses_current_token() {
+    let (client, mut server) = new_mock_session_and_server();
+    let auth = Auth {
+        uid: Uid(proton_api::mocks::auth::SESSION_UID.to_owned()),
+        auth_token: Token(SecretString::new(
+            proton_api::mocks::auth::ACCESS_TOKEN.to_owned().into(),
+        )),
+        refresh_token: RefreshToken(SecretString::new(
+            proton_api::mocks::auth::REFRESH_TOKEN.to_owned().into(),
+        )),
+        expires_at: Some(expiry_timestamp(60)),
+        refresh_rejected: false,
+    };
+    let session = Session::new(
+        client,
+        new_thread_safe_store(InMemoryStore::with(auth.clone())),
+    );
+
+    let event_id = proton_api::domain::event::Id("foo".to_owned());
+    let _auth_refresh_mock =
+        proton_api::mocks::auth::auth_refresh_failed(&mut server, 503, 0).expect(2);
+    let _event_success_mock = server
+        .mock("GET", "/core/v4/events/latest")
+        .match_auth()
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(
+            serde_json::to_vec(&GetLatestEventResponse {
+                event_id: event_id.clone(),
+            })
+            .unwrap(),
+        )
+        .create();
+
+    let remote_event_id = session
+        .execute_with_auth(GetLatestEventRequest {})
+        .expect("Failed to get event id")
+        .event_id;
+    assert_eq!(remote_event_id, event_id);
+
+    // Once the token has expired, the refresh failure is returned.
+    let expired = Auth {
+        expires_at: Some(1),
+        ..auth
+    };
+    session.auth_store().write().store(expired).unwrap();
+    let result = session.execute_with_auth(GetLatestEventRequest {});
+    assert!(matches!(result, Err(http::Error::Http(503, _))));
+    assert!(session.auth_store().read().get().unwrap().is_some());
+}
+
+#[test]
+fn session_explicit_refresh() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+
+    let _auth_refresh_mock = proton_api::mocks::auth::auth_refresh(&mut server);
+    session.refresh().unwrap();
+
+    let refreshed_auth = session.auth_store().read().get().unwrap().cloned().unwrap();
+    assert_eq!(
+        refreshed_auth.auth_token.0.expose_secret(),
+        proton_api::mocks::auth::POST_REFRESH_ACCESS_TOKEN
+    );
+    assert!(refreshed_auth.expires_at.is_some());
+}
+
+#[test]
+fn session_refresh_rejected_deletes_auth() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+
+    let _event_failed_mock = mock_get_latest_event_id_401(&mut server).expect(2);
+    let _auth_refresh_mock =
+        proton_api::mocks::auth::auth_refresh_rejected(&mut server, 10013).expect(2);
+
+    // Another process may have rotated the token, the data is only marked as rejected.
+    let result = session.execute_with_auth(GetLatestEventRequest {});
+    assert!(matches!(result, Err(http::Error::Status(422, _))));
+    let auth = session.auth_store().read().get().unwrap().cloned().unwrap();
+    assert!(auth.refresh_rejected);
+
+    let result = session.execute_with_auth(GetLatestEventRequest {});
+    assert!(matches!(result, Err(http::Error::Status(422, _))));
+    assert!(session.auth_store().read().get().unwrap().is_none());
+}
+
+#[test]
+fn session_refresh_failure_keeps_auth() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+
+    let _event_failed_mock = mock_get_latest_event_id_401(&mut server).expect(3);
+    // Server errors, rate limits and other rejections do not end the session.
+    for (status, api_code) in [(503, 0), (429, 0), (422, 2001)] {
+        let auth_refresh_mock =
+            proton_api::mocks::auth::auth_refresh_failed(&mut server, status, api_code);
+
+        let result = session.execute_with_auth(GetLatestEventRequest {});
+        assert!(
+            matches!(
+                result,
+                Err(http::Error::Http(code, _) | http::Error::Status(code, _))
+                    if usize::from(code) == status
+            ),
+            "{status}"
+        );
+        assert!(session.auth_store().read().get().unwrap().is_some());
+        auth_refresh_mock.assert();
+    }
+}
+
+#[test]
+fn session_refresh_store_failure_keeps_auth() {
+    /// Store which fails to write the data.
+    struct ReadOnlyStore(InMemoryStore);
+
+    impl Store for ReadOnlyStore {
+        fn get(&self) -> Result<Option<&Auth>, StoreError> {
+            self.0.get()
+        }
+
+        fn store(&mut self, _: Auth) -> Result<(), StoreError> {
+            Err(StoreError::Write(anyhow::anyhow!("read only")))
+        }
+
+        fn delete(&mut self) -> Result<(), StoreError> {
+            Err(StoreError::Write(anyhow::anyhow!("read only")))
+        }
+    }
+
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(
+        client.clone(),
+        DEFAULT_USER_EMAIL,
+        DEFAULT_USER_PASSWORD,
+        false,
+    );
+    let auth = session.auth_store().read().get().unwrap().cloned().unwrap();
+    let session = Session::new(
+        client,
+        new_thread_safe_store(ReadOnlyStore(InMemoryStore::with(auth))),
+    );
+
+    let _event_failed_mock = mock_get_latest_event_id_401(&mut server);
+    let _auth_refresh_mock = proton_api::mocks::auth::auth_refresh(&mut server);
+    let result = session.execute_with_auth(GetLatestEventRequest {});
+    assert!(matches!(result, Err(http::Error::Http(401, _))));
+
+    let auth = session.auth_store().read().get().unwrap().cloned().unwrap();
+    assert_eq!(
+        auth.refresh_token.0.expose_secret(),
+        proton_api::mocks::auth::REFRESH_TOKEN
+    );
+}
+
+#[test]
+fn session_list_and_revoke() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+
+    let _sessions_mock = proton_api::mocks::auth::auth_sessions(&mut server);
+    let sessions = session.sessions().unwrap();
+    assert_eq!(sessions, proton_api::mocks::auth::auth_session_list());
+
+    let other_uid = Uid::from(proton_api::mocks::auth::OTHER_SESSION_UID);
+    let revoke_mock = proton_api::mocks::auth::revoke_auth_session(
+        &mut server,
+        proton_api::mocks::auth::OTHER_SESSION_UID,
+    );
+    session.revoke_session(&other_uid).unwrap();
+    revoke_mock.assert();
+    assert!(session.auth_store().read().get().unwrap().is_some());
+
+    // Revoking our own session logs out.
+    let logout_mock = proton_api::mocks::auth::logout(&mut server);
+    session
+        .revoke_session(&Uid::from(proton_api::mocks::auth::SESSION_UID))
+        .unwrap();
+    logout_mock.assert();
+    assert!(session.auth_store().read().get().unwrap().is_none());
+}
+
 fn mock_get_latest_event_id_401(server: &mut Server) -> Mock {
     server
         .mock("GET", "/core/v4/events/latest")
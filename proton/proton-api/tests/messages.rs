@@ -1,12 +1,15 @@
 mod utils;
 
 use crate::utils::{new_mock_session_and_server, perform_login};
-use proton_api::domain::label;
-use proton_api::domain::message::Id;
+use proton_api::domain::conversation::{self, Contact, Conversation, ConversationLabel};
+use proton_api::domain::message::{Id, Message, MessageCount};
+use proton_api::domain::{Boolean, label};
 use proton_api::mocks::{DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD};
 use proton_api::requests::{
-    OperationResponse, PutLabelMessageRequest, PutLabelMessageResponse, PutMarkMessageReadRequest,
-    PutMarkMessageReadResponse,
+    GetConversationsRequest, GetMessageCountsRequest, GetMessageRequest, GetMessagesRequest,
+    MessageFilter, OperationResponse, PutLabelConversationRequest, PutLabelConversationResponse,
+    PutLabelMessageRequest, PutLabelMessageResponse, PutMarkConversationReadRequest,
+    PutMarkConversationReadResponse, PutMarkMessageReadRequest, PutMarkMessageReadResponse,
 };
 
 #[test]
@@ -54,3 +57,167 @@ fn label_message() {
     assert_eq!(response.responses[0].id, id);
     assert!(response.responses[0].is_success());
 }
+
+#[test]
+fn mark_conversation_read() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+    let id = conversation::Id("my_conversation".to_owned());
+
+    let _mock = proton_api::mocks::message::mark_conversation_read(
+        &mut server,
+        vec![id.clone()],
+        &PutMarkConversationReadResponse {
+            responses: vec![OperationResponse::ok(id.clone())],
+        },
+    );
+    let response = session
+        .execute_with_auth(PutMarkConversationReadRequest::new([id.clone()]))
+        .unwrap();
+    assert_eq!(response.responses.len(), 1);
+    assert_eq!(response.responses[0].id, id);
+    assert!(response.responses[0].is_success());
+}
+
+#[test]
+fn label_conversation() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+    let id = conversation::Id("my_conversation".to_owned());
+    let label_id = label::Id::trash();
+
+    let _mock = proton_api::mocks::message::label_conversation(
+        &mut server,
+        label_id.clone(),
+        vec![id.clone()],
+        &PutLabelConversationResponse {
+            responses: vec![OperationResponse::ok(id.clone())],
+        },
+    );
+    let response = session
+        .execute_with_auth(PutLabelConversationRequest::new(label_id, [id.clone()]))
+        .unwrap();
+    assert_eq!(response.responses.len(), 1);
+    assert_eq!(response.responses[0].id, id);
+    assert!(response.responses[0].is_success());
+}
+
+#[test]
+fn get_message() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+    let message = new_message("my_message");
+
+    let _mock = proton_api::mocks::message::get_message(&mut server, &message);
+    let response = session
+        .execute_with_auth(GetMessageRequest::new(&message.id))
+        .unwrap();
+    assert_eq!(response.message, message);
+}
+
+#[test]
+fn list_messages() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+    let messages = vec![new_message("first"), new_message("second")];
+    let filter = MessageFilter {
+        unread: Some(true),
+        sender: Some("bar@proton.me".to_owned()),
+        begin: Some(1_700_000_000),
+        end: Some(1_800_000_000),
+        ..MessageFilter::with_label(label::Id::inbox())
+    };
+
+    let _mock = proton_api::mocks::message::get_messages(
+        &mut server,
+        &GetMessagesRequest::new(filter.clone(), 1, 2),
+        &messages,
+        4,
+    );
+    let response = session
+        .execute_with_auth(GetMessagesRequest::new(filter, 1, 2))
+        .unwrap();
+    assert_eq!(response.total, 4);
+    assert_eq!(response.messages, messages);
+}
+
+#[test]
+fn list_conversations() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+    let conversations = vec![Conversation {
+        id: conversation::Id("conversation".to_owned()),
+        subject: "Hello".to_owned(),
+        senders: vec![Contact {
+            name: "Bar".to_owned(),
+            address: "bar@proton.me".to_owned(),
+        }],
+        num_messages: 3,
+        num_unread: 1,
+        time: 1_700_000_000,
+        labels: vec![ConversationLabel {
+            id: label::Id::inbox(),
+            context_num_messages: 2,
+            context_num_unread: 1,
+        }],
+    }];
+    let filter = MessageFilter {
+        unread: Some(false),
+        ..MessageFilter::default()
+    };
+
+    let _mock = proton_api::mocks::message::get_conversations(
+        &mut server,
+        &GetConversationsRequest::new(filter.clone(), 0, 50),
+        &conversations,
+        1,
+    );
+    let response = session
+        .execute_with_auth(GetConversationsRequest::new(filter, 0, 50))
+        .unwrap();
+    assert_eq!(response.total, 1);
+    assert_eq!(response.conversations, conversations);
+}
+
+#[test]
+fn get_message_counts() {
+    let (client, mut server) = new_mock_session_and_server();
+    let _mocks = proton_api::mocks::auth::login_flow(&mut server, false);
+    let (_, session) = perform_login(client, DEFAULT_USER_EMAIL, DEFAULT_USER_PASSWORD, false);
+    let counts = vec![
+        MessageCount {
+            label_id: label::Id::inbox(),
+            total: 10,
+            unread: 2,
+        },
+        MessageCount {
+            label_id: label::Id::spam(),
+            total: 3,
+            unread: 3,
+        },
+    ];
+
+    let _mock = proton_api::mocks::message::get_message_counts(&mut server, &counts);
+    let response = session
+        .execute_with_auth(GetMessageCountsRequest {})
+        .unwrap();
+    assert_eq!(response.counts, counts);
+}
+
+fn new_message(id: &str) -> Message {
+    Message {
+        id: Id(id.to_owned()),
+        labels: vec![label::Id::inbox()],
+        subject: "Hello".to_owned(),
+        sender_address: "bar@proton.me".to_owned(),
+        sender_name: Some("Bar".to_owned()),
+        unread: Boolean::True,
+        conversation_id: Some(conversation::Id("conversation".to_owned())),
+        time: 1_700_000_000,
+    }
+}
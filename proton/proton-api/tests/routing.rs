@@ -0,0 +1,87 @@
+use crate::utils::new_session;
+use http::Client;
+use proton_api::mocks::auth::MatchExtension;
+use proton_api::requests::Ping;
+use proton_api::routing::{AlternativeRouting, DEFAULT_QUERY_NAME};
+use url::Url;
+
+// Only the session helper is used here.
+#[allow(dead_code)]
+mod utils;
+
+/// Base url on which nothing is listening.
+const UNREACHABLE_URL: &str = "http://127.0.0.1:1/";
+
+fn unreachable_client() -> std::sync::Arc<Client> {
+    Client::builder(Url::parse(UNREACHABLE_URL).unwrap())
+        .allow_http()
+        .build()
+        .unwrap()
+}
+
+fn routing(resolver: &mockito::Server) -> AlternativeRouting {
+    let resolver = Url::parse(&format!("{}/dns-query", resolver.url())).unwrap();
+    AlternativeRouting::new(vec![resolver], DEFAULT_QUERY_NAME)
+}
+
+#[test]
+fn switch_to_alternative_route_on_connection_error() {
+    let mut api = proton_api::mocks::new_server();
+    let mut resolver = proton_api::mocks::new_server();
+    let api_url = proton_api::mocks::server_url(&api);
+
+    let _resolver_mock =
+        proton_api::mocks::routing::doh_resolver(&mut resolver, &[UNREACHABLE_URL, &api_url]);
+    let _ping_mock = api
+        .mock("GET", "/tests/ping")
+        .match_version()
+        .with_status(200)
+        .expect(2)
+        .create();
+
+    let session = new_session(unreachable_client()).with_alternative_routing(routing(&resolver));
+    assert!(session.alternative_base_url().is_none());
+
+    session.execute(Ping).unwrap();
+    assert_eq!(
+        session.alternative_base_url(),
+        Some(Url::parse(&api_url).unwrap())
+    );
+
+    // Subsequent requests go straight to the alternative route.
+    session.execute(Ping).unwrap();
+}
+
+#[test]
+fn connection_error_without_alternative_routes() {
+    let mut resolver = proton_api::mocks::new_server();
+    let _resolver_mock = proton_api::mocks::routing::doh_resolver(&mut resolver, &[]);
+
+    let session = new_session(unreachable_client()).with_alternative_routing(routing(&resolver));
+    let err = session.execute(Ping).unwrap_err();
+    assert!(err.is_connection_error());
+    assert!(session.alternative_base_url().is_none());
+}
+
+#[test]
+fn no_alternative_routing_by_default() {
+    let session = new_session(unreachable_client());
+    let err = session.execute(Ping).unwrap_err();
+    assert!(err.is_connection_error());
+}
+
+#[test]
+fn restore_alternative_route() {
+    let mut api = proton_api::mocks::new_server();
+    let api_url = Url::parse(&proton_api::mocks::server_url(&api)).unwrap();
+    let _ping_mock = api
+        .mock("GET", "/tests/ping")
+        .match_version()
+        .with_status(200)
+        .create();
+
+    let session = new_session(unreachable_client());
+    session.use_base_url(api_url.clone());
+    assert_eq!(session.alternative_base_url(), Some(api_url));
+    session.execute(Ping).unwrap();
+}
@@ -68,6 +68,22 @@ fn main() {
             let totp = line.trim_end_matches('\n');
             sequence.submit_totp(totp).expect("Failed to submit totp");
         }
+        if sequence.is_awaiting_mailbox_password() {
+            let mut stdout = std::io::stdout();
+            let mut line_reader = std::io::BufReader::new(std::io::stdin());
+            stdout
+                .write_all("Please Input Mailbox Password:".as_bytes())
+                .unwrap();
+            stdout.flush().unwrap();
+            let mut line = String::new();
+            line_reader
+                .read_line(&mut line)
+                .expect("Failed to read line");
+            let mailbox_password = line.trim_end_matches('\n');
+            sequence
+                .submit_mailbox_password(mailbox_password)
+                .expect("Failed to submit mailbox password");
+        }
 
         sequence.into_account(&yhm).expect("Failed to add account");
     }
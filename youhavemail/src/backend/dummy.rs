@@ -50,6 +50,10 @@ impl crate::backend::Backend for Backend {
         Ok(builder.build()?)
     }
 
+    fn check_connection(&self, _: Arc<Client>) -> crate::backend::Result<()> {
+        Ok(())
+    }
+
     fn new_poller(
         &self,
         _: Arc<Client>,
@@ -66,13 +70,11 @@ struct Auth {}
 
 impl crate::backend::Poller for Poller {
     fn check(&mut self) -> crate::backend::Result<Vec<NewEmail>> {
-        Ok(vec![NewEmail {
-            sender: DUMMY_EMAIL.to_owned(),
-            subject: "You Have Mail".to_owned(),
-            mark_as_read_action: None,
-            move_to_trash_action: None,
-            move_to_spam_action: None,
-        }])
+        Ok(vec![NewEmail::new(
+            DUMMY_EMAIL,
+            DUMMY_EMAIL,
+            "You Have Mail",
+        )])
     }
 
     fn apply(&mut self, _: &Action) -> crate::backend::Result<()> {
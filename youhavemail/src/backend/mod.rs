@@ -5,8 +5,11 @@ use crate::state;
 use crate::state::Account;
 use http::{Client, Proxy};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub mod dummy;
 pub mod proton;
@@ -16,8 +19,8 @@ pub mod proton;
 pub enum Error {
     #[error("Http: {0}")]
     Http(#[from] http::Error),
-    #[error("Account session has expired")]
-    SessionExpired,
+    #[error("Account session has expired: {0}")]
+    SessionExpired(LogoutReason),
     #[error("Db: {0}")]
     Db(#[from] state::Error),
     #[error("Unknown Backend: {0}")]
@@ -26,10 +29,102 @@ pub enum Error {
     Unknown(#[source] anyhow::Error),
     #[error("Action is not valid or not recognized")]
     InvalidAction,
+    #[error("Too many requests, try again later")]
+    RateLimited,
+    #[error("App version is no longer supported, please update")]
+    UnsupportedAppVersion,
+    #[error("API: {0}")]
+    Api(#[source] ApiError),
+    #[error("Operation is not supported by the backend")]
+    Unsupported,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Category of an error reported by the server of a backend.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// One of the request values was rejected.
+    InvalidValue,
+    /// The login credentials are not correct.
+    WrongPassword,
+    /// The request needs to be repeated with human verification.
+    HumanVerificationRequired,
+    /// The session could not be refreshed, see [`LogoutReason::SessionRevoked`].
+    RefreshRejected,
+    /// Too many requests were made, the request should be retried later.
+    RateLimited,
+    /// Any other error.
+    Unknown,
+}
+
+/// Error reported by the server of a backend.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct ApiError {
+    pub kind: ErrorKind,
+    /// Backend specific error code.
+    pub code: u32,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl Error {
+    /// Get the kind of server error, if this error was returned by the server.
+    #[must_use]
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            Error::Api(e) => Some(e.kind),
+            Error::RateLimited => Some(ErrorKind::RateLimited),
+            _ => None,
+        }
+    }
+}
+
+/// Reason why an account was logged out.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LogoutReason {
+    /// The session was revoked, e.g. by logging out from another device or changing the
+    /// account password.
+    SessionRevoked,
+    /// The session could not be refreshed.
+    RefreshFailed,
+    /// The server ended the session, e.g. because the account was disabled or deleted.
+    ForcedLogout,
+    /// The locally stored authentication data could not be decrypted.
+    SecretUnavailable,
+}
+
+impl LogoutReason {
+    /// Suggested action for the user to recover from the logout.
+    #[must_use]
+    pub fn recovery_hint(&self) -> &'static str {
+        match self {
+            LogoutReason::SessionRevoked | LogoutReason::RefreshFailed => {
+                "Log in again to resume notifications."
+            }
+            LogoutReason::ForcedLogout => {
+                "Check the account status with the provider before logging in again."
+            }
+            LogoutReason::SecretUnavailable => {
+                "The stored credentials could not be read, log in again to replace them."
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for LogoutReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            LogoutReason::SessionRevoked => "Session was revoked",
+            LogoutReason::RefreshFailed => "Session could not be refreshed",
+            LogoutReason::ForcedLogout => "Session was ended by the server",
+            LogoutReason::SecretUnavailable => "Stored credentials could not be decrypted",
+        };
+        f.write_str(reason)
+    }
+}
+
 /// An action to be taken on an account.
 ///
 /// Since this is specific to each backend implementation, we only
@@ -83,8 +178,21 @@ impl Action {
 pub struct NewEmail {
     /// Sender of the email.
     pub sender: String,
+    /// Email address of the sender.
+    #[serde(default)]
+    pub sender_address: String,
     /// Subject of the email.
     pub subject: String,
+    /// Backend ids of the folders and labels the email is in.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Backend id of the thread or conversation the email belongs to.
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    /// Number of new messages represented by this email. Greater than 1 if several messages
+    /// of the same thread were collapsed into this email.
+    #[serde(default = "default_message_count")]
+    pub message_count: usize,
     /// Encoded data to move this message to trash
     pub move_to_trash_action: Option<Action>,
     /// Encoded data to mark this message as read.
@@ -93,6 +201,56 @@ pub struct NewEmail {
     pub move_to_spam_action: Option<Action>,
 }
 
+impl NewEmail {
+    /// Create a new email which is not in any folder or thread and has no actions.
+    #[must_use]
+    pub fn new(
+        sender: impl Into<String>,
+        sender_address: impl Into<String>,
+        subject: impl Into<String>,
+    ) -> Self {
+        Self {
+            sender: sender.into(),
+            sender_address: sender_address.into(),
+            subject: subject.into(),
+            labels: Vec::new(),
+            thread_id: None,
+            message_count: default_message_count(),
+            move_to_trash_action: None,
+            mark_as_read_action: None,
+            move_to_spam_action: None,
+        }
+    }
+}
+
+fn default_message_count() -> usize {
+    1
+}
+
+/// Session of an account on the backend's servers, e.g. one for each logged in device.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RemoteSession {
+    /// Backend specific id of the session.
+    pub id: String,
+    /// Name of the client which created the session.
+    pub client_name: String,
+    /// Unix timestamp in seconds at which the session was created.
+    pub created_at: i64,
+    /// Whether this is the session used to poll the account.
+    pub current: bool,
+    /// Whether the session can be revoked.
+    pub revocable: bool,
+}
+
+/// Number of unread messages of an account.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UnreadCounts {
+    /// Unread messages across all folders which produce notifications.
+    pub total: u32,
+    /// Unread messages of each folder which produces notifications, by backend specific id.
+    pub folders: BTreeMap<String, u32>,
+}
+
 /// Implementation for the backends.
 pub trait Backend: Send + Sync {
     /// Return the backend's name.
@@ -108,6 +266,30 @@ pub trait Backend: Send + Sync {
     /// Should return an error if the client failed to build.
     fn create_client(&self, proxy: Option<Proxy>) -> Result<Arc<Client>>;
 
+    /// Execute a cheap request with `client` to check whether the backend's servers can be
+    /// reached. No authentication is required.
+    ///
+    /// # Errors
+    ///
+    /// Should return error if the servers could not be reached or the check is not supported
+    /// by the backend.
+    fn check_connection(&self, client: Arc<Client>) -> Result<()> {
+        let _ = client;
+        Err(Error::Unsupported)
+    }
+
+    /// Create a login sequence which re-authenticates the existing `account` with `client`,
+    /// see [`crate::yhm::Yhm::relogin`].
+    ///
+    /// # Errors
+    ///
+    /// Should return [`Error::Unsupported`] if the backend does not support login or an error
+    /// if the account's state could not be loaded.
+    fn relogin_sequence(&self, client: Arc<Client>, account: &Account) -> Result<Box<dyn Relogin>> {
+        let _ = (client, account);
+        Err(Error::Unsupported)
+    }
+
     /// Create a new [`Poller`] instance from the database `account` state.
     ///
     /// # Errors
@@ -116,6 +298,33 @@ pub trait Backend: Send + Sync {
     fn new_poller(&self, client: Arc<Client>, account: Account) -> Result<Box<dyn Poller>>;
 }
 
+/// Backend specific login sequence which re-authenticates an existing account, see
+/// [`Backend::relogin_sequence`].
+///
+/// Use [`Self::downcast_mut`] to access the backend's sequence and complete the login, then
+/// convert it with [`Self::into_account`] to update the account.
+pub trait Relogin: Any + Send {
+    /// Email of the account which is being re-authenticated.
+    fn email(&self) -> &str;
+
+    /// Store the new authentication data on the existing account.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the login is not complete or the account could not be updated.
+    fn into_account(
+        self: Box<Self>,
+        yhm: &crate::yhm::Yhm,
+    ) -> std::result::Result<(), crate::yhm::Error>;
+}
+
+impl dyn Relogin {
+    /// Access the backend specific sequence, e.g. [`proton::ReloginSequence`].
+    pub fn downcast_mut<T: Relogin>(&mut self) -> Option<&mut T> {
+        (self as &mut dyn Any).downcast_mut()
+    }
+}
+
 /// Trait that needs to be implemented for all backend accounts
 pub trait Poller {
     /// Check if there are new emails on the account.
@@ -125,6 +334,15 @@ pub trait Poller {
     /// Return error if the operation failed.
     fn check(&mut self) -> Result<Vec<NewEmail>>;
 
+    /// Collapse the `emails` of the same thread into a single email, see
+    /// [`NewEmail::message_count`].
+    ///
+    /// The emails returned by [`Poller::check`] are collapsed after the notification rules
+    /// have been applied to each of them. By default the emails are returned unchanged.
+    fn collapse(&self, emails: Vec<NewEmail>) -> Vec<NewEmail> {
+        emails
+    }
+
     /// Execute the given `action`
     ///
     /// # Errors
@@ -138,4 +356,91 @@ pub trait Poller {
     ///
     /// Return error if the operation failed.
     fn logout(&mut self) -> Result<()>;
+
+    /// List the sessions of the account on the backend's servers.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed or is not supported by the backend.
+    fn sessions(&mut self) -> Result<Vec<RemoteSession>> {
+        Err(Error::Unsupported)
+    }
+
+    /// Revoke the session with `id`. Revoking the current session logs out the account.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed or is not supported by the backend.
+    fn revoke_session(&mut self, id: &str) -> Result<()> {
+        let _ = id;
+        Err(Error::Unsupported)
+    }
+
+    /// Number of unread messages of the account, as of the last check.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed or is not supported by the backend.
+    fn unread_counts(&mut self) -> Result<UnreadCounts> {
+        Err(Error::Unsupported)
+    }
+}
+
+/// Result of a connectivity check, see [`crate::yhm::Yhm::test_proxy`].
+///
+/// All failure variants contain the error description.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ConnectionDiagnosis {
+    /// The servers could be reached.
+    Ok {
+        /// Duration of the check request.
+        latency: Duration,
+    },
+    /// The host name of the proxy or the server could not be resolved.
+    Dns(String),
+    /// Could not connect to the server.
+    Connect(String),
+    /// Could not connect to the proxy or the proxy could not connect to the server.
+    ProxyConnect(String),
+    /// The proxy rejected the credentials.
+    AuthRejected(String),
+    /// The TLS handshake with the server failed.
+    Tls(String),
+    /// The request completed with an error http status, e.g. from a broken proxy or a captive
+    /// portal.
+    Status(u16, String),
+    /// Any other error.
+    Other(String),
+}
+
+impl ConnectionDiagnosis {
+    /// Create a diagnosis from the `result` of [`Backend::check_connection`] which took
+    /// `latency` to complete. `proxied` is whether the connection was made through a proxy.
+    #[must_use]
+    pub fn new(result: &Result<()>, latency: Duration, proxied: bool) -> Self {
+        let err = match result {
+            Ok(()) => return Self::Ok { latency },
+            Err(err) => err,
+        };
+
+        let Error::Http(http_err) = err else {
+            return Self::Other(err.to_string());
+        };
+
+        match http_err.connection_failure() {
+            Some(http::ConnectionFailure::Dns) => Self::Dns(err.to_string()),
+            Some(http::ConnectionFailure::Connect) if proxied => {
+                Self::ProxyConnect(err.to_string())
+            }
+            Some(http::ConnectionFailure::Connect) => Self::Connect(err.to_string()),
+            Some(http::ConnectionFailure::ProxyAuth) => Self::AuthRejected(err.to_string()),
+            Some(http::ConnectionFailure::Tls) => Self::Tls(err.to_string()),
+            None => match http_err {
+                http::Error::Http(status, _) | http::Error::Status(status, _) => {
+                    Self::Status(*status, err.to_string())
+                }
+                _ => Self::Other(err.to_string()),
+            },
+        }
+    }
 }
@@ -1,23 +1,32 @@
 //! You have mail implementation for proton mail accounts.
 
-use crate::backend::{Action, Error as BackendError, Error, NewEmail, Result as BackendResult};
-use crate::state::Account;
+use crate::backend::{
+    Action, ApiError, Error as BackendError, Error, ErrorKind as BackendErrorKind, LogoutReason,
+    NewEmail, Relogin, RemoteSession, Result as BackendResult, UnreadCounts,
+};
+use crate::state::{Account, State};
 use crate::yhm::{IntoAccount, Yhm};
 use anyhow::anyhow;
-use http::{Client, Proxy};
+use http::{Client, FromResponse, Proxy};
 use parking_lot::Mutex;
-use proton_api::auth::{Auth as ProtonAuth, InMemoryStore, StoreError, new_thread_safe_store};
+use proton_api::auth::{
+    Auth as ProtonAuth, AuthUpdate, InMemoryStore, StoreError, ThreadSafeStore,
+    new_thread_safe_store,
+};
 use proton_api::client::ProtonExtension;
+use proton_api::domain::errors::{APIError, ErrorKind};
 use proton_api::domain::event::MoreEvents;
-use proton_api::domain::{Boolean, event, label, message};
-use proton_api::login::Sequence;
+use proton_api::domain::{Boolean, conversation, event, label, message};
+use proton_api::login::{Sequence, Snapshot};
 use proton_api::requests::{
-    GetEventRequest, GetLabelsRequest, GetLatestEventRequest, PutLabelMessageRequest,
-    PutMarkMessageReadRequest,
+    GetEventRequest, GetLabelsRequest, GetLatestEventRequest, GetMessageCountsRequest,
+    OperationResponse, Ping, PutLabelConversationRequest, PutLabelMessageRequest,
+    PutMarkConversationReadRequest, PutMarkMessageReadRequest,
 };
-use proton_api::session::Session;
+use proton_api::routing::AlternativeRouting;
+use proton_api::session::{AppIdentity, Session};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::Duration;
@@ -29,6 +38,7 @@ pub use proton_api;
 /// Proton Mail backend.
 pub struct Backend {
     base_url: Option<http::url::Url>,
+    identity: Option<AppIdentity>,
     // The default client for proton servers can be shared between multiple accounts as long as
     // the process is still alive. Authentication is always read from the database, so there is no
     // risk of the clients interfering with one another.
@@ -40,26 +50,193 @@ impl Backend {
     ///
     /// The `base_url` can be optionally overridden. If no value is specified, the default
     /// url will be used.
+    ///
+    /// The `identity` is used for all accounts and is stored with each account. If no
+    /// value is specified, the identity stored with the account is used instead.
     #[must_use]
-    pub fn new(base_url: Option<http::url::Url>) -> Arc<Self> {
+    pub fn new(base_url: Option<http::url::Url>, identity: Option<AppIdentity>) -> Arc<Self> {
         Arc::new(Backend {
             base_url,
+            identity,
             default_client: Mutex::new(None),
         })
     }
 
-    /// Create a new login sequence for proton accounts.
+    /// Create a new login sequence for proton accounts with the default [`AppIdentity`].
+    ///
+    /// If the Proton servers can't be reached, alternative routes are tried.
     ///
     /// # Errors
     ///
     /// Returns error  if the http client could not be constructed.
     pub fn login_sequence(proxy: Option<Proxy>) -> http::Result<Sequence> {
+        Self::login_sequence_with_identity(proxy, AppIdentity::default())
+    }
+
+    /// Create a new login sequence for proton accounts which identifies as `identity`.
+    ///
+    /// The identity is stored with the account once the login completes.
+    ///
+    /// # Errors
+    ///
+    /// Returns error  if the http client could not be constructed.
+    pub fn login_sequence_with_identity(
+        proxy: Option<Proxy>,
+        identity: AppIdentity,
+    ) -> http::Result<Sequence> {
         let client = new_client(proxy, None)?;
         let store = new_thread_safe_store(InMemoryStore::default());
-        let session = Session::new(client, store);
+        let session = Session::new(client, store)
+            .with_alternative_routing(AlternativeRouting::default())
+            .with_identity(identity);
 
         Ok(Sequence::new(session))
     }
+
+    /// Export the in progress login `sequence` as a blob encrypted with the key of `state`.
+    ///
+    /// The sequence can be restored with [`Self::resume_login_sequence`], e.g. if the process
+    /// was terminated while waiting on the 2FA code.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the sequence state could not be retrieved or encrypted.
+    pub fn export_login_sequence(
+        sequence: &Sequence,
+        state: &State,
+    ) -> Result<Vec<u8>, crate::yhm::Error> {
+        let snapshot = sequence.snapshot().map_err(|e| {
+            error!("Failed to create login sequence snapshot: {e}");
+            Error::Unknown(anyhow!(e))
+        })?;
+        let session = sequence.session();
+        let exported = ExportedSequence {
+            snapshot,
+            proxy: session.client().proxy().cloned(),
+            base_url: session.alternative_base_url().map(Into::into),
+            identity: Some(session.identity().clone()),
+        };
+
+        Ok(state.encrypt(&exported).inspect_err(|e| {
+            error!("Failed to encrypt login sequence: {e}");
+        })?)
+    }
+
+    /// Resume a login sequence from a `blob` created with [`Self::export_login_sequence`]
+    /// with the same `state`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the blob could not be decrypted or the http client could not be
+    /// constructed.
+    pub fn resume_login_sequence(
+        &self,
+        blob: &[u8],
+        state: &State,
+    ) -> Result<Sequence, crate::yhm::Error> {
+        let exported = state.decrypt::<ExportedSequence>(blob).inspect_err(|e| {
+            error!("Failed to decrypt login sequence: {e}");
+        })?;
+
+        let client = crate::backend::Backend::create_client(self, exported.proxy)?;
+        let session = self.new_session(
+            client,
+            new_thread_safe_store(InMemoryStore::default()),
+            self.identity(exported.identity),
+            exported.base_url.as_deref(),
+        );
+
+        Ok(Sequence::resume(session, exported.snapshot).map_err(|e| {
+            error!("Failed to resume login sequence: {e}");
+            Error::Unknown(anyhow!(e))
+        })?)
+    }
+
+    /// Get the notification rules of the proton account with `email`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the account does not exist or its state could not be loaded.
+    pub fn notify_rules(yhm: &Yhm, email: &str) -> Result<NotifyRules, crate::yhm::Error> {
+        let account = Self::proton_account(yhm, email)?;
+        Ok(account
+            .state::<TaskState>()?
+            .map(|state| state.notify_rules)
+            .unwrap_or_default())
+    }
+
+    /// Replace the notification rules of the proton account with `email`.
+    ///
+    /// The rules apply from the next poll onwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the account does not exist or its state could not be updated.
+    pub fn set_notify_rules(
+        yhm: &Yhm,
+        email: &str,
+        rules: NotifyRules,
+    ) -> Result<(), crate::yhm::Error> {
+        let account = Self::proton_account(yhm, email)?;
+        account
+            .update_state(|state: Option<TaskState>| {
+                let mut state = state.unwrap_or_default();
+                state.notify_rules = rules;
+                Some(state)
+            })
+            .inspect_err(|e| {
+                error!("Failed to store notify rules: {e}");
+            })?;
+        Ok(())
+    }
+
+    fn proton_account(yhm: &Yhm, email: &str) -> Result<Account, crate::yhm::Error> {
+        let account = yhm
+            .account(email)?
+            .ok_or(crate::yhm::Error::AccountNotFound(email.to_owned()))?;
+        if account.backend() != NAME {
+            return Err(crate::yhm::Error::BackendNotFound(NAME.to_owned()));
+        }
+        Ok(account)
+    }
+
+    /// Resolve the identity to use, preferring the configured identity over the `stored`
+    /// one.
+    fn identity(&self, stored: Option<AppIdentity>) -> AppIdentity {
+        self.identity.clone().or(stored).unwrap_or_default()
+    }
+
+    /// Create a new session which continues on the alternative route `base_url`, if any.
+    fn new_session(
+        &self,
+        client: Arc<Client>,
+        store: ThreadSafeStore,
+        identity: AppIdentity,
+        base_url: Option<&str>,
+    ) -> Session {
+        let mut session = Session::new(client, store).with_identity(identity);
+        // Only the default proton servers have alternative routes.
+        if self.base_url.is_none() {
+            session = session.with_alternative_routing(AlternativeRouting::default());
+            if let Some(base_url) = base_url {
+                match http::url::Url::parse(base_url) {
+                    Ok(url) => session.use_base_url(url),
+                    Err(e) => error!("Invalid alternative route: {e}"),
+                }
+            }
+        }
+        session
+    }
+}
+
+/// Login sequence state exported with [`Backend::export_login_sequence`].
+#[derive(Serialize, Deserialize)]
+struct ExportedSequence {
+    snapshot: Snapshot,
+    proxy: Option<Proxy>,
+    base_url: Option<String>,
+    #[serde(default)]
+    identity: Option<AppIdentity>,
 }
 
 pub const NAME: &str = "Proton Mail";
@@ -90,28 +267,67 @@ impl crate::backend::Backend for Backend {
         Ok(client)
     }
 
+    fn check_connection(&self, client: Arc<Client>) -> BackendResult<()> {
+        Session::with_in_memory_auth_store(client)
+            .execute(Ping)
+            .inspect_err(|e| error!("Failed to ping server: {e}"))?;
+        Ok(())
+    }
+
+    fn relogin_sequence(
+        &self,
+        client: Arc<Client>,
+        account: &Account,
+    ) -> BackendResult<Box<dyn Relogin>> {
+        let state = account
+            .state::<TaskState>()
+            .inspect_err(|e| error!("Failed to load state: {e}"))?
+            .unwrap_or_default();
+
+        let session = self.new_session(
+            client,
+            new_thread_safe_store(InMemoryStore::default()),
+            self.identity(state.identity),
+            state.base_url.as_deref(),
+        );
+
+        Ok(Box::new(ReloginSequence::new(session, account.email())))
+    }
+
     fn new_poller(
         &self,
         client: Arc<Client>,
         account: Account,
     ) -> BackendResult<Box<dyn crate::backend::Poller>> {
-        let auth = account.secret::<ProtonAuth>().map_err(|e| {
-            error!("Failed to load secret state: {e}");
-            e
-        })?;
+        let auth = match account.secret::<ProtonAuth>() {
+            Ok(auth) => auth,
+            Err(e @ (crate::state::Error::Crypto(_) | crate::state::Error::Encryption(_))) => {
+                error!("Failed to decrypt secret state, logging out: {e}");
+                account.set_secret::<ProtonAuth>(None)?;
+                return Err(Error::SessionExpired(LogoutReason::SecretUnavailable));
+            }
+            Err(e) => {
+                error!("Failed to load secret state: {e}");
+                return Err(e.into());
+            }
+        };
         let state = account.state::<TaskState>().map_err(|e| {
             error!("Failed to load state: {e}");
             e
         })?;
 
+        let mut state = state.unwrap_or_default();
+        let identity = self.identity(state.identity.take());
+        state.identity = Some(identity.clone());
+
         let auth_store = new_thread_safe_store(AuthStore::new(account.clone(), auth));
 
-        let session = Session::new(client, auth_store);
+        let session = self.new_session(client, auth_store, identity, state.base_url.as_deref());
 
         let account = Poller {
-            session,
-            state: state.unwrap_or(TaskState::new()),
             account,
+            session,
+            state,
         };
 
         Ok(Box::new(account))
@@ -146,7 +362,51 @@ impl proton_api::auth::Store for AuthStore {
     fn delete(&mut self) -> Result<(), StoreError> {
         self.account
             .set_secret::<ProtonAuth>(None)
-            .map_err(|e| StoreError::Write(anyhow::Error::new(e)))
+            .map_err(|e| StoreError::Write(anyhow::Error::new(e)))?;
+        self.auth = None;
+        Ok(())
+    }
+
+    /// Re-read the authentication data from the database, since another process may have
+    /// refreshed it already.
+    fn reload(&mut self) -> Result<Option<&ProtonAuth>, StoreError> {
+        self.auth = self
+            .account
+            .secret::<ProtonAuth>()
+            .map_err(|e| StoreError::Read(anyhow::Error::new(e)))?;
+        Ok(self.auth.as_ref())
+    }
+
+    /// Check and write the data in a single transaction, so a refresh by another process is
+    /// never overwritten. The transaction does not include the refresh request.
+    fn update_if_unchanged(
+        &mut self,
+        current: &ProtonAuth,
+        update: AuthUpdate,
+    ) -> Result<bool, StoreError> {
+        let mut applied = false;
+        self.auth = self
+            .account
+            .update_secret::<ProtonAuth, _>(|auth| {
+                if !auth
+                    .as_ref()
+                    .is_some_and(|auth| auth.has_refresh_token_of(current))
+                {
+                    return auth;
+                }
+                applied = true;
+                match update {
+                    AuthUpdate::Keep => auth,
+                    AuthUpdate::Store(auth) => Some(auth),
+                    AuthUpdate::Reject => auth.map(|auth| ProtonAuth {
+                        refresh_rejected: true,
+                        ..auth
+                    }),
+                    AuthUpdate::Delete => None,
+                }
+            })
+            .map_err(|e| StoreError::Write(anyhow::Error::new(e)))?;
+        Ok(applied)
     }
 }
 
@@ -156,6 +416,200 @@ struct Poller {
     state: TaskState,
 }
 
+impl Poller {
+    /// Whether the authentication data was removed after the server rejected a refresh.
+    fn is_session_removed(&self) -> bool {
+        matches!(self.session.auth_store().read().get(), Ok(None))
+    }
+
+    /// Store the state, keeping the notification rules which may have been changed since the
+    /// poller was created.
+    fn store_state(&mut self) -> Result<(), crate::state::Error> {
+        let state = &mut self.state;
+        self.account.update_state(|stored: Option<TaskState>| {
+            if let Some(stored) = stored {
+                state.notify_rules = stored.notify_rules;
+            }
+            Some(state.clone())
+        })?;
+        Ok(())
+    }
+
+    /// Synchronize the notifiable folders and labels and continue from the latest event.
+    fn sync(&mut self) -> BackendResult<()> {
+        let event_id = self
+            .session
+            .execute_with_auth(GetLatestEventRequest {})
+            .map_err(|e| {
+                error!("Failed to get latest event id: {e}");
+                e
+            })?
+            .event_id;
+
+        let mut labels = Vec::new();
+        for label_type in [label::Type::Folder, label::Type::Label] {
+            let response = self
+                .session
+                .execute_with_auth(GetLabelsRequest::new(label_type))
+                .map_err(|e| {
+                    error!("Failed to get custom labels of type {label_type:?}: {e}");
+                    e
+                })?;
+            labels.extend(response.labels);
+        }
+
+        self.state.last_event_id = Some(event_id);
+        self.state.active_folder_ids = HashSet::with_capacity(labels.len() + 1);
+        self.state.active_folder_ids.insert(label::Id::inbox());
+        for label in labels {
+            if label.notify == Boolean::True {
+                debug!("Found label {} ({})", label.name, label.id);
+                self.state.active_folder_ids.insert(label.id);
+            }
+        }
+        // Counts are retrieved again on demand.
+        self.state.unread_counts = None;
+        self.state.base_url = self.session.alternative_base_url().map(String::from);
+        self.store_state().map_err(|e| {
+            error!("Failed to store state after sync: {e}");
+            e
+        })?;
+        Ok(())
+    }
+
+    fn apply_action(&mut self, action: AccountAction) -> BackendResult<()> {
+        match action {
+            AccountAction::MarkMessageRead(id) => {
+                debug!("Marking {id} as read");
+                self.execute_operation(
+                    PutMarkMessageReadRequest::new([id]),
+                    "mark message as read",
+                    |response| response.responses,
+                )
+            }
+            AccountAction::MoveMessageToTrash(id) => {
+                debug!("Moving {id} to trash");
+                self.execute_operation(
+                    PutLabelMessageRequest::new(label::Id::trash(), [id]),
+                    "move message to trash",
+                    |response| response.responses,
+                )
+            }
+            AccountAction::MoveMessageToSpam(id) => {
+                debug!("Moving {id} to spam");
+                self.execute_operation(
+                    PutLabelMessageRequest::new(label::Id::spam(), [id]),
+                    "move message to spam",
+                    |response| response.responses,
+                )
+            }
+            AccountAction::MarkConversationRead(id) => {
+                debug!("Marking conversation {id} as read");
+                self.execute_operation(
+                    PutMarkConversationReadRequest::new([id]),
+                    "mark conversation as read",
+                    |response| response.responses,
+                )
+            }
+            AccountAction::MoveConversationToTrash(id) => {
+                debug!("Moving conversation {id} to trash");
+                self.execute_operation(
+                    PutLabelConversationRequest::new(label::Id::trash(), [id]),
+                    "move conversation to trash",
+                    |response| response.responses,
+                )
+            }
+            AccountAction::MoveConversationToSpam(id) => {
+                debug!("Moving conversation {id} to spam");
+                self.execute_operation(
+                    PutLabelConversationRequest::new(label::Id::spam(), [id]),
+                    "move conversation to spam",
+                    |response| response.responses,
+                )
+            }
+        }
+    }
+
+    /// Execute `request` and check that each of the operations in the response, retrieved
+    /// with `responses`, succeeded. The `operation` is used to describe failures.
+    fn execute_operation<R, T>(
+        &self,
+        request: R,
+        operation: &str,
+        responses: impl FnOnce(<R::Response as FromResponse>::Output) -> Vec<OperationResponse<T>>,
+    ) -> BackendResult<()>
+    where
+        R: http::Request,
+    {
+        let response = self.session.execute_with_auth(request).inspect_err(|e| {
+            error!("Failed to {operation}: {e}");
+        })?;
+        for response in responses(response) {
+            response.into_result().map_err(|e| {
+                error!("Failed to {operation}: {}", e);
+                Error::Unknown(anyhow!("Failed to {operation}"))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Convert http status errors into the matching backend error based on the [`ErrorKind`]
+/// of the server error.
+///
+/// If the session was removed after a rejected refresh, the error is always reported as an
+/// expired session. The first rejection only marks the session, since another process may
+/// have refreshed it in the meantime, and is reported as a regular API error.
+fn backend_error(error: BackendError, session_removed: bool) -> BackendError {
+    let (code, api_error) = match error {
+        BackendError::Http(http::Error::Http(code, response)) => {
+            (code, APIError::with_status_and_response(code, response))
+        }
+        BackendError::Http(http::Error::Status(code, body)) => {
+            (code, APIError::with_status_and_body(code, &body))
+        }
+        error => return error,
+    };
+    match api_error.kind() {
+        ErrorKind::InvalidRefreshToken if session_removed => {
+            BackendError::SessionExpired(LogoutReason::SessionRevoked)
+        }
+        ErrorKind::AccountDeleted | ErrorKind::AccountDisabled => {
+            BackendError::SessionExpired(LogoutReason::ForcedLogout)
+        }
+        _ if session_removed || code == 401 => {
+            BackendError::SessionExpired(LogoutReason::RefreshFailed)
+        }
+        ErrorKind::RateLimited => BackendError::RateLimited,
+        ErrorKind::AppVersionMissing | ErrorKind::AppVersionBad => {
+            BackendError::UnsupportedAppVersion
+        }
+        _ => BackendError::Api(api_error.into()),
+    }
+}
+
+impl From<APIError> for ApiError {
+    fn from(error: APIError) -> Self {
+        let kind = match error.kind() {
+            ErrorKind::InvalidValue => BackendErrorKind::InvalidValue,
+            ErrorKind::WrongPassword => BackendErrorKind::WrongPassword,
+            ErrorKind::HumanVerificationRequired => BackendErrorKind::HumanVerificationRequired,
+            ErrorKind::InvalidRefreshToken => BackendErrorKind::RefreshRejected,
+            ErrorKind::RateLimited => BackendErrorKind::RateLimited,
+            ErrorKind::AppVersionMissing
+            | ErrorKind::AppVersionBad
+            | ErrorKind::AccountDeleted
+            | ErrorKind::AccountDisabled
+            | ErrorKind::Unknown => BackendErrorKind::Unknown,
+        };
+        Self {
+            kind,
+            code: error.api_code,
+            message: error.to_string(),
+        }
+    }
+}
+
 impl crate::backend::Poller for Poller {
     #[tracing::instrument(level=Level::DEBUG,skip(self),fields(email=%self.account.email()))]
     fn check(&mut self) -> BackendResult<Vec<NewEmail>> {
@@ -163,35 +617,7 @@ impl crate::backend::Poller for Poller {
             // First time this code is run, init state.
             if self.state.last_event_id.is_none() {
                 debug!("Account is being run for the fist time, syncing resources");
-                let event_id = self
-                    .session
-                    .execute_with_auth(GetLatestEventRequest {})
-                    .map_err(|e| {
-                        error!("Failed to get latest event id: {e}");
-                        e
-                    })?
-                    .event_id;
-                self.state.last_event_id = Some(event_id);
-
-                let folders = self
-                    .session
-                    .execute_with_auth(GetLabelsRequest::new(label::Type::Folder))
-                    .map_err(|e| {
-                        error!("Failed to get custom folders: {e}");
-                        e
-                    })?
-                    .labels;
-                self.state.active_folder_ids.reserve(folders.len());
-                for folder in folders {
-                    if folder.notify == Boolean::True {
-                        debug!("Found folder {} ({})", folder.name, folder.id);
-                        self.state.active_folder_ids.insert(folder.id);
-                    }
-                }
-                self.account.set_state(Some(&self.state)).map_err(|e| {
-                    error!("Failed to store state after init: {e}");
-                    e
-                })?;
+                self.sync()?;
             }
 
             let mut result = EventState::new();
@@ -210,6 +636,13 @@ impl crate::backend::Poller for Poller {
                     tracing::trace!("Next={} HasMore={:?}", event.event_id, has_more);
                     if event.event_id != event_id || has_more == MoreEvents::Yes {
                         tracing::trace!("Handling event");
+                        if event.refresh.is_mail() {
+                            // The event stream can't be trusted to bring the state up to date.
+                            debug!("Server requested a refresh, syncing resources");
+                            self.sync()?;
+                            return Ok(result.into());
+                        }
+
                         if let Some(label_events) = event.labels {
                             tracing::trace!("Handling label events");
                             self.state.handle_label_events(label_events);
@@ -219,6 +652,11 @@ impl crate::backend::Poller for Poller {
                             result.handle_message_events(message_events, &self.state);
                         }
 
+                        if let Some(message_counts) = event.message_counts {
+                            tracing::trace!("Handling message counts");
+                            self.state.handle_message_counts(message_counts);
+                        }
+
                         event_id = event.event_id;
                         tracing::trace!("Next Event={}", event_id);
                         self.state.last_event_id = Some(event_id.clone());
@@ -228,7 +666,8 @@ impl crate::backend::Poller for Poller {
                             "No more work left. Next Event = {:?}",
                             self.state.last_event_id
                         );
-                        self.account.set_state(Some(&self.state)).map_err(|e| {
+                        self.state.base_url = self.session.alternative_base_url().map(String::from);
+                        self.store_state().map_err(|e| {
                             error!("Failed to update state after check: {e}");
                             e
                         })?;
@@ -241,18 +680,11 @@ impl crate::backend::Poller for Poller {
             Ok(Vec::new())
         };
 
-        match check_fn() {
-            Ok(v) => Ok(v),
-            Err(e) => match e {
-                BackendError::Http(http::Error::Http(code, response)) => {
-                    if code == 401 {
-                        return Err(BackendError::SessionExpired);
-                    }
-                    Err(http::Error::Http(code, response).into())
-                }
-                e => Err(e),
-            },
-        }
+        check_fn().map_err(|e| backend_error(e, self.is_session_removed()))
+    }
+
+    fn collapse(&self, emails: Vec<NewEmail>) -> Vec<NewEmail> {
+        collapse_conversations(emails)
     }
 
     #[tracing::instrument(level=Level::DEBUG,skip(self, action),fields(email=%self.account.email()))]
@@ -262,55 +694,8 @@ impl crate::backend::Poller for Poller {
             Error::InvalidAction
         })?;
 
-        match action {
-            AccountAction::MarkMessageRead(id) => {
-                debug!("Marking {id} as read");
-                let response = self
-                    .session
-                    .execute_with_auth(PutMarkMessageReadRequest::new([id]))
-                    .inspect_err(|e| {
-                        error!("Failed to mark message as read: {e}");
-                    })?;
-                for response in response.responses {
-                    response.into_result().map_err(|e| {
-                        error!("Failed to mark message as read: {}", e);
-                        Error::Unknown(anyhow!("Failed to mark message as read"))
-                    })?;
-                }
-            }
-            AccountAction::MoveMessageToTrash(id) => {
-                debug!("Moving {id} to trash");
-                let response = self
-                    .session
-                    .execute_with_auth(PutLabelMessageRequest::new(label::Id::trash(), [id]))
-                    .inspect_err(|e| {
-                        error!("Failed to move message to trash: {e}");
-                    })?;
-                for response in response.responses {
-                    response.into_result().map_err(|e| {
-                        error!("Failed to move message to trash: {}", e);
-                        Error::Unknown(anyhow!("Failed to move message to trash"))
-                    })?;
-                }
-            }
-            AccountAction::MoveMessageToSpam(id) => {
-                debug!("Moving {id} to spam");
-                let response = self
-                    .session
-                    .execute_with_auth(PutLabelMessageRequest::new(label::Id::spam(), [id]))
-                    .inspect_err(|e| {
-                        error!("Failed to move message to spam: {e}");
-                    })?;
-                for response in response.responses {
-                    response.into_result().map_err(|e| {
-                        error!("Failed to move message to spam: {}", e);
-                        Error::Unknown(anyhow!("Failed to move message to spam"))
-                    })?;
-                }
-            }
-        }
-
-        Ok(())
+        self.apply_action(action)
+            .map_err(|e| backend_error(e, self.is_session_removed()))
     }
 
     fn logout(&mut self) -> BackendResult<()> {
@@ -318,6 +703,92 @@ impl crate::backend::Poller for Poller {
         self.session.logout()?;
         Ok(())
     }
+
+    #[tracing::instrument(level=Level::DEBUG,skip(self),fields(email=%self.account.email()))]
+    fn sessions(&mut self) -> BackendResult<Vec<RemoteSession>> {
+        let current = self.session.session_uid();
+        let sessions = self
+            .session
+            .sessions()
+            .map_err(|e| backend_error(e.into(), self.is_session_removed()))?;
+        Ok(sessions
+            .into_iter()
+            .map(|session| RemoteSession {
+                current: current.as_ref() == Some(&session.uid),
+                id: session.uid.0,
+                client_name: session.localized_client_name,
+                created_at: session.create_time,
+                revocable: session.revocable == Boolean::True,
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(level=Level::DEBUG,skip(self),fields(email=%self.account.email()))]
+    fn unread_counts(&mut self) -> BackendResult<UnreadCounts> {
+        if self.state.unread_counts.is_none() {
+            debug!("Retrieving message counts");
+            let counts = self
+                .session
+                .execute_with_auth(GetMessageCountsRequest {})
+                .map_err(|e| {
+                    error!("Failed to get message counts: {e}");
+                    backend_error(e.into(), self.is_session_removed())
+                })?
+                .counts;
+            self.state.unread_counts = Some(HashMap::with_capacity(counts.len()));
+            self.state.handle_message_counts(counts);
+            self.store_state().map_err(|e| {
+                error!("Failed to store message counts: {e}");
+                e
+            })?;
+        }
+
+        Ok(self.state.folder_unread_counts())
+    }
+
+    #[tracing::instrument(level=Level::DEBUG,skip(self),fields(email=%self.account.email()))]
+    fn revoke_session(&mut self, id: &str) -> BackendResult<()> {
+        self.session
+            .revoke_session(&id.into())
+            .map_err(|e| backend_error(e.into(), self.is_session_removed()))
+    }
+}
+
+/// Collapse the messages of the same conversation into a single email for the most recent
+/// message, whose actions apply to the whole conversation.
+fn collapse_conversations(emails: Vec<NewEmail>) -> Vec<NewEmail> {
+    let mut result: Vec<NewEmail> = Vec::with_capacity(emails.len());
+    // Index in `result` of the email for each conversation.
+    let mut conversations: HashMap<String, usize> = HashMap::new();
+
+    for email in emails {
+        let Some(conversation_id) = email.thread_id.clone() else {
+            result.push(email);
+            continue;
+        };
+
+        if let Some(&index) = conversations.get(&conversation_id) {
+            tracing::trace!("Collapsing message into conversation {conversation_id}");
+            let id = conversation::Id(conversation_id);
+            result[index] = NewEmail {
+                message_count: result[index].message_count + email.message_count,
+                move_to_trash_action: Some(
+                    AccountAction::MoveConversationToTrash(id.clone()).to_action(),
+                ),
+                mark_as_read_action: Some(
+                    AccountAction::MarkConversationRead(id.clone()).to_action(),
+                ),
+                move_to_spam_action: Some(AccountAction::MoveConversationToSpam(id).to_action()),
+                ..email
+            };
+            continue;
+        }
+
+        conversations.insert(conversation_id, result.len());
+        result.push(email);
+    }
+
+    result
 }
 
 /// Create a new client configured for proton.
@@ -341,12 +812,47 @@ fn new_client(
 }
 
 /// Contains the necessary state to process events.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskState {
     /// The last event that was processed.
     pub last_event_id: Option<event::Id>,
-    /// The current list of folders that have the notification setting enabled.
+    /// The current list of folders and labels that have the notification setting enabled.
     pub active_folder_ids: HashSet<label::Id>,
+    /// Base url of the alternative route in use, if the default servers can't be reached.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Id of the Proton user, used to verify re-authentication.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// Identity of the application which logged in the account.
+    #[serde(default)]
+    pub identity: Option<AppIdentity>,
+    /// Number of unread messages per label, once retrieved from the server.
+    #[serde(default)]
+    pub unread_counts: Option<HashMap<label::Id, u32>>,
+    /// User configured notification rules.
+    #[serde(default)]
+    pub notify_rules: NotifyRules,
+}
+
+/// User configured rules which decide which messages produce notifications, on top of the
+/// folders which have notifications enabled on the server.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NotifyRules {
+    /// Labels and system locations, such as [`label::Id::starred`], which always produce
+    /// notifications.
+    #[serde(default)]
+    pub include: HashSet<label::Id>,
+    /// Folders which never produce notifications, even if enabled on the server.
+    #[serde(default)]
+    pub exclude: HashSet<label::Id>,
+}
+
+impl NotifyRules {
+    fn remove(&mut self, id: &label::Id) {
+        self.include.remove(id);
+        self.exclude.remove(id);
+    }
 }
 
 impl Default for TaskState {
@@ -362,6 +868,11 @@ impl TaskState {
         Self {
             last_event_id: None,
             active_folder_ids: HashSet::from([label::Id::inbox()]),
+            base_url: None,
+            user_id: None,
+            identity: None,
+            unread_counts: None,
+            notify_rules: NotifyRules::default(),
         }
     }
 
@@ -371,6 +882,11 @@ impl TaskState {
         Self {
             last_event_id: Some(id),
             active_folder_ids: HashSet::from([label::Id::inbox()]),
+            base_url: None,
+            user_id: None,
+            identity: None,
+            unread_counts: None,
+            notify_rules: NotifyRules::default(),
         }
     }
 
@@ -379,9 +895,8 @@ impl TaskState {
             match event.action {
                 event::Action::Create => {
                     if let Some(label) = event.label {
-                        if label.label_type == label::Type::Folder && label.notify == Boolean::True
-                        {
-                            debug!("New folder: {} ({})", label.name, label.id);
+                        if is_custom_label(&label) && label.notify == Boolean::True {
+                            debug!("New label: {} ({})", label.name, label.id);
                             self.active_folder_ids.insert(label.id);
                         }
                     }
@@ -389,30 +904,66 @@ impl TaskState {
 
                 event::Action::Update | event::Action::UpdateFlags => {
                     if let Some(label) = event.label {
-                        if label.label_type != label::Type::Folder {
+                        if !is_custom_label(&label) {
                             continue;
                         }
                         if label.notify == Boolean::True {
-                            debug!("Folder {} ({}) became notifiable", label.name, label.id);
+                            debug!("Label {} ({}) became notifiable", label.name, label.id);
                             self.active_folder_ids.insert(label.id);
                         } else {
-                            debug!("Folder {} ({}) no longer notifiable", label.name, label.id);
+                            debug!("Label {} ({}) no longer notifiable", label.name, label.id);
                             self.active_folder_ids.remove(&label.id);
                         }
                     }
                 }
 
                 event::Action::Delete => {
-                    debug!("Folder {} deleted", event.id);
+                    debug!("Label {} deleted", event.id);
                     self.active_folder_ids.remove(&event.id);
+                    self.notify_rules.remove(&event.id);
                 }
             }
         }
     }
 
+    fn handle_message_counts(&mut self, counts: impl IntoIterator<Item = message::MessageCount>) {
+        // Events only contain the labels which changed, there is nothing to update until the
+        // full list has been retrieved.
+        let Some(unread_counts) = &mut self.unread_counts else {
+            return;
+        };
+        for count in counts {
+            unread_counts.insert(count.label_id, count.unread);
+        }
+    }
+
+    fn folder_unread_counts(&self) -> UnreadCounts {
+        let unread_counts = self.unread_counts.as_ref();
+        let folders = self
+            .active_folder_ids
+            .iter()
+            .filter(|id| !self.notify_rules.exclude.contains(id))
+            .chain(&self.notify_rules.include)
+            .map(|id| {
+                let unread = unread_counts
+                    .and_then(|counts| counts.get(id))
+                    .copied()
+                    .unwrap_or_default();
+                (id.0.clone(), unread)
+            })
+            .collect::<BTreeMap<_, _>>();
+        UnreadCounts {
+            total: folders.values().sum(),
+            folders,
+        }
+    }
+
     fn should_publish_notification(&self, label_list: &[label::Id]) -> bool {
         for id in label_list {
-            if self.active_folder_ids.contains(id) {
+            if self.notify_rules.include.contains(id) {
+                return true;
+            }
+            if self.active_folder_ids.contains(id) && !self.notify_rules.exclude.contains(id) {
                 return true;
             }
         }
@@ -421,6 +972,11 @@ impl TaskState {
     }
 }
 
+/// Whether `label` is a user created folder or label, which can have notifications enabled.
+fn is_custom_label(label: &label::Label) -> bool {
+    matches!(label.label_type, label::Type::Folder | label::Type::Label)
+}
+
 /// Actions which can be executed by the account.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum AccountAction {
@@ -430,6 +986,12 @@ pub enum AccountAction {
     MoveMessageToTrash(message::Id),
     /// Move a message to spm.
     MoveMessageToSpam(message::Id),
+    /// Mark all messages of a conversation as read.
+    MarkConversationRead(conversation::Id),
+    /// Move all messages of a conversation to trash.
+    MoveConversationToTrash(conversation::Id),
+    /// Move all messages of a conversation to spam.
+    MoveConversationToSpam(conversation::Id),
 }
 
 impl AccountAction {
@@ -456,7 +1018,10 @@ struct EventState {
 struct MessageInfo {
     id: message::Id,
     sender: String,
+    sender_address: String,
     subject: String,
+    labels: Vec<String>,
+    conversation_id: Option<conversation::Id>,
 }
 
 impl EventState {
@@ -493,11 +1058,12 @@ impl EventState {
                             self.new_emails.push(MessageInfo {
                                 id: message.id.clone(),
                                 subject: message.subject.clone(),
-                                sender: if let Some(name) = message.sender_name {
-                                    name
-                                } else {
-                                    message.sender_address
-                                },
+                                sender: message
+                                    .sender_name
+                                    .unwrap_or_else(|| message.sender_address.clone()),
+                                sender_address: message.sender_address,
+                                labels: message.labels.iter().map(ToString::to_string).collect(),
+                                conversation_id: message.conversation_id,
                             });
                             self.unseen.insert(message.id.clone());
                         } else {
@@ -526,32 +1092,33 @@ impl EventState {
         }
     }
 
+    /// Convert the unseen messages into new emails, one for each message. Messages of the same
+    /// conversation are only collapsed once the notification rules have been applied, see
+    /// [`crate::backend::Poller::collapse`].
     fn into_new_email_reply(self) -> Vec<NewEmail> {
-        if self.unseen.is_empty() {
-            return Vec::default();
-        }
-
-        let mut result = Vec::with_capacity(self.unseen.len());
-
-        for msg in self.new_emails {
-            if self.unseen.contains(&msg.id) {
-                result.push(NewEmail {
-                    sender: msg.sender,
-                    subject: msg.subject,
-                    move_to_trash_action: Some(
-                        AccountAction::MoveMessageToTrash(msg.id.clone()).to_action(),
-                    ),
-                    mark_as_read_action: Some(
-                        AccountAction::MarkMessageRead(msg.id.clone()).to_action(),
-                    ),
-                    move_to_spam_action: Some(
-                        AccountAction::MoveMessageToSpam(msg.id.clone()).to_action(),
-                    ),
-                });
-            }
-        }
+        self.new_emails
+            .into_iter()
+            .filter(|msg| self.unseen.contains(&msg.id))
+            .map(MessageInfo::into_new_email)
+            .collect()
+    }
+}
 
-        result
+impl MessageInfo {
+    fn into_new_email(self) -> NewEmail {
+        NewEmail {
+            sender: self.sender,
+            sender_address: self.sender_address,
+            subject: self.subject,
+            labels: self.labels,
+            thread_id: self.conversation_id.map(|id| id.to_string()),
+            message_count: 1,
+            move_to_trash_action: Some(
+                AccountAction::MoveMessageToTrash(self.id.clone()).to_action(),
+            ),
+            mark_as_read_action: Some(AccountAction::MarkMessageRead(self.id.clone()).to_action()),
+            move_to_spam_action: Some(AccountAction::MoveMessageToSpam(self.id).to_action()),
+        }
     }
 }
 
@@ -562,24 +1129,19 @@ impl From<EventState> for Vec<NewEmail> {
 }
 
 impl IntoAccount for Sequence {
+    /// Register the logged in account.
+    ///
+    /// Accounts in two password mode are only logged in once the mailbox password has been
+    /// submitted, see [`Sequence::is_awaiting_mailbox_password`]. Until then
+    /// [`crate::yhm::Error::LoginIncomplete`] is returned.
     #[tracing::instrument(level=Level::DEBUG, skip(self, yhm))]
     fn into_account(mut self, yhm: &Yhm) -> Result<(), crate::yhm::Error> {
-        let (user_info, session) = self
-            .finish()
-            .map_err(|_| crate::state::Error::Other(anyhow::anyhow!("Account is not logged in")))?;
-
-        let guard = session.auth_store().read();
-        let Some(auth) = guard.get().map_err(|e| {
-            error!("Failed to get auth data: {e}");
-            crate::state::Error::Other(anyhow::anyhow!("Failed to get authentication data: {e}"))
-        })?
-        else {
-            error!("No authentication data available");
-            return Err(crate::state::Error::Other(anyhow::anyhow!(
-                "No authentication data available"
-            ))
-            .into());
-        };
+        let (user_info, session) = self.finish().map_err(|e| {
+            error!("Login sequence is not complete: {e}");
+            crate::yhm::Error::LoginIncomplete
+        })?;
+
+        let auth = session_auth(&session)?;
 
         let account = yhm.new_account(&user_info.email, NAME)?;
 
@@ -591,6 +1153,16 @@ impl IntoAccount for Sequence {
             error!("Failed to set proxy on account: {e}");
             e
         })?;
+        let state = TaskState {
+            base_url: session.alternative_base_url().map(Into::into),
+            user_id: Some(user_info.id.to_string()),
+            identity: Some(session.identity().clone()),
+            ..TaskState::new()
+        };
+        account.set_state(Some(&state)).map_err(|e| {
+            error!("Failed to set state on account: {e}");
+            e
+        })?;
 
         // This snippet is to patch ported accounts from v1 which do not have the full
         // email address. E.g: It was possible to register an account "foo@proton.me" only
@@ -619,6 +1191,126 @@ impl IntoAccount for Sequence {
     }
 }
 
+/// Login sequence to re-authenticate an existing account, see [`Yhm::relogin`].
+///
+/// Use [`Self::sequence_mut`] to complete the login and then convert it with
+/// [`Relogin::into_account`] to update the account.
+pub struct ReloginSequence {
+    email: String,
+    sequence: Sequence,
+}
+
+impl ReloginSequence {
+    /// Create a new sequence for the account with `email` which executes requests with
+    /// `session`.
+    #[must_use]
+    pub fn new(session: Session, email: impl Into<String>) -> Self {
+        Self {
+            email: email.into(),
+            sequence: Sequence::new(session),
+        }
+    }
+
+    /// Get the underlying login sequence.
+    #[must_use]
+    pub fn sequence(&self) -> &Sequence {
+        &self.sequence
+    }
+
+    /// Get the underlying login sequence to drive the login process.
+    pub fn sequence_mut(&mut self) -> &mut Sequence {
+        &mut self.sequence
+    }
+}
+
+impl Relogin for ReloginSequence {
+    fn email(&self) -> &str {
+        &self.email
+    }
+
+    fn into_account(self: Box<Self>, yhm: &Yhm) -> Result<(), crate::yhm::Error> {
+        IntoAccount::into_account(*self, yhm)
+    }
+}
+
+impl IntoAccount for ReloginSequence {
+    /// Store the new authentication data on the existing account.
+    ///
+    /// The account's state, proxy and events are preserved. If the logged in user is not the
+    /// account's user, [`crate::yhm::Error::UserMismatch`] is returned.
+    #[tracing::instrument(level=Level::DEBUG, skip(self, yhm))]
+    fn into_account(mut self, yhm: &Yhm) -> Result<(), crate::yhm::Error> {
+        let (user_info, session) = self.sequence.finish().map_err(|e| {
+            error!("Login sequence is not complete: {e}");
+            crate::yhm::Error::LoginIncomplete
+        })?;
+
+        let account = yhm
+            .account(&self.email)?
+            .ok_or_else(|| crate::yhm::Error::AccountNotFound(self.email.clone()))?;
+
+        let mut state = account
+            .state::<TaskState>()
+            .map_err(|e| {
+                error!("Failed to load state: {e}");
+                e
+            })?
+            .unwrap_or_default();
+
+        // Accounts created before the user id was recorded can only be matched by email.
+        let same_user = match &state.user_id {
+            Some(user_id) => *user_id == user_info.id.as_ref(),
+            None => {
+                user_info.email.eq_ignore_ascii_case(&self.email)
+                    || user_info.name.eq_ignore_ascii_case(&self.email)
+            }
+        };
+        if !same_user {
+            error!("Logged in user does not match the account's user");
+            if let Err(e) = session.logout() {
+                error!("Failed to logout session: {e}");
+            }
+            return Err(crate::yhm::Error::UserMismatch(self.email));
+        }
+
+        let auth = session_auth(&session)?;
+        account.set_secret(Some(&auth)).map_err(|e| {
+            error!("Failed to set secret on account: {e}");
+            e
+        })?;
+
+        state.user_id = Some(user_info.id.to_string());
+        state.identity = Some(session.identity().clone());
+        if let Some(base_url) = session.alternative_base_url() {
+            state.base_url = Some(base_url.into());
+        }
+        account.set_state(Some(&state)).map_err(|e| {
+            error!("Failed to set state on account: {e}");
+            e
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Get the authentication data of a logged in `session`.
+fn session_auth(session: &Session) -> Result<ProtonAuth, crate::yhm::Error> {
+    let guard = session.auth_store().read();
+    let Some(auth) = guard.get().map_err(|e| {
+        error!("Failed to get auth data: {e}");
+        crate::state::Error::Other(anyhow::anyhow!("Failed to get authentication data: {e}"))
+    })?
+    else {
+        error!("No authentication data available");
+        return Err(crate::state::Error::Other(anyhow::anyhow!(
+            "No authentication data available"
+        ))
+        .into());
+    };
+
+    Ok(auth.clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -688,6 +1380,72 @@ mod tests {
         assert_eq!(new_emails[1].subject, SUBJECT);
     }
 
+    #[test]
+    fn event_state_collapse_messages_in_same_conversation() {
+        let task_state = TaskState::new();
+        let mut evt_state = EventState::new();
+
+        let conversation_id = conversation::Id("conversation".to_owned());
+        let message = |id: &str, conversation_id: Option<&conversation::Id>| {
+            let mut message =
+                new_message_event_data_with_id(message::Id(id.to_owned()), true, false, None);
+            message.subject = id.to_owned();
+            message.conversation_id = conversation_id.cloned();
+            event::Message {
+                id: message.id.clone(),
+                action: Action::Create,
+                message: Some(message),
+            }
+        };
+
+        let event = [
+            message("first", Some(&conversation_id)),
+            message("standalone", None),
+            message("second", Some(&conversation_id)),
+            message("third", Some(&conversation_id)),
+        ];
+        evt_state.handle_message_events(event, &task_state);
+
+        // Most recent message has been read by another client.
+        evt_state.handle_message_events(
+            [event::Message {
+                id: message::Id("third".to_owned()),
+                action: Action::Update,
+                message: Some(new_message_event_data_with_id(
+                    message::Id("third".to_owned()),
+                    false,
+                    false,
+                    None,
+                )),
+            }],
+            &task_state,
+        );
+
+        let new_emails = collapse_conversations(evt_state.into_new_email_reply());
+        assert_eq!(new_emails.len(), 2);
+        assert_eq!(new_emails[0].subject, "second");
+        assert_eq!(new_emails[0].thread_id, Some(conversation_id.to_string()));
+        assert_eq!(new_emails[0].message_count, 2);
+        assert_eq!(
+            new_emails[0].mark_as_read_action,
+            Some(AccountAction::MarkConversationRead(conversation_id.clone()).to_action())
+        );
+        assert_eq!(
+            new_emails[0].move_to_trash_action,
+            Some(AccountAction::MoveConversationToTrash(conversation_id.clone()).to_action())
+        );
+        assert_eq!(
+            new_emails[0].move_to_spam_action,
+            Some(AccountAction::MoveConversationToSpam(conversation_id).to_action())
+        );
+        assert_eq!(new_emails[1].subject, "standalone");
+        assert_eq!(new_emails[1].thread_id, None);
+        assert_eq!(
+            new_emails[1].mark_as_read_action,
+            Some(AccountAction::MarkMessageRead(message::Id("standalone".to_owned())).to_action())
+        );
+    }
+
     #[test]
     fn event_state_notify_unread_in_custom_folder() {
         let custom_folder_id = label::Id("folder".into());
@@ -700,13 +1458,23 @@ mod tests {
         let event = [event::Message {
             id: message_id(),
             action: Action::Create,
-            message: Some(new_message_event_data(true, false, Some(custom_folder_id))),
+            message: Some(new_message_event_data(
+                true,
+                false,
+                Some(custom_folder_id.clone()),
+            )),
         }];
 
         evt_state.handle_message_events(event, &task_state);
         assert_eq!(evt_state.unseen.len(), 1);
         assert!(evt_state.unseen.contains(&message_id()));
-        assert_eq!(evt_state.new_emails[0], message_info(false));
+        assert_eq!(
+            evt_state.new_emails[0],
+            MessageInfo {
+                labels: vec![custom_folder_id.to_string()],
+                ..message_info(false)
+            }
+        );
 
         let new_emails = evt_state.into_new_email_reply();
         assert_eq!(new_emails.len(), 1);
@@ -811,7 +1579,7 @@ mod tests {
         // Add 3 new labels
         // Folder with notifications
         // Folder without notifications
-        // Label
+        // Label with notifications
 
         let other_folder_id = label::Id("folder_without_notify".to_owned());
         let other_label_id = label::Id("label_with_notify".to_owned());
@@ -842,11 +1610,11 @@ mod tests {
         ];
 
         state.handle_label_events(events);
-        assert_eq!(state.active_folder_ids.len(), 2);
+        assert_eq!(state.active_folder_ids.len(), 3);
         assert!(state.active_folder_ids.contains(&label::Id::inbox()));
         assert!(state.active_folder_ids.contains(&label_id()));
         assert!(!state.active_folder_ids.contains(&other_folder_id));
-        assert!(!state.active_folder_ids.contains(&other_label_id));
+        assert!(state.active_folder_ids.contains(&other_label_id));
     }
 
     #[test]
@@ -856,7 +1624,7 @@ mod tests {
         // 3 updates
         // remove notification from default folder
         // change notification for disable folder
-        // Label
+        // enable notification for label
 
         let other_folder_id = label::Id("folder_without_notify".to_owned());
         let other_label_id = label::Id("label_with_notify".to_owned());
@@ -887,11 +1655,11 @@ mod tests {
         ];
 
         state.handle_label_events(events);
-        assert_eq!(state.active_folder_ids.len(), 2);
+        assert_eq!(state.active_folder_ids.len(), 3);
         assert!(state.active_folder_ids.contains(&label::Id::inbox()));
         assert!(!state.active_folder_ids.contains(&label_id()));
         assert!(state.active_folder_ids.contains(&other_folder_id));
-        assert!(!state.active_folder_ids.contains(&other_label_id));
+        assert!(state.active_folder_ids.contains(&other_label_id));
     }
 
     #[test]
@@ -917,6 +1685,81 @@ mod tests {
         assert!(!state.active_folder_ids.contains(&label_id()));
     }
 
+    #[test]
+    fn poll_state_label_event_delete_removes_notify_rules() {
+        let mut state = TaskState::new();
+        state.notify_rules.include.insert(label_id());
+
+        state.handle_label_events([event::Label {
+            id: label_id(),
+            action: Action::Delete,
+            label: None,
+        }]);
+        assert!(state.notify_rules.include.is_empty());
+    }
+
+    #[test]
+    fn unread_counts_include_label_rules() {
+        let mut state = TaskState::new();
+        state.notify_rules.include.insert(label::Id::starred());
+        state.unread_counts = Some(HashMap::from([
+            (label::Id::inbox(), 3),
+            (label::Id::starred(), 2),
+            (label::Id::spam(), 7),
+        ]));
+
+        let counts = state.folder_unread_counts();
+        assert_eq!(counts.total, 5);
+        assert_eq!(counts.folders.len(), 2);
+        assert_eq!(counts.folders[&label::Id::starred().0], 2);
+    }
+
+    #[test]
+    fn event_state_notify_unread_with_label_rule() {
+        let mut task_state = TaskState::new();
+        task_state.notify_rules.include.insert(label::Id::starred());
+        let mut evt_state = EventState::new();
+
+        let mut message = new_message_event_data(true, false, Some(label::Id::archive()));
+        message.labels.push(label::Id::starred());
+        let event = [event::Message {
+            id: message_id(),
+            action: Action::Create,
+            message: Some(message),
+        }];
+
+        evt_state.handle_message_events(event, &task_state);
+        assert_eq!(evt_state.unseen.len(), 1);
+        assert!(evt_state.unseen.contains(&message_id()));
+    }
+
+    #[test]
+    fn event_state_skip_unread_in_excluded_folder() {
+        let mut task_state = TaskState::new();
+        task_state.notify_rules.exclude.insert(label::Id::inbox());
+        let mut evt_state = EventState::new();
+
+        let event = [event::Message {
+            id: message_id(),
+            action: Action::Create,
+            message: Some(new_message_event_data(true, false, None)),
+        }];
+
+        evt_state.handle_message_events(event, &task_state);
+        assert!(evt_state.unseen.is_empty());
+        assert!(evt_state.into_new_email_reply().is_empty());
+    }
+
+    #[test]
+    fn task_state_without_base_url_deserializes() {
+        let state = serde_json::from_str::<TaskState>(
+            r#"{"last_event_id":"event","active_folder_ids":["0"]}"#,
+        )
+        .unwrap();
+        assert_eq!(state.last_event_id, Some(event::Id("event".to_owned())));
+        assert!(state.base_url.is_none());
+    }
+
     fn new_message_event_data(
         unread: bool,
         with_name: bool,
@@ -945,6 +1788,8 @@ mod tests {
             } else {
                 Boolean::False
             },
+            conversation_id: None,
+            time: 0,
         }
     }
 
@@ -960,7 +1805,10 @@ mod tests {
             } else {
                 SENDER_ADDRESS.to_owned()
             },
+            sender_address: SENDER_ADDRESS.to_owned(),
             subject: SUBJECT.to_string(),
+            labels: vec![label::Id::inbox().to_string()],
+            conversation_id: None,
         }
     }
 
@@ -5,6 +5,7 @@ use sqlite_watcher::connection::Connection as WatchedConnection;
 use sqlite_watcher::watcher::Watcher;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Database pool which maintains a small amount of open connections.
 pub struct Pool {
@@ -15,6 +16,7 @@ pub struct Pool {
 }
 
 const MAX_DB_CONNECTIONS: usize = 4;
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
 
 impl Pool {
     /// Create new instance for a database at `path`.
@@ -104,6 +106,9 @@ impl Pool {
         conn.pragma_update(None, "journal", "WAL")?;
         conn.pragma_update(None, "synchronous", "FULL")?;
         conn.pragma_update(None, "temp_store", "MEMORY")?;
+        // Writes can be delayed by a session refresh in another process, which holds the
+        // write lock while waiting on the server.
+        conn.busy_timeout(BUSY_TIMEOUT)?;
         WatchedConnection::new(conn, Arc::clone(&self.watcher))
     }
 }
@@ -1,4 +1,5 @@
-use crate::backend::{Error, NewEmail};
+use crate::backend::{Error, LogoutReason, NewEmail};
+use crate::grouping::EmailGroup;
 use crate::yhm::PollOutput;
 use rusqlite::ToSql;
 use rusqlite::types::{FromSql, FromSqlResult, ToSqlOutput, Value, ValueRef};
@@ -13,9 +14,26 @@ pub enum Event {
         email: String,
         backend: String,
         emails: Vec<NewEmail>,
+        /// Number of new emails which did not produce a notification due to the account's
+        /// rules.
+        #[serde(default)]
+        suppressed: usize,
+        /// Groups of `emails` which should be presented as a single notification.
+        #[serde(default)]
+        groups: Vec<EmailGroup>,
+    },
+    /// Emails which arrived during quiet hours, delivered once the quiet hours end. Also
+    /// contains any emails which arrived since the last poll.
+    Digest {
+        email: String,
+        backend: String,
+        emails: Vec<NewEmail>,
+        /// Groups of `emails` which should be presented as a single notification.
+        #[serde(default)]
+        groups: Vec<EmailGroup>,
     },
     /// Account has been logged out.
-    LoggedOut(String),
+    LoggedOut { email: String, reason: LogoutReason },
     /// Account servers are not reachable.
     Offline(String),
     /// General error occurred.
@@ -27,7 +45,8 @@ impl Event {
     pub fn email(&self) -> &str {
         match self {
             Event::NewEmail { email, .. }
-            | Event::LoggedOut(email)
+            | Event::Digest { email, .. }
+            | Event::LoggedOut { email, .. }
             | Event::Offline(email)
             | Event::Error(email, _) => email.as_str(),
         }
@@ -37,10 +56,18 @@ impl Event {
 impl Event {
     pub(crate) fn new(value: &PollOutput) -> Self {
         match &value.result {
+            Ok(new_email) if value.digest => Self::Digest {
+                email: value.email.clone(),
+                backend: value.backend.clone(),
+                emails: new_email.clone(),
+                groups: value.groups.clone(),
+            },
             Ok(new_email) => Self::NewEmail {
                 email: value.email.clone(),
                 backend: value.backend.clone(),
                 emails: new_email.clone(),
+                suppressed: value.suppressed,
+                groups: value.groups.clone(),
             },
             Err(e) => match e {
                 Error::Http(http_err) => {
@@ -49,7 +76,10 @@ impl Event {
                     }
                     Self::Error(value.email.clone(), e.to_string())
                 }
-                Error::SessionExpired => Self::LoggedOut(value.email.clone()),
+                Error::SessionExpired(reason) => Self::LoggedOut {
+                    email: value.email.clone(),
+                    reason: *reason,
+                },
                 err => Self::Error(value.email.clone(), err.to_string()),
             },
         }
@@ -68,10 +98,26 @@ impl ToSql for Event {
 impl FromSql for Event {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
         let value = value.as_str()?;
-        let event = serde_json::from_str(value).map_err(|e| {
-            error!("Failed to deserialize event: {e}");
-            rusqlite::types::FromSqlError::InvalidType
-        })?;
+        let event = serde_json::from_str(value)
+            .or_else(|e| {
+                // Events recorded before the logout reason was introduced.
+                let Ok(LegacyEvent::LoggedOut(email)) = serde_json::from_str(value) else {
+                    return Err(e);
+                };
+                Ok(Event::LoggedOut {
+                    email,
+                    reason: LogoutReason::RefreshFailed,
+                })
+            })
+            .map_err(|e| {
+                error!("Failed to deserialize event: {e}");
+                rusqlite::types::FromSqlError::InvalidType
+            })?;
         Ok(event)
     }
 }
+
+#[derive(Deserialize)]
+enum LegacyEvent {
+    LoggedOut(String),
+}
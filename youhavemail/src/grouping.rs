@@ -0,0 +1,331 @@
+//! Group new emails by sender or thread so that many emails arriving at once can be presented
+//! as a few summary notifications.
+//!
+//! Groups are computed by [`crate::yhm::Yhm::poll`] with the configured [`Grouping`] settings
+//! and are available in [`PollOutput`](crate::yhm::PollOutput) and the
+//! [`Event`](crate::events::Event).
+//!
+//! ```rust
+//! use you_have_mail_common::backend::NewEmail;
+//! use you_have_mail_common::grouping::{GroupBy, Grouping};
+//!
+//! let email = |subject: &str| NewEmail::new("GitHub", "noreply@github.com", subject);
+//!
+//! let grouping = Grouping {
+//!     group_by: GroupBy::Sender,
+//!     min_group_size: 2,
+//!     collapse_all_at: None,
+//! };
+//!
+//! let groups = grouping.group(&[email("PR opened"), email("PR merged")]);
+//! assert_eq!(groups[0].summary, "2 new messages from GitHub");
+//! ```
+
+use crate::backend::NewEmail;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Smallest allowed [`Grouping::min_group_size`].
+pub const MIN_GROUP_SIZE: usize = 2;
+
+/// Errors returned when grouping settings are not valid.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Minimum group size {0} is smaller than {MIN_GROUP_SIZE}")]
+    InvalidMinGroupSize(usize),
+    #[error("Collapse threshold {0} is smaller than {MIN_GROUP_SIZE}")]
+    InvalidCollapseAllAt(usize),
+}
+
+/// How emails are grouped.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum GroupBy {
+    /// Group emails from the same sender address.
+    Sender,
+    /// Group emails from the same thread or conversation.
+    Thread,
+}
+
+/// Grouping settings.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Grouping {
+    /// How emails are grouped.
+    pub group_by: GroupBy,
+    /// Minimum number of messages with the same sender or thread to form a group, see
+    /// [`NewEmail::message_count`]. Must be at least [`MIN_GROUP_SIZE`].
+    pub min_group_size: usize,
+    /// If set, all emails are collapsed into a single group once the number of messages
+    /// reaches this value. Must be at least [`MIN_GROUP_SIZE`].
+    pub collapse_all_at: Option<usize>,
+}
+
+impl Default for Grouping {
+    fn default() -> Self {
+        Self {
+            group_by: GroupBy::Sender,
+            min_group_size: 3,
+            collapse_all_at: None,
+        }
+    }
+}
+
+/// Group of emails which should be presented as a single notification.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EmailGroup {
+    /// Sender or subject of the thread the emails are grouped by. Empty if all emails were
+    /// collapsed.
+    pub title: String,
+    /// Summary text, e.g. "5 new messages from GitHub".
+    pub summary: String,
+    /// Indices of the emails in the group.
+    pub emails: Vec<usize>,
+}
+
+impl Grouping {
+    /// Check whether the settings are valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if `min_group_size` or `collapse_all_at` is smaller than
+    /// [`MIN_GROUP_SIZE`].
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.min_group_size < MIN_GROUP_SIZE {
+            return Err(Error::InvalidMinGroupSize(self.min_group_size));
+        }
+        if let Some(limit) = self.collapse_all_at.filter(|limit| *limit < MIN_GROUP_SIZE) {
+            return Err(Error::InvalidCollapseAllAt(limit));
+        }
+        Ok(())
+    }
+
+    /// Group `emails`. Emails which are not part of any group should be presented
+    /// individually.
+    #[must_use]
+    pub fn group(&self, emails: &[NewEmail]) -> Vec<EmailGroup> {
+        if emails.is_empty() {
+            return Vec::new();
+        }
+
+        let total = message_count(emails.iter());
+        if self.collapse_all_at.is_some_and(|limit| total >= limit) {
+            return vec![EmailGroup {
+                title: String::new(),
+                summary: format!("{total} new messages"),
+                emails: (0..emails.len()).collect(),
+            }];
+        }
+
+        // Groups are kept in order of first appearance.
+        let mut groups: Vec<(Cow<'_, str>, Vec<usize>)> = Vec::new();
+        for (index, email) in emails.iter().enumerate() {
+            let Some(key) = self.key(email) else {
+                continue;
+            };
+
+            if let Some((_, indices)) = groups.iter_mut().find(|(k, _)| *k == key) {
+                indices.push(index);
+            } else {
+                groups.push((key, vec![index]));
+            }
+        }
+
+        groups
+            .into_iter()
+            .filter_map(|(_, indices)| {
+                let count = message_count(indices.iter().map(|index| &emails[*index]));
+                (count >= self.min_group_size).then_some((count, indices))
+            })
+            .map(|(count, indices)| {
+                let first = &emails[indices[0]];
+                let (title, summary) = match self.group_by {
+                    GroupBy::Sender => (
+                        first.sender.clone(),
+                        format!("{count} new messages from {}", first.sender),
+                    ),
+                    GroupBy::Thread => (
+                        first.subject.clone(),
+                        format!("{count} new messages in \"{}\"", first.subject),
+                    ),
+                };
+                EmailGroup {
+                    title,
+                    summary,
+                    emails: indices,
+                }
+            })
+            .collect()
+    }
+
+    /// Key of the group of `email`. Email addresses are not case sensitive.
+    fn key<'a>(&self, email: &'a NewEmail) -> Option<Cow<'a, str>> {
+        match self.group_by {
+            GroupBy::Sender if email.sender_address.is_empty() => {
+                Some(Cow::Borrowed(&email.sender))
+            }
+            GroupBy::Sender => Some(Cow::Owned(email.sender_address.to_lowercase())),
+            GroupBy::Thread => email.thread_id.as_deref().map(Cow::Borrowed),
+        }
+    }
+}
+
+/// Total number of messages represented by `emails`.
+fn message_count<'a>(emails: impl Iterator<Item = &'a NewEmail>) -> usize {
+    emails.map(|email| email.message_count).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(sender: &str, subject: &str, thread_id: Option<&str>) -> NewEmail {
+        NewEmail {
+            thread_id: thread_id.map(ToOwned::to_owned),
+            ..NewEmail::new(
+                sender,
+                format!("{}@example.com", sender.to_lowercase()),
+                subject,
+            )
+        }
+    }
+
+    #[test]
+    fn group_by_sender() {
+        let grouping = Grouping {
+            group_by: GroupBy::Sender,
+            min_group_size: 2,
+            collapse_all_at: None,
+        };
+        let emails = [
+            email("GitHub", "A", None),
+            email("Alice", "B", None),
+            email("GitHub", "C", None),
+            email("Bob", "D", None),
+            email("GitHub", "E", None),
+            // Addresses are compared case insensitively.
+            NewEmail {
+                sender_address: "BOB@example.com".to_owned(),
+                ..email("Bob", "F", None)
+            },
+        ];
+
+        let groups = grouping.group(&emails);
+        assert_eq!(
+            groups,
+            vec![
+                EmailGroup {
+                    title: "GitHub".to_owned(),
+                    summary: "3 new messages from GitHub".to_owned(),
+                    emails: vec![0, 2, 4],
+                },
+                EmailGroup {
+                    title: "Bob".to_owned(),
+                    summary: "2 new messages from Bob".to_owned(),
+                    emails: vec![3, 5],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn group_by_thread() {
+        let grouping = Grouping {
+            group_by: GroupBy::Thread,
+            min_group_size: 2,
+            collapse_all_at: None,
+        };
+        let emails = [
+            email("Alice", "Lunch?", Some("t1")),
+            email("Bob", "Re: Lunch?", Some("t1")),
+            email("Carol", "Report", Some("t2")),
+            email("Dave", "Hi", None),
+            email("Eve", "Hello", None),
+        ];
+
+        let groups = grouping.group(&emails);
+        assert_eq!(
+            groups,
+            vec![EmailGroup {
+                title: "Lunch?".to_owned(),
+                summary: "2 new messages in \"Lunch?\"".to_owned(),
+                emails: vec![0, 1],
+            }]
+        );
+    }
+
+    #[test]
+    fn below_threshold_not_grouped() {
+        let grouping = Grouping::default();
+        let emails = [email("GitHub", "A", None), email("GitHub", "B", None)];
+        assert!(grouping.group(&emails).is_empty());
+    }
+
+    #[test]
+    fn collapsed_thread_counts_messages() {
+        let grouping = Grouping {
+            group_by: GroupBy::Thread,
+            ..Grouping::default()
+        };
+        let emails = [
+            NewEmail {
+                message_count: 3,
+                ..email("Alice", "Lunch?", Some("t1"))
+            },
+            email("Bob", "Report", Some("t2")),
+        ];
+
+        assert_eq!(
+            grouping.group(&emails),
+            vec![EmailGroup {
+                title: "Lunch?".to_owned(),
+                summary: "3 new messages in \"Lunch?\"".to_owned(),
+                emails: vec![0],
+            }]
+        );
+    }
+
+    #[test]
+    fn min_group_size_validation() {
+        let grouping = |min_group_size| Grouping {
+            min_group_size,
+            ..Grouping::default()
+        };
+        assert!(grouping(MIN_GROUP_SIZE).validate().is_ok());
+        assert!(matches!(
+            grouping(1).validate(),
+            Err(Error::InvalidMinGroupSize(1))
+        ));
+
+        let collapse_all_at = |collapse_all_at| Grouping {
+            collapse_all_at: Some(collapse_all_at),
+            ..Grouping::default()
+        };
+        assert!(collapse_all_at(MIN_GROUP_SIZE).validate().is_ok());
+        assert!(matches!(
+            collapse_all_at(1).validate(),
+            Err(Error::InvalidCollapseAllAt(1))
+        ));
+    }
+
+    #[test]
+    fn collapse_all() {
+        let grouping = Grouping {
+            collapse_all_at: Some(3),
+            ..Grouping::default()
+        };
+        let emails = [
+            email("Alice", "A", None),
+            email("Bob", "B", None),
+            email("Carol", "C", None),
+        ];
+
+        assert_eq!(
+            grouping.group(&emails),
+            vec![EmailGroup {
+                title: String::new(),
+                summary: "3 new messages".to_owned(),
+                emails: vec![0, 1, 2],
+            }]
+        );
+        assert!(grouping.group(&emails[..2]).is_empty());
+    }
+}
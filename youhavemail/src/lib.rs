@@ -93,8 +93,12 @@
 
 pub mod backend;
 pub mod encryption;
+pub mod grouping;
+pub mod notify;
 //mod observer;
 pub mod db;
+pub mod rules;
+pub mod schedule;
 pub mod state;
 pub mod yhm;
 
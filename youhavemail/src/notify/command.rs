@@ -0,0 +1,120 @@
+//! Local command sink.
+
+use crate::notify::{Error, Notification, NotificationSink, Result};
+use std::ffi::OsString;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Time after which the command is killed, see [`CommandSink::timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval at which a running command is checked for completion.
+const WAIT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Run a command for every notification.
+///
+/// The notification is passed to the command with the following environment variables:
+///
+/// * `YHM_TITLE`: [`Notification::title`].
+/// * `YHM_BODY`: [`Notification::body`].
+/// * `YHM_EMAIL`: Email of the account.
+/// * `YHM_BACKEND`: Backend of the account, empty if not known.
+/// * `YHM_COUNT`: Number of new emails.
+/// * `YHM_EVENT`: The [`Event`](crate::events::Event) as JSON.
+///
+/// The notification fails if the command exits with a non-zero status. Sinks are run as part
+/// of the poll, so the command is killed if it does not finish within the timeout.
+pub struct CommandSink {
+    program: OsString,
+    args: Vec<OsString>,
+    timeout: Duration,
+}
+
+impl CommandSink {
+    /// Create a new sink which runs `program`.
+    #[must_use]
+    pub fn new(program: impl Into<OsString>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Pass `arg` to the program.
+    #[must_use]
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Kill the program if it does not finish within `timeout`. Defaults to
+    /// [`DEFAULT_TIMEOUT`].
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl NotificationSink for CommandSink {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn notify(&self, notification: &Notification<'_>) -> Result<()> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .env("YHM_TITLE", &notification.title)
+            .env("YHM_BODY", &notification.body)
+            .env("YHM_EMAIL", notification.email())
+            .env("YHM_BACKEND", notification.backend().unwrap_or_default())
+            .env("YHM_COUNT", notification.email_count().to_string())
+            .env(
+                "YHM_EVENT",
+                serde_json::to_string(notification.event).unwrap_or_default(),
+            )
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Read stderr concurrently so that the command can't block on a full pipe.
+        let stderr = child.stderr.take().map(|mut stderr| {
+            std::thread::spawn(move || {
+                let mut output = Vec::new();
+                let _ = stderr.read_to_end(&mut output);
+                output
+            })
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                return Err(Error::Command(format!(
+                    "timed out after {}s",
+                    self.timeout.as_secs_f32()
+                )));
+            }
+            std::thread::sleep(WAIT_INTERVAL);
+        };
+
+        if !status.success() {
+            let stderr = stderr
+                .and_then(|stderr| stderr.join().ok())
+                .unwrap_or_default();
+            return Err(Error::Command(format!(
+                "{status}: {}",
+                String::from_utf8_lossy(&stderr).trim()
+            )));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+//! [Matrix](https://matrix.org) room sink.
+
+use crate::notify::request::{PutRequest, new_client};
+use crate::notify::{Notification, NotificationSink, Result};
+use http::url::{Url, form_urlencoded};
+use http::{Client, Proxy};
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Post notifications as text messages to a Matrix room.
+///
+/// The user of the access token must already have joined the room.
+pub struct MatrixSink {
+    client: Arc<Client>,
+    access_token: String,
+    room_id: String,
+    txn_prefix: u128,
+    txn_counter: AtomicU64,
+}
+
+#[derive(Serialize)]
+struct TextMessage<'a> {
+    msgtype: &'static str,
+    body: &'a str,
+}
+
+impl MatrixSink {
+    /// Create a new sink which posts to the room `room_id` on the homeserver at
+    /// `homeserver_url` with `access_token`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the http client could not be created.
+    pub fn new(
+        homeserver_url: Url,
+        proxy: Option<Proxy>,
+        access_token: impl Into<String>,
+        room_id: impl Into<String>,
+    ) -> http::Result<Self> {
+        Ok(Self {
+            client: new_client(homeserver_url, proxy)?,
+            access_token: access_token.into(),
+            room_id: room_id.into(),
+            txn_prefix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+            txn_counter: AtomicU64::new(0),
+        })
+    }
+
+    /// Unique transaction id so that the homeserver can detect retransmissions.
+    fn next_txn_id(&self) -> String {
+        let counter = self.txn_counter.fetch_add(1, Ordering::Relaxed);
+        format!("yhm{}-{counter}", self.txn_prefix)
+    }
+}
+
+impl NotificationSink for MatrixSink {
+    fn name(&self) -> &'static str {
+        "matrix"
+    }
+
+    fn notify(&self, notification: &Notification<'_>) -> Result<()> {
+        let body = format!("{}\n{}", notification.title, notification.body);
+        let message = TextMessage {
+            msgtype: "m.text",
+            body: body.trim_end(),
+        };
+        let room_id = form_urlencoded::byte_serialize(self.room_id.as_bytes()).collect::<String>();
+
+        self.client.execute(&PutRequest {
+            url: format!(
+                "_matrix/client/v3/rooms/{room_id}/send/m.room.message/{}",
+                self.next_txn_id()
+            ),
+            headers: vec![
+                ("Authorization", format!("Bearer {}", self.access_token)),
+                ("Content-Type", "application/json".to_owned()),
+            ],
+            body: serde_json::to_vec(&message).expect("Serialization should never fail"),
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+//! Notification sinks which deliver the results of [`Yhm::poll`](crate::yhm::Yhm::poll) to
+//! external services.
+//!
+//! Sinks are registered with [`Yhm::add_sink`](crate::yhm::Yhm::add_sink) either for all
+//! accounts or for a single account. After every poll, each [`Event`] which should be brought
+//! to the user's attention is converted into a [`Notification`] and handed to the matching
+//! sinks. Sink failures are logged and do not affect the poll result.
+//!
+//! The following sinks are available, each behind a cargo feature of the same name. All but
+//! `sink-command` are enabled by default:
+//!
+//! * `sink-webhook`: [`webhook::WebhookSink`] posts a JSON template to an HTTP endpoint.
+//! * `sink-push`: [`push::NtfySink`], [`push::GotifySink`] and [`push::UnifiedPushSink`].
+//! * `sink-matrix`: [`matrix::MatrixSink`] posts a message to a Matrix room.
+//! * `sink-command`: [`command::CommandSink`] runs a local command.
+//!
+//! Custom sinks can be added by implementing [`NotificationSink`].
+
+#[cfg(feature = "sink-command")]
+pub mod command;
+#[cfg(feature = "sink-matrix")]
+pub mod matrix;
+#[cfg(feature = "sink-push")]
+pub mod push;
+#[cfg(feature = "sink-webhook")]
+pub mod webhook;
+
+#[cfg(any(
+    feature = "sink-webhook",
+    feature = "sink-push",
+    feature = "sink-matrix"
+))]
+mod request;
+
+use crate::backend::NewEmail;
+use crate::events::Event;
+use crate::grouping::EmailGroup;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Http: {0}")]
+    Http(#[from] http::Error),
+    #[error("IO: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Command failed: {0}")]
+    Command(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Destination for notifications.
+pub trait NotificationSink: Send + Sync {
+    /// Name of the sink, used for logging.
+    fn name(&self) -> &str;
+
+    /// Deliver the `notification`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the notification could not be delivered.
+    fn notify(&self, notification: &Notification<'_>) -> Result<()>;
+}
+
+/// Accounts a sink receives notifications for.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SinkScope {
+    /// All accounts.
+    Global,
+    /// Only the account with the given email.
+    Account(String),
+}
+
+impl SinkScope {
+    /// Whether the scope includes the account with `email`.
+    #[must_use]
+    pub fn contains(&self, email: &str) -> bool {
+        match self {
+            SinkScope::Global => true,
+            SinkScope::Account(account) => account == email,
+        }
+    }
+}
+
+/// Notification produced from an [`Event`].
+#[derive(Debug)]
+pub struct Notification<'a> {
+    /// Event which produced the notification.
+    pub event: &'a Event,
+    /// Short summary, e.g. "2 new emails for foo@bar.com".
+    pub title: String,
+    /// Details with one line per email or group of emails.
+    pub body: String,
+}
+
+impl<'a> Notification<'a> {
+    /// Create a notification for `event`.
+    ///
+    /// Returns `None` for events which do not require the user's attention, such as polls
+    /// without new emails or temporary connection problems.
+    #[must_use]
+    pub fn from_event(event: &'a Event) -> Option<Self> {
+        let (title, body) = match event {
+            Event::NewEmail {
+                email,
+                emails,
+                groups,
+                ..
+            } => {
+                if emails.is_empty() {
+                    return None;
+                }
+                (
+                    format!("{} for {email}", new_emails_text(message_count(emails))),
+                    emails_body(emails, groups),
+                )
+            }
+            Event::Digest {
+                email,
+                emails,
+                groups,
+                ..
+            } => (
+                format!(
+                    "{} for {email} during quiet hours",
+                    new_emails_text(message_count(emails))
+                ),
+                emails_body(emails, groups),
+            ),
+            Event::LoggedOut { email, reason } => (
+                format!("{email} has been logged out"),
+                format!("{reason}. {}", reason.recovery_hint()),
+            ),
+            Event::Offline(_) | Event::Error(_, _) => return None,
+        };
+
+        Some(Self { event, title, body })
+    }
+
+    /// Email of the account.
+    #[must_use]
+    pub fn email(&self) -> &str {
+        self.event.email()
+    }
+
+    /// Backend of the account, if known.
+    #[must_use]
+    pub fn backend(&self) -> Option<&str> {
+        match self.event {
+            Event::NewEmail { backend, .. } | Event::Digest { backend, .. } => Some(backend),
+            Event::LoggedOut { .. } | Event::Offline(_) | Event::Error(_, _) => None,
+        }
+    }
+
+    /// Number of new emails, counting each message of a collapsed thread.
+    #[must_use]
+    pub fn email_count(&self) -> usize {
+        match self.event {
+            Event::NewEmail { emails, .. } | Event::Digest { emails, .. } => message_count(emails),
+            Event::LoggedOut { .. } | Event::Offline(_) | Event::Error(_, _) => 0,
+        }
+    }
+}
+
+fn message_count(emails: &[NewEmail]) -> usize {
+    emails.iter().map(|email| email.message_count).sum()
+}
+
+fn new_emails_text(count: usize) -> String {
+    if count == 1 {
+        "1 new email".to_owned()
+    } else {
+        format!("{count} new emails")
+    }
+}
+
+/// One line per group followed by one line per email which is not part of a group.
+fn emails_body(emails: &[NewEmail], groups: &[EmailGroup]) -> String {
+    let mut lines = groups
+        .iter()
+        .map(|group| group.summary.clone())
+        .collect::<Vec<_>>();
+    lines.extend(
+        emails
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !groups.iter().any(|group| group.emails.contains(index)))
+            .map(|(_, email)| {
+                if email.message_count > 1 {
+                    format!(
+                        "{}: {} ({} messages)",
+                        email.sender, email.subject, email.message_count
+                    )
+                } else {
+                    format!("{}: {}", email.sender, email.subject)
+                }
+            }),
+    );
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::LogoutReason;
+
+    fn email(sender: &str, subject: &str) -> NewEmail {
+        NewEmail::new(sender, "", subject)
+    }
+
+    #[test]
+    fn new_email_notification() {
+        let event = Event::NewEmail {
+            email: "foo@bar.com".to_owned(),
+            backend: "backend".to_owned(),
+            emails: vec![
+                email("GitHub", "A"),
+                email("Alice", "Hello"),
+                email("GitHub", "B"),
+            ],
+            suppressed: 0,
+            groups: vec![EmailGroup {
+                title: "GitHub".to_owned(),
+                summary: "2 new messages from GitHub".to_owned(),
+                emails: vec![0, 2],
+            }],
+        };
+
+        let notification = Notification::from_event(&event).unwrap();
+        assert_eq!(notification.email(), "foo@bar.com");
+        assert_eq!(notification.title, "3 new emails for foo@bar.com");
+        assert_eq!(
+            notification.body,
+            "2 new messages from GitHub\nAlice: Hello"
+        );
+    }
+
+    #[test]
+    fn digest_and_logout_notification() {
+        let event = Event::Digest {
+            email: "foo@bar.com".to_owned(),
+            backend: "backend".to_owned(),
+            emails: vec![NewEmail {
+                message_count: 3,
+                ..email("Alice", "Hello")
+            }],
+            groups: vec![],
+        };
+        let notification = Notification::from_event(&event).unwrap();
+        assert_eq!(
+            notification.title,
+            "3 new emails for foo@bar.com during quiet hours"
+        );
+        assert_eq!(notification.body, "Alice: Hello (3 messages)");
+        assert_eq!(notification.email_count(), 3);
+        assert_eq!(new_emails_text(1), "1 new email");
+
+        let event = Event::LoggedOut {
+            email: "foo@bar.com".to_owned(),
+            reason: LogoutReason::SessionRevoked,
+        };
+        let notification = Notification::from_event(&event).unwrap();
+        assert_eq!(notification.title, "foo@bar.com has been logged out");
+    }
+
+    #[test]
+    fn no_notification_without_emails_or_for_errors() {
+        let event = Event::NewEmail {
+            email: "foo@bar.com".to_owned(),
+            backend: "backend".to_owned(),
+            emails: vec![],
+            suppressed: 1,
+            groups: vec![],
+        };
+        assert!(Notification::from_event(&event).is_none());
+        assert!(Notification::from_event(&Event::Offline("foo@bar.com".to_owned())).is_none());
+        assert!(
+            Notification::from_event(&Event::Error("foo@bar.com".to_owned(), "error".to_owned()))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn sink_scope() {
+        assert!(SinkScope::Global.contains("foo@bar.com"));
+        assert!(SinkScope::Account("foo@bar.com".to_owned()).contains("foo@bar.com"));
+        assert!(!SinkScope::Account("foo@bar.com".to_owned()).contains("bar@bar.com"));
+    }
+}
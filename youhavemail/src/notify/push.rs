@@ -0,0 +1,179 @@
+//! Push notification sinks: [ntfy](https://ntfy.sh), [Gotify](https://gotify.net) and
+//! [UnifiedPush](https://unifiedpush.org).
+
+use crate::notify::request::{PostRequest, new_client};
+use crate::notify::{Notification, NotificationSink, Result};
+use http::url::Url;
+use http::{Client, Proxy};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Publish notifications to an ntfy topic.
+pub struct NtfySink {
+    client: Arc<Client>,
+    topic: String,
+    token: Option<String>,
+}
+
+impl NtfySink {
+    /// Create a new sink which publishes to `topic` on the ntfy server at `server_url`, e.g.
+    /// `https://ntfy.sh`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the http client could not be created.
+    pub fn new(
+        server_url: Url,
+        proxy: Option<Proxy>,
+        topic: impl Into<String>,
+    ) -> http::Result<Self> {
+        Ok(Self {
+            client: new_client(server_url, proxy)?,
+            topic: topic.into(),
+            token: None,
+        })
+    }
+
+    /// Authenticate with an access `token`.
+    #[must_use]
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+}
+
+impl NotificationSink for NtfySink {
+    fn name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    fn notify(&self, notification: &Notification<'_>) -> Result<()> {
+        let mut headers = vec![
+            ("Title", notification.title.clone()),
+            ("Tags", "email".to_owned()),
+        ];
+        if let Some(token) = &self.token {
+            headers.push(("Authorization", format!("Bearer {token}")));
+        }
+
+        self.client.execute(&PostRequest {
+            url: self.topic.clone(),
+            headers,
+            body: notification.body.clone().into_bytes(),
+        })?;
+        Ok(())
+    }
+}
+
+/// Publish notifications to a Gotify server.
+pub struct GotifySink {
+    client: Arc<Client>,
+    app_token: String,
+    priority: u8,
+}
+
+#[derive(Serialize)]
+struct GotifyMessage<'a> {
+    title: &'a str,
+    message: &'a str,
+    priority: u8,
+}
+
+impl GotifySink {
+    /// Create a new sink which publishes to the Gotify server at `server_url` with the
+    /// application token `app_token`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the http client could not be created.
+    pub fn new(
+        server_url: Url,
+        proxy: Option<Proxy>,
+        app_token: impl Into<String>,
+    ) -> http::Result<Self> {
+        Ok(Self {
+            client: new_client(server_url, proxy)?,
+            app_token: app_token.into(),
+            priority: 5,
+        })
+    }
+
+    /// Set the message `priority`. The default is 5.
+    #[must_use]
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+impl NotificationSink for GotifySink {
+    fn name(&self) -> &'static str {
+        "gotify"
+    }
+
+    fn notify(&self, notification: &Notification<'_>) -> Result<()> {
+        let message = GotifyMessage {
+            title: &notification.title,
+            message: &notification.body,
+            priority: self.priority,
+        };
+
+        self.client.execute(&PostRequest {
+            url: "message".to_owned(),
+            headers: vec![
+                ("X-Gotify-Key", self.app_token.clone()),
+                ("Content-Type", "application/json".to_owned()),
+            ],
+            body: serde_json::to_vec(&message).expect("Serialization should never fail"),
+        })?;
+        Ok(())
+    }
+}
+
+/// Push notifications to a [UnifiedPush](https://unifiedpush.org) endpoint.
+///
+/// The message is a JSON object with the `title`, `body` and `email` of the notification.
+pub struct UnifiedPushSink {
+    client: Arc<Client>,
+}
+
+#[derive(Serialize)]
+struct UnifiedPushMessage<'a> {
+    title: &'a str,
+    body: &'a str,
+    email: &'a str,
+}
+
+impl UnifiedPushSink {
+    /// Create a new sink which pushes to `endpoint`, as provided by the distributor.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the http client could not be created.
+    pub fn new(endpoint: Url, proxy: Option<Proxy>) -> http::Result<Self> {
+        Ok(Self {
+            client: new_client(endpoint, proxy)?,
+        })
+    }
+}
+
+impl NotificationSink for UnifiedPushSink {
+    fn name(&self) -> &'static str {
+        "unifiedpush"
+    }
+
+    fn notify(&self, notification: &Notification<'_>) -> Result<()> {
+        let message = UnifiedPushMessage {
+            title: &notification.title,
+            body: &notification.body,
+            email: notification.email(),
+        };
+
+        self.client.execute(&PostRequest {
+            url: String::new(),
+            headers: vec![("Content-Type", "application/json".to_owned())],
+            body: serde_json::to_vec(&message).expect("Serialization should never fail"),
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+//! Http helpers shared by the http based sinks.
+
+use http::url::Url;
+use http::{Client, Method, NoResponse, Proxy, RequestBuilder};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Create a client for a sink which sends requests to `base_url`.
+///
+/// Plain http is only allowed if `base_url` uses it.
+///
+/// # Errors
+///
+/// Returns error if the client could not be created.
+pub(super) fn new_client(base_url: Url, proxy: Option<Proxy>) -> http::Result<Arc<Client>> {
+    let allow_http = base_url.scheme() == "http";
+    let mut builder = Client::builder(base_url)
+        .user_agent(concat!("you-have-mail/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(Duration::from_secs(10))
+        .request_timeout(Duration::from_secs(30));
+    if allow_http {
+        builder = builder.allow_http();
+    }
+    if let Some(proxy) = proxy {
+        builder = builder.with_proxy(proxy);
+    }
+    builder.build()
+}
+
+/// Request with an arbitrary body and headers. The url is relative to the client's base url.
+pub(super) struct SinkRequest<const PUT: bool> {
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+#[cfg(any(feature = "sink-webhook", feature = "sink-push"))]
+pub(super) type PostRequest = SinkRequest<false>;
+#[cfg(feature = "sink-matrix")]
+pub(super) type PutRequest = SinkRequest<true>;
+
+impl<const PUT: bool> http::Request for SinkRequest<PUT> {
+    type Response = NoResponse;
+    const METHOD: Method = if PUT { Method::Put } else { Method::Post };
+
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    fn build(&self, builder: RequestBuilder) -> http::Result<RequestBuilder> {
+        Ok(self
+            .headers
+            .iter()
+            .fold(builder, |builder, (key, value)| builder.header(key, value))
+            .bytes(self.body.clone()))
+    }
+}
@@ -0,0 +1,128 @@
+//! Generic HTTP webhook sink.
+
+use crate::notify::request::{PostRequest, new_client};
+use crate::notify::{Notification, NotificationSink, Result};
+use http::url::Url;
+use http::{Client, Proxy};
+use std::sync::Arc;
+
+/// Template used by [`WebhookSink::new`].
+pub const DEFAULT_TEMPLATE: &str =
+    r#"{"title":"{{title}}","body":"{{body}}","email":"{{email}}","count":{{count}}}"#;
+
+/// Post a JSON document rendered from a template to an HTTP endpoint for every notification.
+///
+/// The following placeholders are replaced in the template:
+///
+/// * `{{title}}`: [`Notification::title`].
+/// * `{{body}}`: [`Notification::body`].
+/// * `{{email}}`: Email of the account.
+/// * `{{backend}}`: Backend of the account, empty if not known.
+/// * `{{count}}`: Number of new emails.
+/// * `{{event}}`: The [`Event`](crate::events::Event) as JSON.
+///
+/// All placeholders except `{{count}}` and `{{event}}` are replaced with JSON escaped text
+/// without quotes, so they should be placed inside a JSON string.
+pub struct WebhookSink {
+    client: Arc<Client>,
+    template: String,
+    headers: Vec<(&'static str, String)>,
+}
+
+impl WebhookSink {
+    /// Create a new sink which posts [`DEFAULT_TEMPLATE`] to `url`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the http client could not be created.
+    pub fn new(url: Url, proxy: Option<Proxy>) -> http::Result<Self> {
+        Self::with_template(url, proxy, DEFAULT_TEMPLATE)
+    }
+
+    /// Create a new sink which posts `template` to `url`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the http client could not be created.
+    pub fn with_template(
+        url: Url,
+        proxy: Option<Proxy>,
+        template: impl Into<String>,
+    ) -> http::Result<Self> {
+        Ok(Self {
+            client: new_client(url, proxy)?,
+            template: template.into(),
+            headers: Vec::new(),
+        })
+    }
+
+    /// Send the `Authorization` header with `value` with every request.
+    #[must_use]
+    pub fn authorization(mut self, value: impl Into<String>) -> Self {
+        self.headers.push(("Authorization", value.into()));
+        self
+    }
+
+    /// Render the template for `notification`.
+    ///
+    /// The template is scanned once, so placeholders which appear in the replaced values are
+    /// not replaced again. Unknown placeholders are kept as they are.
+    #[must_use]
+    pub fn render(&self, notification: &Notification<'_>) -> String {
+        let mut output = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let value = rest[2..].find("}}").and_then(|end| {
+                placeholder(&rest[2..2 + end], notification).map(|value| (value, end + 4))
+            });
+            if let Some((value, len)) = value {
+                output.push_str(&value);
+                rest = &rest[len..];
+            } else {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+        output.push_str(rest);
+        output
+    }
+}
+
+impl NotificationSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn notify(&self, notification: &Notification<'_>) -> Result<()> {
+        let mut headers = self.headers.clone();
+        headers.push(("Content-Type", "application/json".to_owned()));
+        self.client.execute(&PostRequest {
+            url: String::new(),
+            headers,
+            body: self.render(notification).into_bytes(),
+        })?;
+        Ok(())
+    }
+}
+
+/// Value of the placeholder with `name` for `notification`.
+fn placeholder(name: &str, notification: &Notification<'_>) -> Option<String> {
+    Some(match name {
+        "title" => escape(&notification.title),
+        "body" => escape(&notification.body),
+        "email" => escape(notification.email()),
+        "backend" => escape(notification.backend().unwrap_or_default()),
+        "count" => notification.email_count().to_string(),
+        "event" => serde_json::to_string(notification.event).unwrap_or_default(),
+        _ => return None,
+    })
+}
+
+/// Escape `value` for use inside a JSON string.
+fn escape(value: &str) -> String {
+    let quoted = serde_json::Value::from(value).to_string();
+    quoted[1..quoted.len() - 1].to_owned()
+}
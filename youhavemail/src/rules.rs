@@ -0,0 +1,431 @@
+//! Client side rules which decide whether a [`NewEmail`] produces a notification.
+//!
+//! Rules are stored per account and evaluated in order by [`crate::yhm::Yhm::poll`]. The
+//! action of the first rule whose condition matches the email is applied. Emails which do not
+//! match any rule produce a notification.
+//!
+//! A [`Condition::Time`] restricts a rule to certain times. Use
+//! [`crate::schedule::QuietHours`] to silence all notifications instead.
+//!
+//! ```rust
+//! use chrono::Weekday;
+//! use you_have_mail_common::rules::{Condition, Rule, RuleAction};
+//! use you_have_mail_common::schedule::{Timezone, Window};
+//!
+//! let night = |weekday| Window {
+//!     weekday,
+//!     start: "22:00:00".parse().unwrap(),
+//!     end: "07:00:00".parse().unwrap(),
+//! };
+//!
+//! let rules = [
+//!     // Always notify for emails from the boss.
+//!     Rule::allow_senders(["boss@example.com"]),
+//!     // Never notify for newsletters.
+//!     Rule::new(Condition::Subject("(?i)newsletter".to_owned()), RuleAction::Suppress),
+//!     // Only notify for VIPs at night.
+//!     Rule::new(
+//!         Condition::All(vec![
+//!             Condition::Time {
+//!                 timezone: Timezone::Local,
+//!                 windows: vec![night(Weekday::Sun), night(Weekday::Mon)],
+//!             },
+//!             Condition::Not(Box::new(Condition::Sender(vec!["vip.com".to_owned()]))),
+//!         ]),
+//!         RuleAction::Suppress,
+//!     ),
+//! ];
+//! ```
+
+use crate::backend::NewEmail;
+use crate::schedule::{Timezone, Window};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Errors returned when rules are not valid.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid subject pattern '{0}': {1}")]
+    InvalidPattern(String, #[source] regex::Error),
+    #[error("Invalid time condition: {0}")]
+    InvalidTime(#[from] crate::schedule::Error),
+}
+
+/// Condition which an email needs to match for a [`Rule`] to apply.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    /// The sender matches one of the entries. An entry is either a full email address or a
+    /// domain, such as `example.com` or `@example.com`, which also matches its subdomains.
+    Sender(Vec<String>),
+    /// The subject matches the regular expression.
+    Subject(String),
+    /// The email is in one of the folders or labels, using the backend's ids.
+    Folder(Vec<String>),
+    /// The rules are evaluated at a time within one of the `windows`, in `timezone`. Combine
+    /// it with other conditions to apply a rule at certain times only.
+    Time {
+        timezone: Timezone,
+        windows: Vec<Window>,
+    },
+    /// All the conditions match. An empty list always matches.
+    All(Vec<Condition>),
+    /// At least one of the conditions matches.
+    Any(Vec<Condition>),
+    /// The condition does not match.
+    Not(Box<Condition>),
+}
+
+/// Action to take on an email which matches a [`Rule`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RuleAction {
+    /// Produce a notification.
+    Notify,
+    /// Do not produce a notification.
+    Suppress,
+}
+
+/// A notification filter rule.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub condition: Condition,
+    pub action: RuleAction,
+}
+
+impl Rule {
+    /// Create a new rule which applies `action` when `condition` matches.
+    #[must_use]
+    pub fn new(condition: Condition, action: RuleAction) -> Self {
+        Self { condition, action }
+    }
+
+    /// Always notify for emails from `senders`, see [`Condition::Sender`].
+    #[must_use]
+    pub fn allow_senders<S: Into<String>>(senders: impl IntoIterator<Item = S>) -> Self {
+        Self::new(
+            Condition::Sender(senders.into_iter().map(Into::into).collect()),
+            RuleAction::Notify,
+        )
+    }
+
+    /// Never notify for emails from `senders`, see [`Condition::Sender`].
+    #[must_use]
+    pub fn deny_senders<S: Into<String>>(senders: impl IntoIterator<Item = S>) -> Self {
+        Self::new(
+            Condition::Sender(senders.into_iter().map(Into::into).collect()),
+            RuleAction::Suppress,
+        )
+    }
+
+    /// Only notify for emails from `senders`, e.g. a list of VIPs.
+    #[must_use]
+    pub fn only_senders<S: Into<String>>(senders: impl IntoIterator<Item = S>) -> Self {
+        Self::new(
+            Condition::Not(Box::new(Condition::Sender(
+                senders.into_iter().map(Into::into).collect(),
+            ))),
+            RuleAction::Suppress,
+        )
+    }
+}
+
+/// Rules which are ready to be evaluated.
+pub struct RuleSet {
+    rules: Vec<(CompiledCondition, RuleAction)>,
+}
+
+impl RuleSet {
+    /// Validate and prepare `rules` for evaluation.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a subject pattern is not a valid regular expression or the timezone of
+    /// a time condition is not valid.
+    pub fn new(rules: &[Rule]) -> Result<Self, Error> {
+        let rules = rules
+            .iter()
+            .map(|rule| Ok((CompiledCondition::new(&rule.condition)?, rule.action)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Whether there are no rules.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Evaluate the rules for `email` at time `now`.
+    #[must_use]
+    pub fn evaluate(&self, email: &NewEmail, now: DateTime<Utc>) -> RuleAction {
+        self.rules
+            .iter()
+            .find(|(condition, _)| condition.matches(email, now))
+            .map_or(RuleAction::Notify, |(_, action)| *action)
+    }
+
+    /// Split `emails` into the emails which produce a notification and the number of
+    /// suppressed messages, see [`NewEmail::message_count`].
+    #[must_use]
+    pub fn filter(&self, emails: Vec<NewEmail>, now: DateTime<Utc>) -> (Vec<NewEmail>, usize) {
+        let (notify, suppressed): (Vec<_>, Vec<_>) = emails
+            .into_iter()
+            .partition(|email| self.evaluate(email, now) == RuleAction::Notify);
+        let suppressed = suppressed.iter().map(|email| email.message_count).sum();
+        (notify, suppressed)
+    }
+}
+
+enum CompiledCondition {
+    Sender(Vec<String>),
+    Subject(Regex),
+    Folder(Vec<String>),
+    Time {
+        timezone: Timezone,
+        windows: Vec<Window>,
+    },
+    All(Vec<CompiledCondition>),
+    Any(Vec<CompiledCondition>),
+    Not(Box<CompiledCondition>),
+}
+
+impl CompiledCondition {
+    fn new(condition: &Condition) -> Result<Self, Error> {
+        Ok(match condition {
+            Condition::Sender(senders) => Self::Sender(
+                senders
+                    .iter()
+                    .map(|sender| sender.trim().to_lowercase())
+                    .collect(),
+            ),
+            Condition::Subject(pattern) => Self::Subject(
+                Regex::new(pattern).map_err(|e| Error::InvalidPattern(pattern.clone(), e))?,
+            ),
+            Condition::Folder(folders) => Self::Folder(folders.clone()),
+            Condition::Time { timezone, windows } => {
+                timezone.validate()?;
+                Self::Time {
+                    timezone: *timezone,
+                    windows: windows.clone(),
+                }
+            }
+            Condition::All(conditions) => {
+                Self::All(conditions.iter().map(Self::new).collect::<Result<_, _>>()?)
+            }
+            Condition::Any(conditions) => {
+                Self::Any(conditions.iter().map(Self::new).collect::<Result<_, _>>()?)
+            }
+            Condition::Not(condition) => Self::Not(Box::new(Self::new(condition)?)),
+        })
+    }
+
+    fn matches(&self, email: &NewEmail, now: DateTime<Utc>) -> bool {
+        match self {
+            Self::Sender(senders) => {
+                let address = email.sender_address.to_lowercase();
+                senders
+                    .iter()
+                    .any(|sender| sender_matches(sender, &address))
+            }
+            Self::Subject(regex) => regex.is_match(&email.subject),
+            Self::Folder(folders) => email.labels.iter().any(|label| folders.contains(label)),
+            Self::Time { timezone, windows } => {
+                let time = timezone.local_time(now);
+                windows.iter().any(|window| window.contains(time))
+            }
+            Self::All(conditions) => conditions.iter().all(|c| c.matches(email, now)),
+            Self::Any(conditions) => conditions.iter().any(|c| c.matches(email, now)),
+            Self::Not(condition) => !condition.matches(email, now),
+        }
+    }
+}
+
+/// Check whether the lowercase sender `address` matches the lowercase `sender` entry.
+fn sender_matches(sender: &str, address: &str) -> bool {
+    if let Some(domain) = sender.strip_prefix('@').or_else(|| {
+        if sender.contains('@') {
+            None
+        } else {
+            Some(sender)
+        }
+    }) {
+        let Some((_, address_domain)) = address.rsplit_once('@') else {
+            return false;
+        };
+        return address_domain == domain
+            || address_domain
+                .strip_suffix(domain)
+                .is_some_and(|prefix| prefix.ends_with('.'));
+    }
+
+    sender == address
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Weekday};
+
+    fn email(sender_address: &str, subject: &str, labels: &[&str]) -> NewEmail {
+        NewEmail {
+            labels: labels.iter().map(|l| (*l).to_owned()).collect(),
+            ..NewEmail::new("Sender", sender_address, subject)
+        }
+    }
+
+    /// 2024-01-01 is a Monday.
+    fn utc(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, hour, minute, 0).unwrap()
+    }
+
+    fn noon() -> DateTime<Utc> {
+        utc(12, 0)
+    }
+
+    #[test]
+    fn no_rules_notify() {
+        let rules = RuleSet::new(&[]).unwrap();
+        assert!(rules.is_empty());
+        assert_eq!(
+            rules.evaluate(&email("foo@bar.com", "Hi", &[]), noon()),
+            RuleAction::Notify
+        );
+    }
+
+    #[test]
+    fn sender_lists() {
+        let rules = RuleSet::new(&[
+            Rule::allow_senders(["Boss@Work.com"]),
+            Rule::deny_senders(["work.com", "@spam.org", "someone@else.net"]),
+        ])
+        .unwrap();
+
+        let check = |address| rules.evaluate(&email(address, "Hi", &[]), noon());
+        assert_eq!(check("boss@work.com"), RuleAction::Notify);
+        assert_eq!(check("colleague@work.com"), RuleAction::Suppress);
+        assert_eq!(check("colleague@eu.work.com"), RuleAction::Suppress);
+        assert_eq!(check("someone@homework.com"), RuleAction::Notify);
+        assert_eq!(check("bulk@spam.org"), RuleAction::Suppress);
+        assert_eq!(check("someone@else.net"), RuleAction::Suppress);
+        assert_eq!(check("other@else.net"), RuleAction::Notify);
+    }
+
+    #[test]
+    fn only_senders() {
+        let rules = RuleSet::new(&[Rule::only_senders(["vip@example.com"])]).unwrap();
+
+        assert_eq!(
+            rules.evaluate(&email("vip@example.com", "Hi", &[]), noon()),
+            RuleAction::Notify
+        );
+        assert_eq!(
+            rules.evaluate(&email("other@example.com", "Hi", &[]), noon()),
+            RuleAction::Suppress
+        );
+    }
+
+    #[test]
+    fn subject_and_folder() {
+        let rules = RuleSet::new(&[
+            Rule::new(
+                Condition::Subject("(?i)^\\[newsletter\\]".to_owned()),
+                RuleAction::Suppress,
+            ),
+            Rule::new(
+                Condition::Folder(vec!["updates".to_owned()]),
+                RuleAction::Suppress,
+            ),
+        ])
+        .unwrap();
+
+        let check = |subject, labels| rules.evaluate(&email("a@b.com", subject, labels), noon());
+        assert_eq!(check("[Newsletter] Weekly", &["0"]), RuleAction::Suppress);
+        assert_eq!(check("Re: [Newsletter]", &["0"]), RuleAction::Notify);
+        assert_eq!(check("Hello", &["0", "updates"]), RuleAction::Suppress);
+    }
+
+    #[test]
+    fn combined_conditions() {
+        let rules = RuleSet::new(&[Rule::new(
+            Condition::All(vec![
+                Condition::Any(vec![
+                    Condition::Folder(vec!["updates".to_owned()]),
+                    Condition::Subject("(?i)digest".to_owned()),
+                ]),
+                Condition::Not(Box::new(Condition::Sender(vec!["vip.com".to_owned()]))),
+            ]),
+            RuleAction::Suppress,
+        )])
+        .unwrap();
+
+        let check =
+            |address, subject, labels| rules.evaluate(&email(address, subject, labels), noon());
+        assert_eq!(check("a@b.com", "Hi", &["updates"]), RuleAction::Suppress);
+        assert_eq!(check("a@b.com", "Daily Digest", &[]), RuleAction::Suppress);
+        assert_eq!(check("a@b.com", "Hi", &[]), RuleAction::Notify);
+        assert_eq!(check("a@vip.com", "Hi", &["updates"]), RuleAction::Notify);
+    }
+
+    #[test]
+    fn time_condition() {
+        let night = Condition::Time {
+            timezone: Timezone::Utc,
+            windows: vec![Window {
+                weekday: Weekday::Mon,
+                start: "00:00:00".parse().unwrap(),
+                end: "07:00:00".parse().unwrap(),
+            }],
+        };
+        let rules = RuleSet::new(&[Rule::new(
+            Condition::All(vec![
+                night,
+                Condition::Not(Box::new(Condition::Sender(vec!["vip.com".to_owned()]))),
+            ]),
+            RuleAction::Suppress,
+        )])
+        .unwrap();
+
+        let regular = email("a@b.com", "Hi", &[]);
+        let vip = email("a@vip.com", "Hi", &[]);
+        assert_eq!(rules.evaluate(&regular, utc(6, 59)), RuleAction::Suppress);
+        assert_eq!(rules.evaluate(&regular, utc(7, 0)), RuleAction::Notify);
+        assert_eq!(rules.evaluate(&vip, utc(6, 0)), RuleAction::Notify);
+
+        let invalid = Condition::Time {
+            timezone: Timezone::Offset(86_400),
+            windows: Vec::new(),
+        };
+        assert!(matches!(
+            RuleSet::new(&[Rule::new(invalid, RuleAction::Suppress)]),
+            Err(Error::InvalidTime(_))
+        ));
+    }
+
+    #[test]
+    fn filter_counts_suppressed() {
+        let rules = RuleSet::new(&[Rule::deny_senders(["spam.org"])]).unwrap();
+        let (notify, suppressed) = rules.filter(
+            vec![
+                email("a@spam.org", "Hi", &[]),
+                email("a@b.com", "Hi", &[]),
+                NewEmail {
+                    message_count: 3,
+                    ..email("b@spam.org", "Hi", &[])
+                },
+            ],
+            noon(),
+        );
+        assert_eq!(notify.len(), 1);
+        assert_eq!(notify[0].sender_address, "a@b.com");
+        assert_eq!(suppressed, 4);
+    }
+
+    #[test]
+    fn invalid_subject_pattern() {
+        let result = RuleSet::new(&[Rule::new(
+            Condition::Subject("(".to_owned()),
+            RuleAction::Suppress,
+        )]);
+        assert!(matches!(result, Err(Error::InvalidPattern(pattern, _)) if pattern == "("));
+    }
+}
@@ -0,0 +1,281 @@
+//! Quiet hours schedule which pauses polling or holds back notifications during
+//! configured time windows.
+//!
+//! The windows are evaluated in a [`Timezone`]. Only [`Timezone::Local`] and
+//! [`Timezone::Zone`] follow daylight saving time, a [`Timezone::Offset`] never changes.
+//!
+//! When notifications are held, the emails are stored and delivered as a single
+//! [`Event::Digest`](crate::events::Event::Digest) by the first [`crate::yhm::Yhm::poll`] after
+//! the quiet hours end.
+//!
+//! ```rust
+//! use chrono::Weekday;
+//! use you_have_mail_common::schedule::{QuietHours, QuietMode, Timezone, Window};
+//!
+//! let night = |weekday| Window {
+//!     weekday,
+//!     start: "22:00:00".parse().unwrap(),
+//!     end: "07:00:00".parse().unwrap(),
+//! };
+//!
+//! let quiet_hours = QuietHours {
+//!     timezone: Timezone::Local,
+//!     mode: QuietMode::HoldNotifications,
+//!     windows: vec![
+//!         night(Weekday::Fri),
+//!         night(Weekday::Sat),
+//!     ],
+//! };
+//! ```
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDateTime, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+pub use chrono_tz::Tz;
+
+/// Errors returned when a schedule is not valid.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid timezone offset {0}s")]
+    InvalidOffset(i32),
+}
+
+/// Timezone in which the schedule windows are evaluated.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Timezone {
+    /// Timezone of the system.
+    Local,
+    /// Coordinated Universal Time.
+    Utc,
+    /// Fixed offset in seconds east of UTC, which does not follow daylight saving time.
+    Offset(i32),
+    /// IANA timezone, such as `Europe/Berlin`, including its daylight saving time rules.
+    Zone(Tz),
+}
+
+impl Timezone {
+    /// Check whether the timezone is valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the timezone offset is out of range.
+    pub fn validate(self) -> Result<(), Error> {
+        if let Timezone::Offset(offset) = self {
+            FixedOffset::east_opt(offset).ok_or(Error::InvalidOffset(offset))?;
+        }
+        Ok(())
+    }
+
+    /// Local date and time of `now` in this timezone.
+    #[must_use]
+    pub fn local_time(self, now: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Timezone::Local => now.with_timezone(&chrono::Local).naive_local(),
+            Timezone::Utc => now.naive_utc(),
+            Timezone::Offset(offset) => FixedOffset::east_opt(offset)
+                .map_or(now.naive_utc(), |offset| {
+                    now.with_timezone(&offset).naive_local()
+                }),
+            Timezone::Zone(zone) => now.with_timezone(&zone).naive_local(),
+        }
+    }
+}
+
+/// What to do during quiet hours.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum QuietMode {
+    /// Do not poll any account.
+    PausePolling,
+    /// Keep polling, but hold back the new emails until the quiet hours end.
+    HoldNotifications,
+}
+
+/// Quiet time window which starts on `weekday`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    /// Day on which the window starts.
+    pub weekday: Weekday,
+    /// Start time of the window.
+    pub start: NaiveTime,
+    /// End time of the window. If `end` comes before `start`, the window ends on the next
+    /// day. If `end` equals `start`, the window lasts the whole day.
+    pub end: NaiveTime,
+}
+
+impl Window {
+    /// Whether the local `time` falls within this window.
+    #[must_use]
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        let weekday = time.date().weekday();
+        let time = time.time();
+        match self.start.cmp(&self.end) {
+            Ordering::Less => weekday == self.weekday && self.start <= time && time < self.end,
+            Ordering::Greater => {
+                (weekday == self.weekday && self.start <= time)
+                    || (weekday == self.weekday.succ() && time < self.end)
+            }
+            Ordering::Equal => weekday == self.weekday,
+        }
+    }
+}
+
+/// Quiet hours schedule.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// Timezone of the `windows`.
+    pub timezone: Timezone,
+    /// Behavior during quiet hours.
+    pub mode: QuietMode,
+    /// Quiet time windows.
+    pub windows: Vec<Window>,
+}
+
+impl QuietHours {
+    /// Check whether the schedule is valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the timezone offset is out of range.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.timezone.validate()
+    }
+
+    /// Whether `now` falls within one of the quiet time windows.
+    #[must_use]
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let time = self.timezone.local_time(now);
+        self.windows.iter().any(|window| window.contains(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time(value: &str) -> NaiveTime {
+        value.parse().unwrap()
+    }
+
+    /// 2024-01-01 is a Monday.
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    fn quiet_hours(timezone: Timezone, windows: Vec<Window>) -> QuietHours {
+        QuietHours {
+            timezone,
+            mode: QuietMode::HoldNotifications,
+            windows,
+        }
+    }
+
+    #[test]
+    fn window_within_day() {
+        let schedule = quiet_hours(
+            Timezone::Utc,
+            vec![Window {
+                weekday: Weekday::Mon,
+                start: time("09:00:00"),
+                end: time("17:00:00"),
+            }],
+        );
+
+        assert!(!schedule.is_active(utc(1, 8, 59)));
+        assert!(schedule.is_active(utc(1, 9, 0)));
+        assert!(schedule.is_active(utc(1, 16, 59)));
+        assert!(!schedule.is_active(utc(1, 17, 0)));
+        // Tuesday
+        assert!(!schedule.is_active(utc(2, 12, 0)));
+    }
+
+    #[test]
+    fn window_past_midnight() {
+        let schedule = quiet_hours(
+            Timezone::Utc,
+            vec![Window {
+                weekday: Weekday::Sun,
+                start: time("22:00:00"),
+                end: time("07:00:00"),
+            }],
+        );
+
+        // Sunday night and Monday morning.
+        assert!(schedule.is_active(utc(7, 23, 0)));
+        assert!(schedule.is_active(utc(8, 6, 59)));
+        assert!(!schedule.is_active(utc(8, 7, 0)));
+        // Monday night is not covered.
+        assert!(!schedule.is_active(utc(8, 23, 0)));
+        // Sunday morning is not covered.
+        assert!(!schedule.is_active(utc(7, 6, 0)));
+    }
+
+    #[test]
+    fn whole_day_window() {
+        let schedule = quiet_hours(
+            Timezone::Utc,
+            vec![Window {
+                weekday: Weekday::Sat,
+                start: time("00:00:00"),
+                end: time("00:00:00"),
+            }],
+        );
+
+        assert!(!schedule.is_active(utc(5, 23, 59)));
+        assert!(schedule.is_active(utc(6, 0, 0)));
+        assert!(schedule.is_active(utc(6, 23, 59)));
+        assert!(!schedule.is_active(utc(7, 0, 0)));
+    }
+
+    #[test]
+    fn timezone_offset() {
+        let schedule = quiet_hours(
+            Timezone::Offset(2 * 3600),
+            vec![Window {
+                weekday: Weekday::Tue,
+                start: time("00:00:00"),
+                end: time("01:00:00"),
+            }],
+        );
+
+        // Monday 22:30 UTC is Tuesday 00:30 at UTC+2.
+        assert!(schedule.is_active(utc(1, 22, 30)));
+        assert!(!schedule.is_active(utc(2, 0, 30)));
+        assert!(schedule.validate().is_ok());
+
+        let invalid = quiet_hours(Timezone::Offset(86_400), Vec::new());
+        assert!(matches!(
+            invalid.validate(),
+            Err(Error::InvalidOffset(86_400))
+        ));
+    }
+
+    #[test]
+    fn timezone_zone_follows_dst() {
+        let schedule = quiet_hours(
+            Timezone::Zone(Tz::Europe__Berlin),
+            vec![Window {
+                weekday: Weekday::Mon,
+                start: time("08:00:00"),
+                end: time("09:00:00"),
+            }],
+        );
+
+        // 2024-01-01 is in winter time, UTC+1.
+        assert!(schedule.is_active(utc(1, 7, 30)));
+        assert!(!schedule.is_active(utc(1, 6, 30)));
+        // 2024-07-01 is a Monday in summer time, UTC+2.
+        let summer = |hour| Utc.with_ymd_and_hms(2024, 7, 1, hour, 30, 0).unwrap();
+        assert!(schedule.is_active(summer(6)));
+        assert!(!schedule.is_active(summer(7)));
+
+        let json = serde_json::to_string(&schedule.timezone).unwrap();
+        assert_eq!(json, r#"{"Zone":"Europe/Berlin"}"#);
+        assert_eq!(
+            serde_json::from_str::<Timezone>(&json).unwrap(),
+            schedule.timezone
+        );
+        assert!(serde_json::from_str::<Timezone>(r#"{"Zone":"Mars/Olympus"}"#).is_err());
+    }
+}
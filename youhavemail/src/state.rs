@@ -1,9 +1,13 @@
 //! State management of accounts in the database.
 
+use crate::backend::NewEmail;
 use crate::db;
 use crate::db::{Pool, Transaction};
 use crate::encryption::Key;
 use crate::events::Event;
+use crate::grouping::Grouping;
+use crate::rules::Rule;
+use crate::schedule::QuietHours;
 use chrono::{DateTime, Utc};
 use http::Proxy;
 use rusqlite::{OptionalExtension, Row};
@@ -148,6 +152,36 @@ impl Account {
         }
     }
 
+    /// Atomically replace the secret state with the result of `update`.
+    ///
+    /// See [`State::update_secret_state`].
+    ///
+    /// # Errors
+    ///
+    /// Return error if the state could not be read or written.
+    pub fn update_secret<T, F>(&self, update: F) -> Result<Option<T>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> Option<T>,
+    {
+        self.state.update_secret_state(&self.email, update)
+    }
+
+    /// Atomically replace the state with the result of `update`.
+    ///
+    /// See [`State::update_account_state`].
+    ///
+    /// # Errors
+    ///
+    /// Return error if the state could not be read or written.
+    pub fn update_state<T, F>(&self, update: F) -> Result<Option<T>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> Option<T>,
+    {
+        self.state.update_account_state(&self.email, update)
+    }
+
     /// Update the account with new `proxy` config.
     ///
     /// # Errors
@@ -264,6 +298,25 @@ impl State {
         &self.encryption_key
     }
 
+    /// Serialize and encrypt `value` with the encryption key, for data that needs to be
+    /// persisted outside of the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the serialization or encryption failed.
+    pub fn encrypt<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        secret_to_bytes(self.encryption_key.expose_secret(), value)
+    }
+
+    /// Decrypt and deserialize `bytes` produced by [`Self::encrypt`].
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the decryption or deserialization failed.
+    pub fn decrypt<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        secret_from_bytes(self.encryption_key.expose_secret(), bytes)
+    }
+
     /// Get database watcher instance.
     #[must_use]
     pub fn watcher(&self) -> &Arc<Watcher> {
@@ -403,6 +456,8 @@ INSERT OR IGNORE INTO yhm (email, backend) VALUES (
     pub fn delete_account(&self, email: &str) -> Result<(), Error> {
         self.pool.with_transaction(|tx| {
             tx.execute("DELETE FROM yhm WHERE email=?", [email])?;
+            tx.execute("DELETE FROM yhm_rules WHERE email=?", [email])?;
+            tx.execute("DELETE FROM yhm_held_email WHERE email=?", [email])?;
             Ok(())
         })
     }
@@ -487,6 +542,44 @@ INSERT OR IGNORE INTO yhm (email, backend) VALUES (
         Ok(secret)
     }
 
+    /// Replace the secret state of the account with `email` with the result of `update`,
+    /// which receives the latest stored secret. If `update` returns `None` the secret is
+    /// erased.
+    ///
+    /// The secret is read and written in a single transaction. Other processes sharing the
+    /// database can not modify the state until `update` has completed.
+    ///
+    /// Returns the new secret state.
+    ///
+    /// # Errors
+    ///
+    /// Return error it the query failed or the state failed to serialize.
+    pub fn update_secret_state<T, F>(&self, email: &str, update: F) -> Result<Option<T>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> Option<T>,
+    {
+        let key = self.encryption_key.expose_secret();
+        self.pool.with_transaction(|tx| {
+            let secret_bytes: Option<Vec<u8>> = tx.query_row(
+                "SELECT secret FROM yhm WHERE email=? LIMIT 1",
+                [email],
+                |r| r.get(0),
+            )?;
+            let secret = secret_bytes
+                .map(|bytes| secret_from_bytes::<T>(key, &bytes))
+                .transpose()?;
+
+            let secret = update(secret);
+            let bytes = secret
+                .as_ref()
+                .map(|secret| secret_to_bytes(key, secret))
+                .transpose()?;
+            tx.execute("UPDATE yhm SET secret=? WHERE email=?", (bytes, email))?;
+            Ok(secret)
+        })
+    }
+
     /// Remove the state of the account with `email`.
     ///
     /// # Errors
@@ -512,6 +605,39 @@ INSERT OR IGNORE INTO yhm (email, backend) VALUES (
         })
     }
 
+    /// Replace the state of the account with `email` with the result of `update`, which
+    /// receives the latest stored state. If `update` returns `None` the state is erased.
+    ///
+    /// The state is read and written in a single transaction, so changes made by others in
+    /// the meantime are not lost.
+    ///
+    /// Returns the new state.
+    ///
+    /// # Errors
+    ///
+    /// Return error it the query failed or the state failed to (de)serialize.
+    pub fn update_account_state<T, F>(&self, email: &str, update: F) -> Result<Option<T>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> Option<T>,
+    {
+        self.pool.with_transaction(|tx| {
+            let state_bytes: Option<Vec<u8>> = tx.query_row(
+                "SELECT state FROM yhm WHERE email=? LIMIT 1",
+                [email],
+                |r| r.get(0),
+            )?;
+            let state = state_bytes
+                .map(|bytes| serde_json::from_slice::<T>(&bytes))
+                .transpose()?;
+
+            let state = update(state);
+            let bytes = state.as_ref().map(serde_json::to_vec).transpose()?;
+            tx.execute("UPDATE yhm SET state=? WHERE email=?", (bytes, email))?;
+            Ok(state)
+        })
+    }
+
     /// Remove the secret state of the account with `email`.
     ///
     /// # Errors
@@ -546,6 +672,83 @@ INSERT OR IGNORE INTO yhm (email, backend) VALUES (
         Ok(state)
     }
 
+    /// Get the notification rules of the account with `email`.
+    ///
+    /// # Errors
+    ///
+    /// Return error it the query failed or the rules failed to deserialize.
+    pub fn account_rules(&self, email: &str) -> Result<Vec<Rule>, Error> {
+        let rules: Option<String> = self.pool.with_connection(|conn| {
+            conn.query_row(
+                "SELECT rules FROM yhm_rules WHERE email=? LIMIT 1",
+                [email],
+                |r| r.get(0),
+            )
+            .optional()
+        })?;
+
+        match rules {
+            None => Ok(Vec::new()),
+            Some(rules) => Ok(serde_json::from_str(&rules)?),
+        }
+    }
+
+    /// Replace the notification `rules` of the account with `email`.
+    ///
+    /// # Errors
+    ///
+    /// Return error it the query failed or the rules failed to serialize.
+    pub fn set_account_rules(&self, email: &str, rules: &[Rule]) -> Result<(), Error> {
+        let rules = serde_json::to_string(rules)?;
+        self.pool.with_transaction(|tx| {
+            tx.execute(
+                r"
+INSERT INTO yhm_rules (email, rules) VALUES (?,?)
+ON CONFLICT(email) DO UPDATE SET rules=excluded.rules
+",
+                (email, rules),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get the number of emails of the account with `email` which did not produce a
+    /// notification due to its rules.
+    ///
+    /// # Errors
+    ///
+    /// Return error it the query failed.
+    pub fn suppressed_count(&self, email: &str) -> Result<u64, Error> {
+        let count: Option<u64> = self.pool.with_connection(|conn| {
+            conn.query_row(
+                "SELECT suppressed FROM yhm_rules WHERE email=? LIMIT 1",
+                [email],
+                |r| r.get(0),
+            )
+            .optional()
+        })?;
+
+        Ok(count.unwrap_or_default())
+    }
+
+    /// Increase the suppressed email counter of the account with `email` by `count`.
+    ///
+    /// # Errors
+    ///
+    /// Return error it the query failed.
+    pub fn add_suppressed_count(&self, email: &str, count: u64) -> Result<(), Error> {
+        self.pool.with_transaction(|tx| {
+            tx.execute(
+                r"
+INSERT INTO yhm_rules (email, suppressed) VALUES (?,?)
+ON CONFLICT(email) DO UPDATE SET suppressed=suppressed + excluded.suppressed
+",
+                (email, count),
+            )?;
+            Ok(())
+        })
+    }
+
     /// Delete account with `email`.
     ///
     /// # Errors
@@ -554,6 +757,8 @@ INSERT OR IGNORE INTO yhm (email, backend) VALUES (
     pub fn delete(&self, email: &str) -> Result<(), Error> {
         self.pool.with_transaction(|tx| {
             tx.execute("DELETE FROM yhm WHERE email=?", [email])?;
+            tx.execute("DELETE FROM yhm_rules WHERE email=?", [email])?;
+            tx.execute("DELETE FROM yhm_held_email WHERE email=?", [email])?;
             Ok(())
         })
     }
@@ -589,6 +794,150 @@ INSERT OR IGNORE INTO yhm (email, backend) VALUES (
         })
     }
 
+    /// Get the quiet hours setting.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed.
+    pub fn quiet_hours(&self) -> Result<Option<QuietHours>, Error> {
+        let quiet_hours: Option<String> = self.pool.with_connection(|conn| {
+            conn.query_row(
+                "SELECT quiet_hours FROM yhm_settings WHERE id=? LIMIT 1",
+                [SETTINGS_ID],
+                |r| r.get(0),
+            )
+        })?;
+
+        Ok(quiet_hours
+            .map(|quiet_hours| serde_json::from_str(&quiet_hours))
+            .transpose()?)
+    }
+
+    /// Set the quiet hours setting. Setting to `None` disables quiet hours.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed.
+    pub fn set_quiet_hours(&self, quiet_hours: Option<&QuietHours>) -> Result<(), Error> {
+        let quiet_hours = quiet_hours.map(serde_json::to_string).transpose()?;
+        self.pool.with_transaction(|tx| {
+            tx.execute(
+                "UPDATE yhm_settings SET quiet_hours=? WHERE id=?",
+                (quiet_hours, SETTINGS_ID),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get the notification grouping setting.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed.
+    pub fn grouping(&self) -> Result<Option<Grouping>, Error> {
+        let grouping: Option<String> = self.pool.with_connection(|conn| {
+            conn.query_row(
+                "SELECT grouping FROM yhm_settings WHERE id=? LIMIT 1",
+                [SETTINGS_ID],
+                |r| r.get(0),
+            )
+        })?;
+
+        Ok(grouping
+            .map(|grouping| serde_json::from_str(&grouping))
+            .transpose()?)
+    }
+
+    /// Set the notification grouping setting. Setting to `None` disables grouping.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed.
+    pub fn set_grouping(&self, grouping: Option<&Grouping>) -> Result<(), Error> {
+        let grouping = grouping.map(serde_json::to_string).transpose()?;
+        self.pool.with_transaction(|tx| {
+            tx.execute(
+                "UPDATE yhm_settings SET grouping=? WHERE id=?",
+                (grouping, SETTINGS_ID),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Append `emails` to the emails held during quiet hours for the account with `email`.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed.
+    pub fn hold_emails(&self, email: &str, emails: &[NewEmail]) -> Result<(), Error> {
+        self.pool.with_transaction(|tx| {
+            let held: Option<String> = tx
+                .query_row(
+                    "SELECT emails FROM yhm_held_email WHERE email=? LIMIT 1",
+                    [email],
+                    |r| r.get(0),
+                )
+                .optional()?;
+            let mut held = held
+                .map(|held| serde_json::from_str::<Vec<NewEmail>>(&held))
+                .transpose()?
+                .unwrap_or_default();
+            held.extend_from_slice(emails);
+
+            tx.execute(
+                "INSERT OR REPLACE INTO yhm_held_email (email, emails) VALUES (?,?)",
+                (email, serde_json::to_string(&held)?),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get the emails held during quiet hours for the account with `email`.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed.
+    pub fn held_emails(&self, email: &str) -> Result<Vec<NewEmail>, Error> {
+        let held: Option<String> = self.pool.with_connection(|conn| {
+            conn.query_row(
+                "SELECT emails FROM yhm_held_email WHERE email=? LIMIT 1",
+                [email],
+                |r| r.get(0),
+            )
+            .optional()
+        })?;
+
+        match held {
+            None => Ok(Vec::new()),
+            Some(held) => Ok(serde_json::from_str(&held)?),
+        }
+    }
+
+    /// Remove and return the emails held during quiet hours for the account with `email`.
+    ///
+    /// Run this in the transaction which stores the events delivering the emails, see
+    /// [`State::store_events`], so they are only discarded once they have been delivered.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the operation failed.
+    pub(crate) fn take_held_emails(tx: &Transaction, email: &str) -> Result<Vec<NewEmail>, Error> {
+        let held: Option<String> = tx
+            .query_row(
+                "SELECT emails FROM yhm_held_email WHERE email=? LIMIT 1",
+                [email],
+                |r| r.get(0),
+            )
+            .optional()?;
+        let Some(held) = held else {
+            return Ok(Vec::new());
+        };
+
+        let held = serde_json::from_str(&held)?;
+        tx.execute("DELETE FROM yhm_held_email WHERE email=?", [email])?;
+        Ok(held)
+    }
+
     /// Check if account with `email` is logged out.
     ///
     /// # Errors
@@ -610,11 +959,20 @@ INSERT OR IGNORE INTO yhm (email, backend) VALUES (
     ///
     /// Returns error if the process failed
     pub fn create_or_update_events(&self, events: &[Event]) -> Result<(), Error> {
+        self.pool
+            .with_transaction(|tx| Self::store_events(tx, events))
+    }
+
+    /// Store `events` into the database with the transaction `tx`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the process failed
+    pub(crate) fn store_events(tx: &Transaction, events: &[Event]) -> Result<(), Error> {
         let time = Utc::now();
-        self.pool.with_transaction(|tx| {
-            tx.execute("DELETE FROM yhm_poll_event", ())?;
-            let mut event_stmt = tx.prepare(
-                r"
+        tx.execute("DELETE FROM yhm_poll_event", ())?;
+        let mut event_stmt = tx.prepare(
+            r"
 WITH cte(email, event) AS (
     VALUES (?,?)
 )
@@ -622,17 +980,16 @@ INSERT OR REPLACE INTO yhm_poll_event (email, event)
 SELECT c.email,c.event FROM cte AS C
 WHERE EXISTS (SELECT 1 FROM yhm WHERE email=c.email)
 ",
-            )?;
-            let mut update_account_stmt = tx.prepare("UPDATE yhm SET last_poll=? WHERE email=?")?;
+        )?;
+        let mut update_account_stmt = tx.prepare("UPDATE yhm SET last_poll=? WHERE email=?")?;
 
-            for event in events {
-                let email = event.email();
-                update_account_stmt.execute((time, email))?;
-                event_stmt.execute((email, event))?;
-            }
+        for event in events {
+            let email = event.email();
+            update_account_stmt.execute((time, email))?;
+            event_stmt.execute((email, event))?;
+        }
 
-            Ok(())
-        })
+        Ok(())
     }
 
     /// Load all events
@@ -716,14 +1073,20 @@ CREATE TABLE IF NOT EXISTS yhm (
         r"
 CREATE TABLE IF NOT EXISTS yhm_settings (
     id PRIMARY KEY,
-    poll_interval INTEGER NOT NULL DEFAULT 300
+    poll_interval INTEGER NOT NULL DEFAULT 300,
+    quiet_hours TEXT DEFAULT NULL,
+    grouping TEXT DEFAULT NULL
 )
 ",
         (),
     )?;
 
+    // Columns added after the initial release.
+    add_column_if_missing(tx, "yhm_settings", "quiet_hours", "TEXT DEFAULT NULL")?;
+    add_column_if_missing(tx, "yhm_settings", "grouping", "TEXT DEFAULT NULL")?;
+
     tx.execute(
-        "INSERT OR IGNORE INTO yhm_settings VALUES (?,?)",
+        "INSERT OR IGNORE INTO yhm_settings (id, poll_interval) VALUES (?,?)",
         (SETTINGS_ID, DEFAULT_POLL_INTERVAL_SECONDS),
     )?;
 
@@ -738,6 +1101,48 @@ CREATE TABLE IF NOT EXISTS yhm_poll_event (
         (),
     )?;
 
+    tx.execute(
+        r"
+CREATE TABLE IF NOT EXISTS yhm_rules (
+    email TEXT PRIMARY KEY,
+    rules TEXT NOT NULL DEFAULT '[]',
+    suppressed INTEGER NOT NULL DEFAULT 0
+)
+",
+        (),
+    )?;
+
+    tx.execute(
+        r"
+CREATE TABLE IF NOT EXISTS yhm_held_email (
+    email TEXT PRIMARY KEY,
+    emails TEXT NOT NULL
+)
+",
+        (),
+    )?;
+
+    Ok(())
+}
+
+/// Add `column` with `definition` to `table` if the table does not have it yet.
+fn add_column_if_missing(
+    tx: &mut Transaction,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let exists: bool = tx.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name=?",
+        (table, column),
+        |r| r.get(0),
+    )?;
+    if !exists {
+        tx.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            (),
+        )?;
+    }
     Ok(())
 }
 
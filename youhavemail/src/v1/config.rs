@@ -13,6 +13,8 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error("Crypto error occurred: {0}")]
     State(#[from] crate::state::Error),
+    #[error("Account '{0}' uses an https proxy, which is not supported")]
+    UnsupportedProxy(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -40,21 +42,31 @@ pub struct Config {
 
 impl Config {
     /// Convert existing information to v2 accounts.
-    pub fn to_v2_accounts(&self) -> Vec<Converted> {
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an account uses a proxy which is not supported.
+    pub fn to_v2_accounts(&self) -> Result<Vec<Converted>> {
         let mut result = Vec::with_capacity(self.accounts.len());
         for account in &self.accounts {
-            let proxy = account.proxy.clone().map(|v| http::Proxy {
-                protocol: match v.protocol {
-                    ProxyProtocol::Https => http::ProxyProtocol::Http,
-                    ProxyProtocol::Socks5 => http::ProxyProtocol::Socks5,
-                },
-                auth: v.auth.map(|auth| http::ProxyAuth {
-                    username: auth.username,
-                    password: SecretString::new(auth.password.into()),
-                }),
-                host: v.url,
-                port: v.port,
-            });
+            let proxy = account
+                .proxy
+                .clone()
+                .map(|v| -> Result<http::Proxy> {
+                    Ok(http::Proxy {
+                        protocol: v
+                            .protocol
+                            .to_v2()
+                            .ok_or_else(|| Error::UnsupportedProxy(account.email.clone()))?,
+                        auth: v.auth.map(|auth| http::ProxyAuth {
+                            username: auth.username,
+                            password: SecretString::new(auth.password.into()),
+                        }),
+                        host: v.url,
+                        port: v.port,
+                    })
+                })
+                .transpose()?;
 
             result.push(Converted {
                 email: account.email.clone(),
@@ -64,7 +76,7 @@ impl Config {
             });
         }
 
-        result
+        Ok(result)
     }
 }
 
@@ -96,6 +108,17 @@ enum ProxyProtocol {
     Socks5,
 }
 
+impl ProxyProtocol {
+    /// Protocol of the v2 configuration. TLS connections to the proxy itself are not
+    /// supported by the transport, so there is none for https proxies.
+    fn to_v2(self) -> Option<http::ProxyProtocol> {
+        match self {
+            ProxyProtocol::Https => None,
+            ProxyProtocol::Socks5 => Some(http::ProxyProtocol::Socks5),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 struct ProxyAuth {
     pub username: String,
@@ -156,7 +179,7 @@ fn test_config_v1_into_v2() {
     let config_loaded = load(encryption_key.expose_secret(), &config_path).unwrap();
     assert_eq!(config_loaded, config);
 
-    let accounts_v2 = config_loaded.to_v2_accounts();
+    let accounts_v2 = config_loaded.to_v2_accounts().unwrap();
 
     assert_eq!(accounts_v2[0].email, config_loaded.accounts[0].email);
     assert_eq!(accounts_v2[0].backend, crate::backend::proton::NAME);
@@ -166,3 +189,26 @@ fn test_config_v1_into_v2() {
     assert_eq!(accounts_v2[1].backend, crate::backend::proton::NAME);
     assert!(accounts_v2[1].proxy.is_none());
 }
+
+#[test]
+fn test_config_v1_https_proxy_is_rejected() {
+    let config = Config {
+        poll_interval: None,
+        accounts: vec![Account {
+            email: "foo".to_string(),
+            backend: "foo".to_string(),
+            value: None,
+            proxy: Some(Proxy {
+                protocol: ProxyProtocol::Https,
+                auth: None,
+                url: "127.0.0.1".into(),
+                port: 8443,
+            }),
+        }],
+    };
+
+    assert!(matches!(
+        config.to_v2_accounts(),
+        Err(Error::UnsupportedProxy(email)) if email == "foo"
+    ));
+}
@@ -1,12 +1,18 @@
-use crate::backend::{Action, Backend, NewEmail, Poller};
+use crate::backend::{
+    Action, Backend, ConnectionDiagnosis, NewEmail, Poller, Relogin, RemoteSession, UnreadCounts,
+};
 use crate::events::Event;
+use crate::grouping::{EmailGroup, Grouping};
+use crate::notify::{Notification, NotificationSink, SinkScope};
+use crate::rules::{Rule, RuleSet};
+use crate::schedule::{QuietHours, QuietMode};
 use crate::state::{Account, AccountWatcher, Error as StateError, State};
 use http::Proxy;
 use secrecy::ExposeSecret;
 use sqlite_watcher::watcher::DropRemoveTableObserverHandle;
 use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tracing::{Level, debug, error};
 
 /// Conversion trait for new accounts.
@@ -23,6 +29,7 @@ pub trait IntoAccount {
 pub struct Yhm {
     state: Arc<State>,
     backends: Vec<Arc<dyn Backend>>,
+    sinks: Vec<(SinkScope, Arc<dyn NotificationSink>)>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -33,10 +40,22 @@ pub enum Error {
     AccountNotFound(String),
     #[error("Backend '{0}' does not exist")]
     BackendNotFound(String),
+    #[error("Backend '{0}' does not support login")]
+    LoginNotSupported(String),
+    #[error("Account '{0}' belongs to a different user")]
+    UserMismatch(String),
+    #[error("Login has not been completed")]
+    LoginIncomplete,
     #[error("Backend: {0}")]
     Backend(#[from] crate::backend::Error),
     #[error("State: {0}")]
     State(#[from] StateError),
+    #[error("Rules: {0}")]
+    Rules(#[from] crate::rules::Error),
+    #[error("Schedule: {0}")]
+    Schedule(#[from] crate::schedule::Error),
+    #[error("Grouping: {0}")]
+    Grouping(#[from] crate::grouping::Error),
 }
 
 /// Output of polling an account.
@@ -48,12 +67,19 @@ pub struct PollOutput {
     pub backend: String,
     /// Result of the poll process.
     pub result: crate::backend::Result<Vec<NewEmail>>,
+    /// Number of new emails which did not produce a notification due to the account's rules.
+    pub suppressed: usize,
+    /// Whether `result` also contains the emails held during the last quiet hours.
+    pub digest: bool,
+    /// Groups of new emails which should be presented as a single notification, see
+    /// [`Yhm::set_grouping`].
+    pub groups: Vec<EmailGroup>,
 }
 impl Yhm {
     /// Create new instance with the given `state` and a default list of backends.
     #[must_use]
     pub fn new(state: Arc<State>) -> Self {
-        let backends: [Arc<dyn Backend>; 1] = [crate::backend::proton::Backend::new(None)];
+        let backends: [Arc<dyn Backend>; 1] = [crate::backend::proton::Backend::new(None, None)];
         Self::with_backends(state, backends)
     }
 
@@ -65,17 +91,41 @@ impl Yhm {
         Self {
             state,
             backends: Vec::from_iter(backends),
+            sinks: Vec::new(),
         }
     }
 
+    /// Deliver the notifications produced by [`Yhm::poll`] for the accounts in `scope` to
+    /// `sink`.
+    ///
+    /// See [`crate::notify`] for more details.
+    pub fn add_sink(&mut self, scope: SinkScope, sink: Arc<dyn NotificationSink>) {
+        self.sinks.push((scope, sink));
+    }
+
     /// Poll all active accounts and check for new emails.
     ///
+    /// During quiet hours, see [`Yhm::set_quiet_hours`], polling is either skipped or the new
+    /// emails are held back until the quiet hours end.
+    ///
     /// # Errors
     ///
     /// Returns error if the list of accounts can't be loaded from the db. Individual account
     /// errors are returned in the result field.
     #[tracing::instrument(level=Level::DEBUG,skip(self))]
     pub fn poll(&self) -> Result<Vec<PollOutput>, Error> {
+        let quiet_mode = self
+            .state
+            .quiet_hours()?
+            .filter(|quiet_hours| quiet_hours.is_active(chrono::Utc::now()))
+            .map(|quiet_hours| quiet_hours.mode);
+        if quiet_mode == Some(QuietMode::PausePolling) {
+            debug!("Quiet hours, skipping poll");
+            return Ok(Vec::new());
+        }
+        let hold = quiet_mode == Some(QuietMode::HoldNotifications);
+        let grouping = self.state.grouping()?;
+
         let accounts = self.state.active_accounts()?;
         let mut results = Vec::with_capacity(accounts.len());
 
@@ -85,12 +135,41 @@ impl Yhm {
                     debug!("Polling...");
                     let email = account.email().to_owned();
                     let backend = account.backend().to_owned();
-                    let mut account = self.build_account_poller(account)?;
+                    // The rules are applied to each message before the messages of the same
+                    // thread are collapsed.
+                    let (result, suppressed) = match self.build_account_poller(account) {
+                        Ok(mut account) => match account.check() {
+                            Ok(emails) if !emails.is_empty() => {
+                                let (emails, suppressed) = self.apply_rules(&email, emails);
+                                (Ok(account.collapse(emails)), suppressed)
+                            }
+                            result => (result, 0),
+                        },
+                        Err(e @ crate::backend::Error::SessionExpired(_)) => (Err(e), 0),
+                        Err(e) => return Err(e.into()),
+                    };
+
+                    let result = match result {
+                        Ok(emails) if hold && !emails.is_empty() => {
+                            debug!("Quiet hours, holding {} email(s)", emails.len());
+                            match self.state.hold_emails(&email, &emails) {
+                                Ok(()) => Ok(Vec::new()),
+                                Err(e) => {
+                                    error!("Failed to hold emails, delivering them: {e}");
+                                    Ok(emails)
+                                }
+                            }
+                        }
+                        result => result,
+                    };
 
                     Ok(PollOutput {
                         email,
                         backend,
-                        result: account.check(),
+                        result,
+                        suppressed,
+                        digest: false,
+                        groups: Vec::new(),
                     })
                 },
             );
@@ -98,15 +177,92 @@ impl Yhm {
             results.push(result?);
         }
 
-        let events = results.iter().map(Event::new).collect::<Vec<_>>();
-        self.state.create_or_update_events(&events).map_err(|e| {
-            error!("Failed to store result as events: {e}");
-            e
-        })?;
+        // The held emails are only removed together with the events which deliver them.
+        let events = self
+            .state
+            .db_write(|tx| -> Result<Vec<Event>, StateError> {
+                for output in &mut results {
+                    if !hold {
+                        release_held_emails(tx, output);
+                    }
+                    if let (Ok(emails), Some(grouping)) = (&output.result, &grouping) {
+                        output.groups = grouping.group(emails);
+                    }
+                }
+
+                let events = results.iter().map(Event::new).collect::<Vec<_>>();
+                State::store_events(tx, &events)?;
+                Ok(events)
+            })
+            .map_err(|e| {
+                error!("Failed to store result as events: {e}");
+                e
+            })?;
+
+        self.notify_sinks(&events);
 
         Ok(results)
     }
 
+    /// Deliver the notifications for `events` to the registered sinks.
+    fn notify_sinks(&self, events: &[Event]) {
+        if self.sinks.is_empty() {
+            return;
+        }
+
+        for notification in events.iter().filter_map(Notification::from_event) {
+            for (scope, sink) in &self.sinks {
+                if !scope.contains(notification.email()) {
+                    continue;
+                }
+
+                if let Err(e) = sink.notify(&notification) {
+                    error!(
+                        "Failed to notify {} sink for {}: {e}",
+                        sink.name(),
+                        notification.email()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Filter `emails` with the rules of the account with `email` and record the number of
+    /// suppressed emails.
+    ///
+    /// The emails have already been consumed from the backend at this point, so if the rules
+    /// can't be loaded all the emails are kept rather than failing the poll.
+    fn apply_rules(&self, email: &str, emails: Vec<NewEmail>) -> (Vec<NewEmail>, usize) {
+        let rules = match self.state.account_rules(email) {
+            Ok(rules) => rules,
+            Err(e) => {
+                error!("Failed to load rules, skipping: {e}");
+                return (emails, 0);
+            }
+        };
+        let rules = match RuleSet::new(&rules) {
+            Ok(rules) => rules,
+            Err(e) => {
+                error!("Invalid rules, skipping: {e}");
+                return (emails, 0);
+            }
+        };
+
+        if rules.is_empty() {
+            return (emails, 0);
+        }
+
+        let (emails, suppressed) = rules.filter(emails, chrono::Utc::now());
+        if suppressed != 0 {
+            debug!("Suppressed {suppressed} email(s)");
+            if let Err(e) = self.state.add_suppressed_count(email, suppressed as u64) {
+                error!("Failed to record suppressed count: {e}");
+            }
+        }
+
+        (emails, suppressed)
+    }
+
     /// Get the current active backend.
     #[must_use]
     pub fn backends(&self) -> &[Arc<dyn Backend>] {
@@ -182,6 +338,74 @@ impl Yhm {
         })?)
     }
 
+    /// Create a login sequence to re-authenticate the existing account with `email`, e.g.
+    /// after its session expired.
+    ///
+    /// The sequence uses the account's proxy and is created by the account's backend, see
+    /// [`Backend::relogin_sequence`]. Like the poller, it prefers the identity configured on
+    /// the backend over the one stored with the account. Once completed, use
+    /// [`Relogin::into_account`] to update the account, which preserves its state and events.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the account is not found, its backend does not support login or
+    /// the http client could not be created.
+    #[tracing::instrument(level=Level::DEBUG, skip(self))]
+    pub fn relogin(&self, email: &str) -> Result<Box<dyn Relogin>, Error> {
+        let account = self
+            .state
+            .account(email)?
+            .ok_or(Error::AccountNotFound(email.to_owned()))?;
+
+        let backend = self
+            .find_backend(account.backend())
+            .ok_or(Error::BackendNotFound(account.backend().to_owned()))?;
+
+        let client = backend.create_client(account.proxy()?).map_err(|e| {
+            error!("Failed to create client: {e}");
+            e
+        })?;
+
+        match backend.relogin_sequence(client, &account) {
+            Ok(sequence) => Ok(sequence),
+            Err(crate::backend::Error::Unsupported) => {
+                Err(Error::LoginNotSupported(account.backend().to_owned()))
+            }
+            Err(e) => {
+                error!("Failed to create login sequence: {e}");
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Check whether the servers of `backend` can be reached through `proxy`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the backend does not exist. Connection failures are reported through
+    /// the returned [`ConnectionDiagnosis`].
+    #[tracing::instrument(level=Level::DEBUG, skip(self, proxy))]
+    pub fn test_proxy(&self, backend: &str, proxy: Proxy) -> Result<ConnectionDiagnosis, Error> {
+        let backend = self
+            .find_backend(backend)
+            .ok_or(Error::BackendNotFound(backend.to_owned()))?;
+
+        let client = match backend.create_client(Some(proxy)) {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Failed to create client: {e}");
+                return Ok(ConnectionDiagnosis::new(&Err(e), Duration::ZERO, true));
+            }
+        };
+
+        let proxied = client.resolved_proxy().is_some();
+        let start = Instant::now();
+        let result = backend.check_connection(client);
+        let diagnosis = ConnectionDiagnosis::new(&result, start.elapsed(), proxied);
+        debug!("Diagnosis: {diagnosis:?}");
+        Ok(diagnosis)
+    }
+
     /// Get poll interval.
     ///
     /// # Errors
@@ -207,6 +431,67 @@ impl Yhm {
         })?)
     }
 
+    /// Get the quiet hours setting.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the operation failed.
+    pub fn quiet_hours(&self) -> Result<Option<QuietHours>, Error> {
+        Ok(self.state.quiet_hours().map_err(|e| {
+            error!("Failed to get quiet hours:{e}");
+            e
+        })?)
+    }
+
+    /// Set the quiet hours setting. Setting to `None` disables quiet hours.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the schedule is not valid or the operation failed.
+    pub fn set_quiet_hours(&self, quiet_hours: Option<&QuietHours>) -> Result<(), Error> {
+        if let Some(quiet_hours) = quiet_hours {
+            quiet_hours.validate()?;
+        }
+
+        tracing::info!("Quiet hours updated");
+        Ok(self.state.set_quiet_hours(quiet_hours).map_err(|e| {
+            error!("Failed to set quiet hours:{e}");
+            e
+        })?)
+    }
+
+    /// Get the notification grouping setting.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the operation failed.
+    pub fn grouping(&self) -> Result<Option<Grouping>, Error> {
+        Ok(self.state.grouping().map_err(|e| {
+            error!("Failed to get grouping:{e}");
+            e
+        })?)
+    }
+
+    /// Set the notification grouping setting. Setting to `None` disables grouping.
+    ///
+    /// The [`Grouping::min_group_size`] must be at least
+    /// [`MIN_GROUP_SIZE`](crate::grouping::MIN_GROUP_SIZE).
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the settings are not valid or the operation failed.
+    pub fn set_grouping(&self, grouping: Option<&Grouping>) -> Result<(), Error> {
+        if let Some(grouping) = grouping {
+            grouping.validate()?;
+        }
+
+        tracing::info!("Grouping updated");
+        Ok(self.state.set_grouping(grouping).map_err(|e| {
+            error!("Failed to set grouping:{e}");
+            e
+        })?)
+    }
+
     /// Delete an existing account.
     ///
     /// Logout will be attempted, but if the logout fails the account data will still
@@ -268,6 +553,112 @@ impl Yhm {
         })?)
     }
 
+    /// List the sessions of the account with `email` on the backend's servers.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the account is not found, the backend does not support sessions or the
+    /// request failed.
+    #[tracing::instrument(level=Level::DEBUG, skip(self))]
+    pub fn sessions(&self, email: &str) -> Result<Vec<RemoteSession>, Error> {
+        let account = self
+            .state
+            .account(email)?
+            .ok_or(Error::AccountNotFound(email.to_owned()))?;
+
+        let mut account = self.build_account_poller(account)?;
+        Ok(account.sessions().inspect_err(|e| {
+            error!("Failed to list sessions:{e}");
+        })?)
+    }
+
+    /// Revoke the session with `id` of the account with `email`.
+    ///
+    /// The account is not removed locally. If `id` is the session used by the account, the
+    /// account is logged out.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the account is not found, the backend does not support sessions or the
+    /// request failed.
+    #[tracing::instrument(level=Level::DEBUG, skip(self))]
+    pub fn revoke_session(&self, email: &str, id: &str) -> Result<(), Error> {
+        tracing::info!("Revoking session");
+        let account = self
+            .state
+            .account(email)?
+            .ok_or(Error::AccountNotFound(email.to_owned()))?;
+
+        let mut account = self.build_account_poller(account)?;
+        Ok(account.revoke_session(id).inspect_err(|e| {
+            error!("Failed to revoke session:{e}");
+        })?)
+    }
+
+    /// Number of unread messages of the account with `email`, in total and for each folder
+    /// which produces notifications.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the account is not found, the backend does not support unread counts or
+    /// the counts could not be retrieved.
+    #[tracing::instrument(level=Level::DEBUG, skip(self))]
+    pub fn unread_counts(&self, email: &str) -> Result<UnreadCounts, Error> {
+        let account = self
+            .state
+            .account(email)?
+            .ok_or(Error::AccountNotFound(email.to_owned()))?;
+
+        let mut account = self.build_account_poller(account)?;
+        Ok(account.unread_counts().inspect_err(|e| {
+            error!("Failed to get unread counts:{e}");
+        })?)
+    }
+
+    /// Get the notification rules of the account with `email`.
+    ///
+    /// See [`crate::rules`] for more details.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the rules could not be loaded.
+    pub fn rules(&self, email: &str) -> Result<Vec<Rule>, Error> {
+        Ok(self.state.account_rules(email).inspect_err(|e| {
+            error!("Failed to load rules: {e}");
+        })?)
+    }
+
+    /// Replace the notification `rules` of the account with `email`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the account is not found, the rules are not valid or could not be
+    /// stored.
+    #[tracing::instrument(level=Level::DEBUG, skip(self, rules))]
+    pub fn set_rules(&self, email: &str, rules: &[Rule]) -> Result<(), Error> {
+        if !self.state.has_account(email)? {
+            return Err(Error::AccountNotFound(email.to_owned()));
+        }
+
+        RuleSet::new(rules)?;
+        self.state
+            .set_account_rules(email, rules)
+            .inspect_err(|e| {
+                error!("Failed to store rules: {e}");
+            })?;
+        Ok(())
+    }
+
+    /// Total number of new emails of the account with `email` which did not produce a
+    /// notification due to its rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the count could not be loaded.
+    pub fn suppressed_count(&self, email: &str) -> Result<u64, Error> {
+        Ok(self.state.suppressed_count(email)?)
+    }
+
     /// Apply the given `actions` on the account with `email`.
     ///
     /// # Errors
@@ -306,7 +697,10 @@ impl Yhm {
                     error!("Failed to load v1 config: {e}");
                     e
                 })?;
-        let accounts = config.to_v2_accounts();
+        let accounts = config.to_v2_accounts().map_err(|e| {
+            error!("Failed to convert v1 accounts: {e}");
+            e
+        })?;
 
         if let Some(interval) = config.poll_interval.map(Duration::from_secs) {
             self.state.set_poll_interval(interval).map_err(|e| {
@@ -399,3 +793,24 @@ impl Yhm {
         self.state.as_ref()
     }
 }
+
+/// Prepend the emails held during the last quiet hours to the new emails of `output` and turn
+/// it into a digest.
+///
+/// If the held emails can't be loaded, the new emails are delivered on their own and the held
+/// emails are kept for the next poll.
+fn release_held_emails(tx: &crate::db::Transaction, output: &mut PollOutput) {
+    let Ok(emails) = &mut output.result else {
+        return;
+    };
+
+    match State::take_held_emails(tx, &output.email) {
+        Ok(held) if held.is_empty() => {}
+        Ok(mut held) => {
+            held.append(emails);
+            *emails = held;
+            output.digest = true;
+        }
+        Err(e) => error!("Failed to load held emails, delivering new emails only: {e}"),
+    }
+}
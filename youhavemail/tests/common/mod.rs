@@ -1,6 +1,8 @@
 use http::url;
 use proton_api::mocks::mockito;
+use secrecy::SecretBox;
 use sqlite_watcher::watcher::Watcher;
+use std::path::PathBuf;
 use std::sync::Arc;
 use temp_dir::TempDir;
 use you_have_mail_common::backend::Backend;
@@ -14,6 +16,8 @@ pub struct TestCtx {
     _temp_dir: TempDir,
     pub server: mockito::Server,
     pub state: Arc<State>,
+    db_path: PathBuf,
+    encryption_key: SecretBox<Key>,
 }
 
 impl TestCtx {
@@ -24,13 +28,13 @@ impl TestCtx {
         let db_path = dir.path().join("sqlite.db");
         let server = proton_api::mocks::new_server();
         let watcher = Watcher::new().unwrap();
-        let state = State::new(db_path, encryption_key, watcher).unwrap();
+        let state = State::new(db_path.clone(), encryption_key.clone(), watcher).unwrap();
 
         let url = url::Url::parse(&proton_api::mocks::server_url(&server)).unwrap();
         tracing::info!("Mock Server: {}", url.to_string());
 
         let backend: Arc<dyn Backend> =
-            you_have_mail_common::backend::proton::Backend::new(Some(url));
+            you_have_mail_common::backend::proton::Backend::new(Some(url), None);
         let yhm = Yhm::with_backends(Arc::clone(&state), [backend]);
 
         Self {
@@ -38,6 +42,14 @@ impl TestCtx {
             _temp_dir: dir,
             server,
             state,
+            db_path,
+            encryption_key,
         }
     }
+
+    /// Open the database again with its own connections, like another process would.
+    pub fn other_process_state(&self) -> Arc<State> {
+        let watcher = Watcher::new().unwrap();
+        State::new(self.db_path.clone(), self.encryption_key.clone(), watcher).unwrap()
+    }
 }
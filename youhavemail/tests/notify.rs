@@ -0,0 +1,244 @@
+use http::url::Url;
+use proton_api::mocks::mockito::{Matcher, Server};
+use serde_json::json;
+use you_have_mail_common::backend::NewEmail;
+use you_have_mail_common::events::Event;
+use you_have_mail_common::notify::matrix::MatrixSink;
+use you_have_mail_common::notify::push::{GotifySink, NtfySink, UnifiedPushSink};
+use you_have_mail_common::notify::webhook::WebhookSink;
+use you_have_mail_common::notify::{Notification, NotificationSink};
+
+const ACCOUNT_EMAIL: &str = "foo@bar.com";
+
+fn new_email_event() -> Event {
+    Event::NewEmail {
+        email: ACCOUNT_EMAIL.to_owned(),
+        backend: "backend".to_owned(),
+        emails: vec![NewEmail::new("Alice", "alice@bar.com", "Hello \"there\"")],
+        suppressed: 0,
+        groups: Vec::new(),
+    }
+}
+
+fn server_url(server: &Server, path: &str) -> Url {
+    Url::parse(&proton_api::mocks::server_url(server))
+        .unwrap()
+        .join(path)
+        .unwrap()
+}
+
+#[test]
+fn webhook_sink() {
+    let mut server = proton_api::mocks::new_server();
+    let mock = server
+        .mock("POST", "/hook")
+        .match_header("Content-Type", "application/json")
+        .match_header("Authorization", "Secret")
+        .match_body(Matcher::Json(json!({
+            "text": "1 new email for foo@bar.com",
+            "detail": "Alice: Hello \"there\"",
+            "count": 1,
+        })))
+        .with_status(200)
+        .create();
+
+    let sink = WebhookSink::with_template(
+        server_url(&server, "hook"),
+        None,
+        r#"{"text":"{{title}}","detail":"{{body}}","count":{{count}}}"#,
+    )
+    .unwrap()
+    .authorization("Secret");
+
+    let event = new_email_event();
+    sink.notify(&Notification::from_event(&event).unwrap())
+        .unwrap();
+    mock.assert();
+}
+
+#[test]
+fn webhook_render_single_pass() {
+    let sink = WebhookSink::with_template(
+        Url::parse("http://localhost/hook").unwrap(),
+        None,
+        r#"{"body":"{{body}}","other":"{{other}}","brace":"{{{email}}"}"#,
+    )
+    .unwrap();
+
+    let mut event = new_email_event();
+    if let Event::NewEmail { emails, .. } = &mut event {
+        emails[0].subject = "x{{event}}".to_owned();
+    }
+
+    assert_eq!(
+        sink.render(&Notification::from_event(&event).unwrap()),
+        r#"{"body":"Alice: x{{event}}","other":"{{other}}","brace":"{foo@bar.com"}"#
+    );
+}
+
+#[test]
+fn webhook_sink_error() {
+    let mut server = proton_api::mocks::new_server();
+    let mock = server.mock("POST", "/hook").with_status(500).create();
+
+    let sink = WebhookSink::new(server_url(&server, "hook"), None).unwrap();
+
+    let event = new_email_event();
+    assert!(
+        sink.notify(&Notification::from_event(&event).unwrap())
+            .is_err()
+    );
+    mock.assert();
+}
+
+#[test]
+fn ntfy_sink() {
+    let mut server = proton_api::mocks::new_server();
+    let mock = server
+        .mock("POST", "/yhm")
+        .match_header("Title", "1 new email for foo@bar.com")
+        .match_header("Authorization", "Bearer token")
+        .match_body("Alice: Hello \"there\"")
+        .with_status(200)
+        .create();
+
+    let sink = NtfySink::new(server_url(&server, ""), None, "yhm")
+        .unwrap()
+        .token("token");
+
+    let event = new_email_event();
+    sink.notify(&Notification::from_event(&event).unwrap())
+        .unwrap();
+    mock.assert();
+}
+
+#[test]
+fn gotify_sink() {
+    let mut server = proton_api::mocks::new_server();
+    let mock = server
+        .mock("POST", "/message")
+        .match_header("X-Gotify-Key", "app-token")
+        .match_body(Matcher::Json(json!({
+            "title": "1 new email for foo@bar.com",
+            "message": "Alice: Hello \"there\"",
+            "priority": 8,
+        })))
+        .with_status(200)
+        .create();
+
+    let sink = GotifySink::new(server_url(&server, ""), None, "app-token")
+        .unwrap()
+        .priority(8);
+
+    let event = new_email_event();
+    sink.notify(&Notification::from_event(&event).unwrap())
+        .unwrap();
+    mock.assert();
+}
+
+#[test]
+fn unified_push_sink() {
+    let mut server = proton_api::mocks::new_server();
+    let mock = server
+        .mock("POST", "/push/endpoint")
+        .match_body(Matcher::Json(json!({
+            "title": "1 new email for foo@bar.com",
+            "body": "Alice: Hello \"there\"",
+            "email": ACCOUNT_EMAIL,
+        })))
+        .with_status(201)
+        .create();
+
+    let sink = UnifiedPushSink::new(server_url(&server, "push/endpoint"), None).unwrap();
+
+    let event = new_email_event();
+    sink.notify(&Notification::from_event(&event).unwrap())
+        .unwrap();
+    mock.assert();
+}
+
+#[test]
+fn matrix_sink() {
+    let mut server = proton_api::mocks::new_server();
+    let mock = server
+        .mock(
+            "PUT",
+            Matcher::Regex(
+                r"^/_matrix/client/v3/rooms/%21room%3Aexample\.org/send/m\.room\.message/yhm\d+-\d+$"
+                    .to_owned(),
+            ),
+        )
+        .match_header("Authorization", "Bearer access-token")
+        .match_body(Matcher::Json(json!({
+            "msgtype": "m.text",
+            "body": "1 new email for foo@bar.com\nAlice: Hello \"there\"",
+        })))
+        .with_status(200)
+        .with_body(r#"{"event_id":"$event"}"#)
+        .expect(2)
+        .create();
+
+    let sink = MatrixSink::new(
+        server_url(&server, ""),
+        None,
+        "access-token",
+        "!room:example.org",
+    )
+    .unwrap();
+
+    let event = new_email_event();
+    let notification = Notification::from_event(&event).unwrap();
+    sink.notify(&notification).unwrap();
+    sink.notify(&notification).unwrap();
+    mock.assert();
+}
+
+#[cfg(all(unix, feature = "sink-command"))]
+#[test]
+fn command_sink() {
+    use you_have_mail_common::notify::command::CommandSink;
+
+    let dir = temp_dir::TempDir::with_prefix("yhm_notify").unwrap();
+    let output = dir.path().join("output");
+
+    let sink = CommandSink::new("sh")
+        .arg("-c")
+        .arg(r#"printf '%s|%s|%s' "$YHM_TITLE" "$YHM_EMAIL" "$YHM_COUNT" > "$0""#)
+        .arg(&output);
+
+    let event = new_email_event();
+    sink.notify(&Notification::from_event(&event).unwrap())
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&output).unwrap(),
+        "1 new email for foo@bar.com|foo@bar.com|1"
+    );
+
+    let failing = CommandSink::new("sh")
+        .arg("-c")
+        .arg("echo failed >&2; exit 3");
+    let err = failing
+        .notify(&Notification::from_event(&event).unwrap())
+        .unwrap_err();
+    assert!(err.to_string().contains("failed"));
+}
+
+#[cfg(all(unix, feature = "sink-command"))]
+#[test]
+fn command_sink_timeout() {
+    use std::time::{Duration, Instant};
+    use you_have_mail_common::notify::command::CommandSink;
+
+    let sink = CommandSink::new("sh")
+        .arg("-c")
+        .arg("sleep 10")
+        .timeout(Duration::from_millis(200));
+
+    let start = Instant::now();
+    let event = new_email_event();
+    let err = sink
+        .notify(&Notification::from_event(&event).unwrap())
+        .unwrap_err();
+    assert!(err.to_string().contains("timed out"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
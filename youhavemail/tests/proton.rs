@@ -1,16 +1,34 @@
 mod common;
 
 use crate::common::TestCtx;
+use parking_lot::Mutex;
 use proton_api::auth::{Auth, RefreshToken, Token, Uid};
-use proton_api::domain::event::MoreEvents;
-use proton_api::domain::{Boolean, SecretString, event, label, message};
+use proton_api::domain::event::{MoreEvents, RefreshFlags};
+use proton_api::domain::{Boolean, SecretString, conversation, event, label, message};
+use proton_api::mocks::auth::MatchExtension;
 use proton_api::requests::{
-    OperationResponse, PutLabelMessageResponse, PutMarkMessageReadResponse,
+    GetEventRequest, OperationResponse, PutLabelConversationResponse, PutLabelMessageResponse,
+    PutMarkConversationReadResponse, PutMarkMessageReadResponse,
 };
+use proton_api::session::AppIdentity;
 use secrecy::ExposeSecret;
-use you_have_mail_common::backend::proton::{AccountAction, TaskState};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use you_have_mail_common::backend::Backend as _;
+use you_have_mail_common::backend::proton::{
+    AccountAction, Backend as ProtonBackend, NotifyRules, ReloginSequence, TaskState,
+};
+use you_have_mail_common::backend::{
+    ConnectionDiagnosis, Error as BackendError, ErrorKind, LogoutReason,
+};
 use you_have_mail_common::events::Event;
-use you_have_mail_common::yhm::IntoAccount;
+use you_have_mail_common::grouping::{EmailGroup, GroupBy, Grouping};
+use you_have_mail_common::notify::{Notification, NotificationSink, SinkScope};
+use you_have_mail_common::rules::{Condition, Rule, RuleAction};
+use you_have_mail_common::schedule::{QuietHours, QuietMode, Timezone, Window};
+use you_have_mail_common::yhm::{Error as YhmError, IntoAccount, PollOutput};
 
 #[test]
 fn login_sequence() {
@@ -41,7 +59,14 @@ fn login_sequence() {
 
     assert_eq!(ctx.yhm.account_count().unwrap(), 1);
 
-    assert!(account_state(&ctx).is_none());
+    let state = account_state(&ctx).unwrap();
+    assert!(state.last_event_id.is_none());
+    assert!(state.base_url.is_none());
+    assert_eq!(
+        state.user_id.as_deref(),
+        Some(proton_api::mocks::auth::USER_ID)
+    );
+    assert_eq!(state.identity, Some(AppIdentity::default()));
     let auth = account_auth(&ctx).unwrap();
 
     assert_eq!(
@@ -58,6 +83,204 @@ fn login_sequence() {
     );
 }
 
+#[test]
+fn login_sequence_export_and_resume() {
+    let mut ctx = TestCtx::new();
+    let url = http::url::Url::parse(&proton_api::mocks::server_url(&ctx.server)).unwrap();
+    let backend = you_have_mail_common::backend::proton::Backend::new(Some(url), None);
+
+    let client = backend.create_client(None).unwrap();
+    let session = proton_api::session::Session::with_in_memory_auth_store(client);
+    let mut sequence = proton_api::login::Sequence::without_server_proof_check(session);
+
+    let _auth_mocks = proton_api::mocks::auth::login_flow(&mut ctx.server, true);
+    sequence
+        .login(
+            proton_api::mocks::DEFAULT_USER_EMAIL,
+            proton_api::mocks::DEFAULT_USER_PASSWORD,
+            None,
+        )
+        .unwrap();
+    assert!(sequence.is_awaiting_totp());
+
+    let blob = you_have_mail_common::backend::proton::Backend::export_login_sequence(
+        &sequence, &ctx.state,
+    )
+    .unwrap();
+    drop(sequence);
+
+    let mut sequence = backend.resume_login_sequence(&blob, &ctx.state).unwrap();
+    assert!(sequence.is_awaiting_totp());
+    sequence
+        .submit_totp(proton_api::mocks::auth::TFA_CODE)
+        .unwrap();
+    sequence.into_account(&ctx.yhm).unwrap();
+
+    assert_eq!(ctx.yhm.account_count().unwrap(), 1);
+    let auth = account_auth(&ctx).unwrap();
+    assert_eq!(
+        auth.auth_token.0.expose_secret(),
+        proton_api::mocks::auth::ACCESS_TOKEN
+    );
+}
+
+#[test]
+fn login_sequence_two_password_mode() {
+    let mut ctx = TestCtx::new();
+    let url = http::url::Url::parse(&proton_api::mocks::server_url(&ctx.server)).unwrap();
+    let backend = you_have_mail_common::backend::proton::Backend::new(Some(url), None);
+
+    let client = backend.create_client(None).unwrap();
+    let session = proton_api::session::Session::with_in_memory_auth_store(client);
+    let mut sequence = proton_api::login::Sequence::without_server_proof_check(session);
+
+    let _auth_mocks = [
+        proton_api::mocks::auth::auth_info(&mut ctx.server),
+        proton_api::mocks::auth::auth_response_two_password(&mut ctx.server),
+        // The user is fetched again after resuming the sequence.
+        proton_api::mocks::auth::user_info_two_password(&mut ctx.server).expect(2),
+        proton_api::mocks::auth::key_salts(&mut ctx.server).expect(2),
+    ];
+    sequence
+        .login(
+            proton_api::mocks::DEFAULT_USER_EMAIL,
+            proton_api::mocks::DEFAULT_USER_PASSWORD,
+            None,
+        )
+        .unwrap();
+    assert!(sequence.is_awaiting_mailbox_password());
+
+    let blob = you_have_mail_common::backend::proton::Backend::export_login_sequence(
+        &sequence, &ctx.state,
+    )
+    .unwrap();
+    assert!(matches!(
+        sequence.into_account(&ctx.yhm),
+        Err(YhmError::LoginIncomplete)
+    ));
+    assert_eq!(ctx.yhm.account_count().unwrap(), 0);
+
+    let mut sequence = backend.resume_login_sequence(&blob, &ctx.state).unwrap();
+    assert!(sequence.is_awaiting_mailbox_password());
+    assert!(matches!(
+        sequence.submit_mailbox_password("wrong"),
+        Err(proton_api::login::Error::InvalidMailboxPassword)
+    ));
+    sequence
+        .submit_mailbox_password(proton_api::mocks::auth::MAILBOX_PASSWORD)
+        .unwrap();
+    sequence.into_account(&ctx.yhm).unwrap();
+
+    assert_eq!(ctx.yhm.account_count().unwrap(), 1);
+    let auth = account_auth(&ctx).unwrap();
+    assert_eq!(
+        auth.auth_token.0.expose_secret(),
+        proton_api::mocks::auth::ACCESS_TOKEN
+    );
+}
+
+#[test]
+fn relogin_logged_out_account() {
+    let mut ctx = TestCtx::new();
+    let state = TaskState {
+        user_id: Some(proton_api::mocks::auth::USER_ID.to_owned()),
+        ..TaskState::with_event_id(event_id(1))
+    };
+    create_authenticated_account(&ctx, Some(state));
+    let account = ctx.yhm.account(ACCOUNT_EMAIL).unwrap().unwrap();
+    account.set_secret::<Auth>(None).unwrap();
+    assert!(account.is_logged_out().unwrap());
+
+    let mut relogin = ctx.yhm.relogin(ACCOUNT_EMAIL).unwrap();
+    assert_eq!(relogin.email(), ACCOUNT_EMAIL);
+    let proton_relogin = relogin.downcast_mut::<ReloginSequence>().unwrap();
+    let session = proton_relogin.sequence().session().clone();
+    *proton_relogin.sequence_mut() =
+        proton_api::login::Sequence::without_server_proof_check(session);
+    {
+        let _auth_mocks = proton_api::mocks::auth::login_flow(&mut ctx.server, false);
+        proton_relogin
+            .sequence_mut()
+            .login(
+                proton_api::mocks::DEFAULT_USER_EMAIL,
+                proton_api::mocks::DEFAULT_USER_PASSWORD,
+                None,
+            )
+            .unwrap();
+        relogin.into_account(&ctx.yhm).unwrap();
+    }
+
+    assert_eq!(ctx.yhm.account_count().unwrap(), 1);
+    assert!(account_auth(&ctx).is_some());
+    let state = account_state(&ctx).unwrap();
+    assert_eq!(state.last_event_id, Some(event_id(1)));
+    assert_eq!(
+        state.user_id.as_deref(),
+        Some(proton_api::mocks::auth::USER_ID)
+    );
+}
+
+#[test]
+fn relogin_prefers_configured_identity() {
+    let ctx = TestCtx::new();
+    let stored = AppIdentity::new("linux-stored@1.0.0", "Stored/1.0.0");
+    let configured = AppIdentity::new("linux-configured@1.0.0", "Configured/1.0.0");
+    let state = TaskState {
+        identity: Some(stored.clone()),
+        ..TaskState::new()
+    };
+    create_authenticated_account(&ctx, Some(state));
+
+    let mut relogin = ctx.yhm.relogin(ACCOUNT_EMAIL).unwrap();
+    let proton_relogin = relogin.downcast_mut::<ReloginSequence>().unwrap();
+    assert_eq!(proton_relogin.sequence().session().identity(), &stored);
+
+    let url = http::url::Url::parse(&proton_api::mocks::server_url(&ctx.server)).unwrap();
+    let backend: Arc<dyn you_have_mail_common::backend::Backend> =
+        ProtonBackend::new(Some(url), Some(configured.clone()));
+    let yhm = you_have_mail_common::yhm::Yhm::with_backends(Arc::clone(&ctx.state), [backend]);
+    let mut relogin = yhm.relogin(ACCOUNT_EMAIL).unwrap();
+    let proton_relogin = relogin.downcast_mut::<ReloginSequence>().unwrap();
+    assert_eq!(proton_relogin.sequence().session().identity(), &configured);
+}
+
+#[test]
+fn relogin_rejects_different_user() {
+    let mut ctx = TestCtx::new();
+    let state = TaskState {
+        user_id: Some("other-user".to_owned()),
+        ..TaskState::with_event_id(event_id(1))
+    };
+    create_authenticated_account(&ctx, Some(state));
+    let account = ctx.yhm.account(ACCOUNT_EMAIL).unwrap().unwrap();
+    account.set_secret::<Auth>(None).unwrap();
+
+    let mut relogin = ctx.yhm.relogin(ACCOUNT_EMAIL).unwrap();
+    let proton_relogin = relogin.downcast_mut::<ReloginSequence>().unwrap();
+    let session = proton_relogin.sequence().session().clone();
+    *proton_relogin.sequence_mut() =
+        proton_api::login::Sequence::without_server_proof_check(session);
+    {
+        let _auth_mocks = proton_api::mocks::auth::login_flow(&mut ctx.server, false);
+        let _logout_mock = proton_api::mocks::auth::logout(&mut ctx.server);
+        proton_relogin
+            .sequence_mut()
+            .login(
+                proton_api::mocks::DEFAULT_USER_EMAIL,
+                proton_api::mocks::DEFAULT_USER_PASSWORD,
+                None,
+            )
+            .unwrap();
+        assert!(matches!(
+            relogin.into_account(&ctx.yhm),
+            Err(you_have_mail_common::yhm::Error::UserMismatch(_))
+        ));
+    }
+
+    assert!(account_auth(&ctx).is_none());
+    assert!(ctx.yhm.relogin("unknown@proton.me").is_err());
+}
+
 #[test]
 fn remove_old_account_without_domain_suffix() {
     // Login after migration for an account with domain suffix (foo) should be removed and
@@ -100,15 +323,11 @@ fn poll_sequence() {
     create_authenticated_account(&ctx, None);
 
     let event_id = event_id(1);
-    let event = event::Event {
-        event_id: event_id.clone(),
-        more: MoreEvents::No,
-        messages: None,
-        labels: None,
-    };
+    let event = empty_event(&event_id);
 
     let label_id_with_notification = label::Id("label".to_owned());
     let label_id_without_notification = label::Id("label_silent".to_owned());
+    let custom_label_id = label::Id("custom_label".to_owned());
 
     let labels = vec![
         label::Label {
@@ -138,6 +357,19 @@ fn poll_sequence() {
             order: 0,
         },
     ];
+    let custom_labels = vec![label::Label {
+        id: custom_label_id.clone(),
+        parent_id: None,
+        name: String::new(),
+        path: String::new(),
+        color: String::new(),
+        label_type: label::Type::Label,
+        notify: Boolean::True,
+        display: Boolean::default(),
+        sticky: Boolean::default(),
+        expanded: Boolean::default(),
+        order: 0,
+    }];
 
     assert_eq!(
         ctx.yhm
@@ -155,6 +387,11 @@ fn poll_sequence() {
             proton_api::mocks::events::get_latest_event_id(&mut ctx.server, event_id.clone());
         let _mock_labels =
             proton_api::mocks::labels::get_labels(&mut ctx.server, label::Type::Folder, &labels);
+        let _mock_custom_labels = proton_api::mocks::labels::get_labels(
+            &mut ctx.server,
+            label::Type::Label,
+            &custom_labels,
+        );
 
         let _mock_event =
             proton_api::mocks::events::get_event(&mut ctx.server, &event.event_id, &event);
@@ -167,15 +404,16 @@ fn poll_sequence() {
         let new_emails = output.result.unwrap();
         assert!(new_emails.is_empty());
 
-        // state should now be saved and have the extra notifiable folder.
+        // state should now be saved and have the extra notifiable folder and label.
         let state = account_state(&ctx).expect("account should have state");
         assert_eq!(state.last_event_id, Some(event_id.clone()));
-        assert_eq!(state.active_folder_ids.len(), 2);
+        assert_eq!(state.active_folder_ids.len(), 3);
         assert!(
             state
                 .active_folder_ids
                 .contains(&label_id_with_notification)
         );
+        assert!(state.active_folder_ids.contains(&custom_label_id));
         assert!(state.active_folder_ids.contains(&label::Id::inbox()));
     }
 
@@ -185,6 +423,8 @@ fn poll_sequence() {
             email: ACCOUNT_EMAIL.to_owned(),
             backend: you_have_mail_common::backend::proton::NAME.to_owned(),
             emails: vec![],
+            suppressed: 0,
+            groups: vec![],
         })
     );
 
@@ -203,12 +443,13 @@ fn poll_sequence() {
         // No changes have been made to the state.
         let state = account_state(&ctx).expect("account should have state");
         assert_eq!(state.last_event_id, Some(event_id));
-        assert_eq!(state.active_folder_ids.len(), 2);
+        assert_eq!(state.active_folder_ids.len(), 3);
         assert!(
             state
                 .active_folder_ids
                 .contains(&label_id_with_notification)
         );
+        assert!(state.active_folder_ids.contains(&custom_label_id));
         assert!(state.active_folder_ids.contains(&label::Id::inbox()));
     }
 
@@ -218,6 +459,8 @@ fn poll_sequence() {
             email: ACCOUNT_EMAIL.to_owned(),
             backend: you_have_mail_common::backend::proton::NAME.to_owned(),
             emails: vec![],
+            suppressed: 0,
+            groups: vec![],
         })
     );
 }
@@ -233,25 +476,16 @@ fn poll_event_loop() {
     let event_id3 = event_id(3);
 
     let event_1 = event::Event {
-        event_id: event_id1.clone(),
         more: MoreEvents::Yes,
-        messages: None,
-        labels: None,
+        ..empty_event(&event_id1)
     };
 
     let event_2 = event::Event {
-        event_id: event_id2.clone(),
         more: MoreEvents::Yes,
-        messages: None,
-        labels: None,
+        ..empty_event(&event_id2)
     };
 
-    let event_3 = event::Event {
-        event_id: event_id3.clone(),
-        more: MoreEvents::No,
-        messages: None,
-        labels: None,
-    };
+    let event_3 = empty_event(&event_id3);
 
     create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
 
@@ -285,40 +519,15 @@ fn message_event_creates_notification() {
     let subject = "hello world!".to_owned();
     let sender_address = "bar@proton.me".to_owned();
 
-    let event_1 = event::Event {
-        event_id: event_id1.clone(),
-        more: MoreEvents::No,
-        messages: Some(vec![event::Message {
-            id: message_id.clone(),
-            action: event::Action::Create,
-            message: Some(message::Message {
-                id: message_id.clone(),
-                labels: vec![label::Id::inbox()],
-                subject: subject.clone(),
-                sender_address: sender_address.clone(),
-                sender_name: None,
-                unread: Boolean::True,
-            }),
-        }]),
-        labels: None,
-    };
-
-    let event_loop_exit = event::Event {
-        event_id: event_id1.clone(),
-        more: MoreEvents::No,
-        messages: None,
-        labels: None,
-    };
+    let event_1 = message_event(
+        &event_id1,
+        vec![new_message(&message_id.0, &sender_address, None)],
+    );
 
     create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
 
     {
-        let _event_1_mock =
-            proton_api::mocks::events::get_event(&mut ctx.server, &event_id0, &event_1);
-        let _event_2_mock =
-            proton_api::mocks::events::get_event(&mut ctx.server, &event_id1, &event_loop_exit);
-
-        let output = ctx.yhm.poll().unwrap().remove(0);
+        let output = poll_with_event(&mut ctx, &event_id0, &event_1);
         let info = output.result.unwrap();
         assert!(!info.is_empty());
         assert_eq!(info[0].subject, subject);
@@ -342,6 +551,8 @@ fn message_event_creates_notification() {
                 email: ACCOUNT_EMAIL.to_owned(),
                 backend: you_have_mail_common::backend::proton::NAME.to_owned(),
                 emails: info,
+                suppressed: 0,
+                groups: vec![],
             })
         );
     }
@@ -351,148 +562,1205 @@ fn message_event_creates_notification() {
 }
 
 #[test]
-fn no_poll_after_logout() {
+fn rules_suppress_notifications() {
     let mut ctx = TestCtx::new();
-    create_authenticated_account(&ctx, None);
+
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    let event_1 = message_event(
+        &event_id1,
+        vec![
+            new_message("message1", "bar@proton.me", None),
+            new_message("message2", "news@spam.org", None),
+        ],
+    );
+
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    let invalid = [Rule::new(
+        Condition::Subject("(".to_owned()),
+        RuleAction::Suppress,
+    )];
+    assert!(matches!(
+        ctx.yhm.set_rules(ACCOUNT_EMAIL, &invalid),
+        Err(YhmError::Rules(_))
+    ));
+    assert!(ctx.yhm.rules(ACCOUNT_EMAIL).unwrap().is_empty());
+
+    let rules = [Rule::deny_senders(["spam.org"])];
+    ctx.yhm.set_rules(ACCOUNT_EMAIL, &rules).unwrap();
+    assert_eq!(ctx.yhm.rules(ACCOUNT_EMAIL).unwrap(), rules);
+    assert_eq!(ctx.yhm.suppressed_count(ACCOUNT_EMAIL).unwrap(), 0);
 
     {
-        let _mock = proton_api::mocks::auth::logout(&mut ctx.server);
-        ctx.yhm.logout(ACCOUNT_EMAIL).unwrap();
-    }
+        let output = poll_with_event(&mut ctx, &event_id0, &event_1);
+        assert_eq!(output.suppressed, 1);
+        let info = output.result.unwrap();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].sender_address, "bar@proton.me");
+        assert_eq!(info[0].labels, vec![label::Id::inbox().to_string()]);
 
-    assert_eq!(ctx.yhm.account_count().unwrap(), 1);
-    assert!(ctx.yhm.poll().unwrap().is_empty());
-    assert_eq!(
-        ctx.yhm
-            .account(ACCOUNT_EMAIL)
-            .unwrap()
-            .unwrap()
-            .last_event()
-            .unwrap(),
-        None,
-    );
-}
+        assert!(matches!(
+            account_event(&ctx),
+            Some(Event::NewEmail { suppressed: 1, .. })
+        ));
+    }
 
-#[test]
-fn no_poll_after_delete() {
-    let mut ctx = TestCtx::new();
-    create_authenticated_account(&ctx, None);
+    assert_eq!(ctx.yhm.suppressed_count(ACCOUNT_EMAIL).unwrap(), 1);
 
+    // Rules which can't be loaded don't lose the emails which were already consumed.
+    ctx.state
+        .db_write(|tx| -> rusqlite::Result<_> {
+            tx.execute(
+                "UPDATE yhm_rules SET rules='garbage' WHERE email=?",
+                [ACCOUNT_EMAIL],
+            )
+        })
+        .unwrap();
     {
-        let _mock = proton_api::mocks::auth::logout(&mut ctx.server);
-        ctx.yhm.delete(ACCOUNT_EMAIL).unwrap();
+        let event_2 = message_event(
+            &event_id(2),
+            vec![new_message("message3", "news@spam.org", None)],
+        );
+        let output = poll_with_event(&mut ctx, &event_id1, &event_2);
+        assert_eq!(output.suppressed, 0);
+        let info = output.result.unwrap();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].sender_address, "news@spam.org");
     }
 
-    assert!(ctx.yhm.poll().unwrap().is_empty());
-    assert_eq!(ctx.yhm.account_count().unwrap(), 0);
+    ctx.yhm.delete_without_logout(ACCOUNT_EMAIL).unwrap();
+    assert!(ctx.yhm.rules(ACCOUNT_EMAIL).unwrap().is_empty());
+    assert_eq!(ctx.yhm.suppressed_count(ACCOUNT_EMAIL).unwrap(), 0);
 }
 
 #[test]
-fn mark_message_read_action() {
-    // check event loop logic,
+fn rules_apply_to_messages_before_collapsing() {
     let mut ctx = TestCtx::new();
-    create_authenticated_account(&ctx, Some(TaskState::new()));
-
-    let id = message::Id("message".to_owned());
-
-    let action = AccountAction::MarkMessageRead(id.clone()).to_action();
 
-    let _mock = proton_api::mocks::message::mark_message_read(
-        &mut ctx.server,
-        vec![id.clone()],
-        &PutMarkMessageReadResponse {
-            responses: vec![OperationResponse::ok(id.clone())],
-        },
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    // A message of a VIP sender followed by a reply of another sender.
+    let event_1 = message_event(
+        &event_id1,
+        vec![
+            new_message("message1", "boss@vip.com", Some("conversation")),
+            new_message("message2", "colleague@proton.me", Some("conversation")),
+        ],
     );
 
-    ctx.yhm.apply_actions(ACCOUNT_EMAIL, [action]).unwrap()
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+    ctx.yhm
+        .set_rules(ACCOUNT_EMAIL, &[Rule::only_senders(["vip.com"])])
+        .unwrap();
+
+    let output = poll_with_event(&mut ctx, &event_id0, &event_1);
+    assert_eq!(output.suppressed, 1);
+    let info = output.result.unwrap();
+    assert_eq!(info.len(), 1);
+    assert_eq!(info[0].sender_address, "boss@vip.com");
+    assert_eq!(info[0].message_count, 1);
 }
 
 #[test]
-fn move_to_trash_action() {
-    // check event loop logic,
+fn poll_groups_emails_by_sender() {
     let mut ctx = TestCtx::new();
-    create_authenticated_account(&ctx, Some(TaskState::new()));
 
-    let id = message::Id("message".to_owned());
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    let new_message = |id, conversation_id| new_message(id, "bar@proton.me", Some(conversation_id));
+    let event_1 = message_event(
+        &event_id1,
+        vec![
+            new_message("message1", "conversation1"),
+            new_message("message2", "conversation2"),
+            new_message("message3", "conversation1"),
+        ],
+    );
 
-    let action = AccountAction::MoveMessageToTrash(id.clone()).to_action();
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
 
-    let _mock = proton_api::mocks::message::label_message(
-        &mut ctx.server,
-        label::Id::trash(),
-        vec![id.clone()],
-        &PutLabelMessageResponse {
-            responses: vec![OperationResponse::ok(id.clone())],
-        },
+    let grouping = Grouping {
+        group_by: GroupBy::Sender,
+        min_group_size: 2,
+        collapse_all_at: None,
+    };
+    assert_eq!(ctx.yhm.grouping().unwrap(), None);
+    let invalid = Grouping {
+        min_group_size: 1,
+        ..grouping.clone()
+    };
+    assert!(matches!(
+        ctx.yhm.set_grouping(Some(&invalid)),
+        Err(YhmError::Grouping(_))
+    ));
+    assert_eq!(ctx.yhm.grouping().unwrap(), None);
+    ctx.yhm.set_grouping(Some(&grouping)).unwrap();
+    assert_eq!(ctx.yhm.grouping().unwrap(), Some(grouping));
+
+    let output = poll_with_event(&mut ctx, &event_id0, &event_1);
+    let expected_groups = vec![EmailGroup {
+        title: "bar@proton.me".to_owned(),
+        summary: "3 new messages from bar@proton.me".to_owned(),
+        emails: vec![0, 1],
+    }];
+    assert_eq!(output.groups, expected_groups);
+    // Messages of the same conversation are collapsed into a single email.
+    let info = output.result.unwrap();
+    assert_eq!(info.len(), 2);
+    assert_eq!(info[0].thread_id.as_deref(), Some("conversation1"));
+    assert_eq!(info[0].message_count, 2);
+    assert_eq!(
+        info[0].mark_as_read_action,
+        Some(
+            AccountAction::MarkConversationRead(conversation::Id("conversation1".to_owned()))
+                .to_action()
+        )
+    );
+    assert_eq!(info[1].thread_id.as_deref(), Some("conversation2"));
+    assert_eq!(
+        info[1].mark_as_read_action,
+        Some(AccountAction::MarkMessageRead(message::Id("message2".to_owned())).to_action())
     );
 
-    ctx.yhm.apply_actions(ACCOUNT_EMAIL, [action]).unwrap()
+    assert!(matches!(
+        account_event(&ctx),
+        Some(Event::NewEmail { groups, .. }) if groups == expected_groups
+    ));
+}
+
+#[derive(Default)]
+struct CollectSink {
+    notifications: Mutex<Vec<(String, String)>>,
+}
+
+impl NotificationSink for CollectSink {
+    fn name(&self) -> &'static str {
+        "collect"
+    }
+
+    fn notify(&self, notification: &Notification<'_>) -> you_have_mail_common::notify::Result<()> {
+        self.notifications
+            .lock()
+            .push((notification.email().to_owned(), notification.title.clone()));
+        Ok(())
+    }
 }
 
 #[test]
-fn move_to_spam_action() {
-    // check event loop logic,
+fn poll_notifies_sinks() {
     let mut ctx = TestCtx::new();
-    create_authenticated_account(&ctx, Some(TaskState::new()));
 
-    let id = message::Id("message".to_owned());
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    let event_1 = message_event(
+        &event_id1,
+        vec![new_message("message1", "bar@proton.me", None)],
+    );
 
-    let action = AccountAction::MoveMessageToSpam(id.clone()).to_action();
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
 
-    let _mock = proton_api::mocks::message::label_message(
-        &mut ctx.server,
-        label::Id::spam(),
-        vec![id.clone()],
-        &PutLabelMessageResponse {
-            responses: vec![OperationResponse::ok(id.clone())],
-        },
+    let global = Arc::new(CollectSink::default());
+    let account = Arc::new(CollectSink::default());
+    let other_account = Arc::new(CollectSink::default());
+    ctx.yhm.add_sink(SinkScope::Global, global.clone());
+    ctx.yhm.add_sink(
+        SinkScope::Account(ACCOUNT_EMAIL.to_owned()),
+        account.clone(),
+    );
+    ctx.yhm.add_sink(
+        SinkScope::Account("other@proton.me".to_owned()),
+        other_account.clone(),
     );
 
-    ctx.yhm.apply_actions(ACCOUNT_EMAIL, [action]).unwrap()
+    poll_with_event(&mut ctx, &event_id0, &event_1);
+
+    let expected = vec![(
+        ACCOUNT_EMAIL.to_owned(),
+        format!("1 new email for {ACCOUNT_EMAIL}"),
+    )];
+    assert_eq!(*global.notifications.lock(), expected);
+    assert_eq!(*account.notifications.lock(), expected);
+    assert!(other_account.notifications.lock().is_empty());
+
+    // Polls without new emails do not produce notifications.
+    let _event_mock =
+        proton_api::mocks::events::get_event(&mut ctx.server, &event_id1, &empty_event(&event_id1));
+    ctx.yhm.poll().unwrap();
+    assert_eq!(global.notifications.lock().len(), 1);
 }
 
-fn create_authenticated_account(ctx: &TestCtx, state: Option<TaskState>) {
-    let account = ctx
-        .yhm
-        .new_account(ACCOUNT_EMAIL, you_have_mail_common::backend::proton::NAME)
-        .expect("Failed to create account");
-    let auth = proton_api::auth::Auth {
-        uid: Uid(proton_api::mocks::session_id().to_owned()),
-        auth_token: Token(SecretString::new(
-            proton_api::mocks::auth::ACCESS_TOKEN.to_owned().into(),
-        )),
-        refresh_token: RefreshToken(SecretString::new(
-            proton_api::mocks::auth::REFRESH_TOKEN.to_owned().into(),
-        )),
-    };
+#[test]
+fn quiet_hours_hold_notifications_until_digest() {
+    let mut ctx = TestCtx::new();
 
-    account.set_state(state.as_ref()).unwrap();
-    account.set_secret(Some(&auth)).unwrap();
-}
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    let event_1 = message_event(
+        &event_id1,
+        vec![new_message("message", "bar@proton.me", None)],
+    );
+    let event_loop_exit = empty_event(&event_id1);
 
-fn create_v1_suffixless_account(ctx: &TestCtx) {
-    ctx.yhm
-        .new_account(
-            ACCOUNT_EMAIL_NO_SUFFIX,
-            you_have_mail_common::backend::proton::NAME,
-        )
-        .expect("Failed to create account");
-}
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
 
-fn account_state(ctx: &TestCtx) -> Option<TaskState> {
-    let account = ctx
-        .state
-        .account(ACCOUNT_EMAIL)
-        .unwrap()
-        .expect("failed to find account");
-    account.state::<TaskState>().unwrap()
-}
+    let quiet_hours = always_quiet(QuietMode::HoldNotifications);
+    ctx.yhm.set_quiet_hours(Some(&quiet_hours)).unwrap();
+    assert_eq!(ctx.yhm.quiet_hours().unwrap(), Some(quiet_hours));
 
-fn account_auth(ctx: &TestCtx) -> Option<Auth> {
-    let account = ctx
-        .state
-        .account(ACCOUNT_EMAIL)
+    // Emails are held during quiet hours.
+    {
+        let output = poll_with_event(&mut ctx, &event_id0, &event_1);
+        assert!(!output.digest);
+        assert!(output.result.unwrap().is_empty());
+        assert_eq!(
+            account_event(&ctx),
+            Some(Event::NewEmail {
+                email: ACCOUNT_EMAIL.to_owned(),
+                backend: you_have_mail_common::backend::proton::NAME.to_owned(),
+                emails: vec![],
+                suppressed: 0,
+                groups: vec![],
+            })
+        );
+    }
+
+    // Held emails are kept if the digest can't be stored.
+    ctx.yhm.set_quiet_hours(None).unwrap();
+    ctx.state
+        .db_write(|tx| -> rusqlite::Result<_> {
+            tx.execute(
+                "CREATE TRIGGER fail_poll_event BEFORE INSERT ON yhm_poll_event \
+                 BEGIN SELECT RAISE(ABORT, 'failed'); END",
+                (),
+            )
+        })
+        .unwrap();
+    {
+        let _event_mock =
+            proton_api::mocks::events::get_event(&mut ctx.server, &event_id1, &event_loop_exit);
+
+        assert!(ctx.yhm.poll().is_err());
+    }
+    ctx.state
+        .db_write(|tx| -> rusqlite::Result<_> { tx.execute("DROP TRIGGER fail_poll_event", ()) })
+        .unwrap();
+
+    // Held emails are delivered once the quiet hours end.
+    {
+        let _event_mock =
+            proton_api::mocks::events::get_event(&mut ctx.server, &event_id1, &event_loop_exit);
+
+        let output = ctx.yhm.poll().unwrap().remove(0);
+        assert!(output.digest);
+        let info = output.result.unwrap();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].subject, "hello world!");
+        assert_eq!(
+            account_event(&ctx),
+            Some(Event::Digest {
+                email: ACCOUNT_EMAIL.to_owned(),
+                backend: you_have_mail_common::backend::proton::NAME.to_owned(),
+                emails: info,
+                groups: vec![],
+            })
+        );
+    }
+
+    // Digest is only delivered once.
+    {
+        let _event_mock =
+            proton_api::mocks::events::get_event(&mut ctx.server, &event_id1, &event_loop_exit);
+
+        let output = ctx.yhm.poll().unwrap().remove(0);
+        assert!(!output.digest);
+        assert!(output.result.unwrap().is_empty());
+    }
+}
+
+#[test]
+fn quiet_hours_failures_deliver_new_emails() {
+    let mut ctx = TestCtx::new();
+
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    let event_1 = message_event(
+        &event_id1,
+        vec![new_message("message", "bar@proton.me", None)],
+    );
+
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    // Emails which can't be held are delivered right away.
+    ctx.yhm
+        .set_quiet_hours(Some(&always_quiet(QuietMode::HoldNotifications)))
+        .unwrap();
+    ctx.state
+        .db_write(|tx| -> rusqlite::Result<_> {
+            tx.execute(
+                "CREATE TRIGGER fail_hold BEFORE INSERT ON yhm_held_email \
+                 BEGIN SELECT RAISE(ABORT, 'failed'); END",
+                (),
+            )
+        })
+        .unwrap();
+    {
+        let output = poll_with_event(&mut ctx, &event_id0, &event_1);
+        assert!(!output.digest);
+        assert_eq!(output.result.unwrap().len(), 1);
+        assert!(matches!(
+            account_event(&ctx),
+            Some(Event::NewEmail { emails, .. }) if emails.len() == 1
+        ));
+    }
+
+    // New emails are delivered if the held emails can't be loaded.
+    ctx.yhm.set_quiet_hours(None).unwrap();
+    ctx.state
+        .db_write(|tx| -> rusqlite::Result<_> {
+            tx.execute("DROP TRIGGER fail_hold", ())?;
+            tx.execute(
+                "INSERT INTO yhm_held_email (email, emails) VALUES (?, 'garbage')",
+                [ACCOUNT_EMAIL],
+            )
+        })
+        .unwrap();
+    {
+        let event_2 = event::Event {
+            event_id: event_id(2),
+            ..event_1
+        };
+        let output = poll_with_event(&mut ctx, &event_id1, &event_2);
+        assert!(!output.digest);
+        assert_eq!(output.result.unwrap().len(), 1);
+        assert!(matches!(
+            account_event(&ctx),
+            Some(Event::NewEmail { emails, .. }) if emails.len() == 1
+        ));
+    }
+}
+
+#[test]
+fn quiet_hours_pause_polling() {
+    let ctx = TestCtx::new();
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id(0))));
+
+    let invalid = QuietHours {
+        timezone: Timezone::Offset(i32::MAX),
+        ..always_quiet(QuietMode::PausePolling)
+    };
+    assert!(matches!(
+        ctx.yhm.set_quiet_hours(Some(&invalid)),
+        Err(YhmError::Schedule(_))
+    ));
+    assert_eq!(ctx.yhm.quiet_hours().unwrap(), None);
+
+    // No server mocks, any request would fail the poll.
+    ctx.yhm
+        .set_quiet_hours(Some(&always_quiet(QuietMode::PausePolling)))
+        .unwrap();
+    assert!(ctx.yhm.poll().unwrap().is_empty());
+    assert_eq!(account_event(&ctx), None);
+}
+
+#[test]
+fn revoked_session_logs_out_account() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    {
+        let url = format!("/{}", http::Request::url(&GetEventRequest::new(&event_id0)));
+        let _event_mock = ctx
+            .server
+            .mock("GET", url.as_str())
+            .with_status(401)
+            .expect(2)
+            .create();
+        let _refresh_mock =
+            proton_api::mocks::auth::auth_refresh_rejected(&mut ctx.server, 10013).expect(2);
+
+        // The first rejection may be caused by another process rotating the token.
+        let output = ctx.yhm.poll().unwrap().remove(0);
+        assert_eq!(
+            output.result.unwrap_err().kind(),
+            Some(ErrorKind::RefreshRejected)
+        );
+        assert!(account_auth(&ctx).unwrap().refresh_rejected);
+
+        let output = ctx.yhm.poll().unwrap().remove(0);
+        assert!(matches!(
+            output.result,
+            Err(BackendError::SessionExpired(LogoutReason::SessionRevoked))
+        ));
+    }
+
+    assert!(account_auth(&ctx).is_none());
+    assert_eq!(
+        account_event(&ctx),
+        Some(Event::LoggedOut {
+            email: ACCOUNT_EMAIL.to_owned(),
+            reason: LogoutReason::SessionRevoked,
+        })
+    );
+}
+
+#[test]
+fn poll_uses_stored_identity() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    let identity = AppIdentity::new("linux-test@1.0.0", "Test/1.0.0");
+    let state = TaskState {
+        identity: Some(identity.clone()),
+        ..TaskState::with_event_id(event_id0.clone())
+    };
+    create_authenticated_account(&ctx, Some(state));
+
+    let event = empty_event(&event_id0);
+    let url = format!("/{}", http::Request::url(&GetEventRequest::new(&event_id0)));
+    let _event_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .match_header("X-Pm-Appversion", identity.app_version.as_str())
+        .match_header("User-Agent", identity.user_agent.as_str())
+        .with_status(200)
+        .with_body(serde_json::to_vec(&event).unwrap())
+        .create();
+
+    let output = ctx.yhm.poll().unwrap().remove(0);
+    assert!(output.result.unwrap().is_empty());
+    let state = account_state(&ctx).expect("account should have state");
+    assert_eq!(state.identity, Some(identity));
+}
+
+#[test]
+fn unread_counts_updated_from_events() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    let folder_id = label::Id("my_folder".to_owned());
+    let mut state = TaskState::with_event_id(event_id0.clone());
+    state.active_folder_ids.insert(folder_id.clone());
+    create_authenticated_account(&ctx, Some(state));
+
+    let count = |label_id: label::Id, unread: u32| message::MessageCount {
+        label_id,
+        total: 10,
+        unread,
+    };
+
+    {
+        let _mock = proton_api::mocks::message::get_message_counts(
+            &mut ctx.server,
+            &[
+                count(label::Id::inbox(), 3),
+                count(folder_id.clone(), 2),
+                count(label::Id::spam(), 7),
+            ],
+        );
+        let counts = ctx.yhm.unread_counts(ACCOUNT_EMAIL).unwrap();
+        assert_eq!(counts.total, 5);
+        assert_eq!(counts.folders.len(), 2);
+        assert_eq!(counts.folders[&label::Id::inbox().0], 3);
+        assert_eq!(counts.folders[&folder_id.0], 2);
+    }
+
+    let event_1 = event::Event {
+        message_counts: Some(vec![count(label::Id::inbox(), 4)]),
+        ..empty_event(&event_id1)
+    };
+    let output = poll_with_event(&mut ctx, &event_id0, &event_1);
+    assert!(output.result.unwrap().is_empty());
+
+    // Counts are served from the account state without contacting the server.
+    let counts = ctx.yhm.unread_counts(ACCOUNT_EMAIL).unwrap();
+    assert_eq!(counts.total, 6);
+    assert_eq!(counts.folders[&label::Id::inbox().0], 4);
+    assert_eq!(counts.folders[&folder_id.0], 2);
+}
+
+#[test]
+fn refresh_event_resyncs_folders() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    let stale_folder_id = label::Id("stale_folder".to_owned());
+    let folder_id = label::Id("my_folder".to_owned());
+    let mut state = TaskState::with_event_id(event_id0.clone());
+    state.active_folder_ids.insert(stale_folder_id);
+    create_authenticated_account(&ctx, Some(state));
+
+    let refresh_event = event::Event {
+        refresh: RefreshFlags::MAIL,
+        ..empty_event(&event_id(2))
+    };
+    let folders = [label::Label {
+        id: folder_id.clone(),
+        parent_id: None,
+        name: "Folder".to_owned(),
+        path: "Folder".to_owned(),
+        color: String::new(),
+        label_type: label::Type::Folder,
+        notify: Boolean::True,
+        display: Boolean::default(),
+        sticky: Boolean::default(),
+        expanded: Boolean::default(),
+        order: 0,
+    }];
+
+    {
+        let _event_mock =
+            proton_api::mocks::events::get_event(&mut ctx.server, &event_id0, &refresh_event);
+        let _latest_mock =
+            proton_api::mocks::events::get_latest_event_id(&mut ctx.server, event_id1.clone());
+        let _labels_mock =
+            proton_api::mocks::labels::get_labels(&mut ctx.server, label::Type::Folder, &folders);
+        let _custom_labels_mock =
+            proton_api::mocks::labels::get_labels(&mut ctx.server, label::Type::Label, &[]);
+        let output = ctx.yhm.poll().unwrap().remove(0);
+        assert!(output.result.unwrap().is_empty());
+    }
+
+    let state = account_state(&ctx).expect("account should have state");
+    assert_eq!(state.last_event_id, Some(event_id1));
+    assert_eq!(
+        state.active_folder_ids,
+        HashSet::from([label::Id::inbox(), folder_id])
+    );
+}
+
+#[test]
+fn notify_rules_changed_during_poll_are_kept() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    let event_id1 = event_id(1);
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    let backend = ctx
+        .yhm
+        .backend_with_name(you_have_mail_common::backend::proton::NAME)
+        .unwrap()
+        .clone();
+    let account = ctx.state.account(ACCOUNT_EMAIL).unwrap().unwrap();
+    let client = backend.create_client(None).unwrap();
+    let mut poller = backend.new_poller(client, account).unwrap();
+
+    let rules = NotifyRules {
+        include: HashSet::from([label::Id::starred()]),
+        exclude: HashSet::new(),
+    };
+    ProtonBackend::set_notify_rules(&ctx.yhm, ACCOUNT_EMAIL, rules.clone()).unwrap();
+
+    let event = empty_event(&event_id1);
+    {
+        let _event_mock = proton_api::mocks::events::get_event(&mut ctx.server, &event_id0, &event);
+        let _exit_mock = proton_api::mocks::events::get_event(&mut ctx.server, &event_id1, &event);
+        assert!(poller.check().unwrap().is_empty());
+    }
+
+    let state = account_state(&ctx).expect("account should have state");
+    assert_eq!(state.last_event_id, Some(event_id1));
+    assert_eq!(state.notify_rules, rules);
+    assert_eq!(
+        ProtonBackend::notify_rules(&ctx.yhm, ACCOUNT_EMAIL).unwrap(),
+        rules
+    );
+}
+
+#[test]
+fn poll_uses_tokens_refreshed_by_other_process() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    let backend = ctx
+        .yhm
+        .backend_with_name(you_have_mail_common::backend::proton::NAME)
+        .unwrap()
+        .clone();
+    let account = ctx.state.account(ACCOUNT_EMAIL).unwrap().unwrap();
+    let client = backend.create_client(None).unwrap();
+    let mut poller = backend.new_poller(client, account.clone()).unwrap();
+
+    // Another process refreshes the session after the poller was created.
+    account.set_secret(Some(&post_refresh_auth())).unwrap();
+
+    let event = empty_event(&event_id0);
+    let url = format!("/{}", http::Request::url(&GetEventRequest::new(&event_id0)));
+    let _expired_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .match_auth()
+        .with_status(401)
+        .create();
+    let _event_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .match_auth_refreshed()
+        .with_status(200)
+        .with_body(serde_json::to_vec(&event).unwrap())
+        .create();
+    let refresh_mock = proton_api::mocks::auth::auth_refresh(&mut ctx.server).expect(0);
+
+    assert!(poller.check().unwrap().is_empty());
+    refresh_mock.assert();
+    let auth = account_auth(&ctx).unwrap();
+    assert_eq!(
+        auth.refresh_token.0.expose_secret(),
+        proton_api::mocks::auth::POST_REFRESH_REFRESH_TOKEN
+    );
+}
+
+#[test]
+fn concurrent_refresh_by_other_process() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    let backend = ctx
+        .yhm
+        .backend_with_name(you_have_mail_common::backend::proton::NAME)
+        .unwrap()
+        .clone();
+    let account = ctx.state.account(ACCOUNT_EMAIL).unwrap().unwrap();
+    let client = backend.create_client(None).unwrap();
+    let mut poller = backend.new_poller(client, account).unwrap();
+
+    let event = empty_event(&event_id0);
+    let url = format!("/{}", http::Request::url(&GetEventRequest::new(&event_id0)));
+    let _expired_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .match_auth()
+        .with_status(401)
+        .create();
+    let _event_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .match_auth_refreshed()
+        .with_status(200)
+        .with_body(serde_json::to_vec(&event).unwrap())
+        .create();
+
+    // While the refresh request is in flight, another process refreshes the session with the
+    // same refresh token first, so the server rejects this refresh.
+    let other_state = ctx.other_process_state();
+    let stored_by_other = Arc::new(AtomicBool::new(false));
+    let stored_by_other_copy = Arc::clone(&stored_by_other);
+    let refresh_mock = ctx
+        .server
+        .mock("POST", "/auth/v4/refresh")
+        .with_status(422)
+        .with_header("Content-Type", "application/json")
+        .with_body_from_request(move |_| {
+            let other_state = Arc::clone(&other_state);
+            let (sender, receiver) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                let account = other_state.account(ACCOUNT_EMAIL).unwrap().unwrap();
+                let _ = sender.send(account.set_secret(Some(&post_refresh_auth())).is_ok());
+            });
+            // The database must not be locked by the refreshing process.
+            if receiver.recv_timeout(Duration::from_secs(10)) == Ok(true) {
+                stored_by_other_copy.store(true, Ordering::SeqCst);
+            }
+            br#"{"Code":10013,"Error":"Invalid refresh token"}"#.to_vec()
+        })
+        .create();
+
+    assert!(poller.check().unwrap().is_empty());
+    refresh_mock.assert();
+    assert!(stored_by_other.load(Ordering::SeqCst));
+    let auth = account_auth(&ctx).unwrap();
+    assert_eq!(
+        auth.refresh_token.0.expose_secret(),
+        proton_api::mocks::auth::POST_REFRESH_REFRESH_TOKEN
+    );
+}
+
+#[test]
+fn refresh_rejected_before_other_process_stores_token() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    let event = empty_event(&event_id0);
+    let url = format!("/{}", http::Request::url(&GetEventRequest::new(&event_id0)));
+    let _expired_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .match_auth()
+        .with_status(401)
+        .create();
+    let _event_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .match_auth_refreshed()
+        .with_status(200)
+        .with_body(serde_json::to_vec(&event).unwrap())
+        .create();
+    let refresh_mock = proton_api::mocks::auth::auth_refresh_rejected(&mut ctx.server, 10013);
+
+    // Another process rotated the token, but has not stored the new one yet.
+    let output = ctx.yhm.poll().unwrap().remove(0);
+    assert_eq!(
+        output.result.unwrap_err().kind(),
+        Some(ErrorKind::RefreshRejected)
+    );
+    refresh_mock.assert();
+    assert!(account_auth(&ctx).unwrap().refresh_rejected);
+
+    ctx.other_process_state()
+        .account(ACCOUNT_EMAIL)
+        .unwrap()
+        .unwrap()
+        .set_secret(Some(&post_refresh_auth()))
+        .unwrap();
+
+    let output = ctx.yhm.poll().unwrap().remove(0);
+    assert!(output.result.unwrap().is_empty());
+    let auth = account_auth(&ctx).unwrap();
+    assert!(!auth.refresh_rejected);
+    assert_eq!(
+        auth.refresh_token.0.expose_secret(),
+        proton_api::mocks::auth::POST_REFRESH_REFRESH_TOKEN
+    );
+}
+
+#[test]
+fn rate_limited_poll() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    let url = format!("/{}", http::Request::url(&GetEventRequest::new(&event_id0)));
+    let _event_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .with_status(429)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"Code":2028,"Error":"Too many recent requests"}"#)
+        .create();
+
+    let output = ctx.yhm.poll().unwrap().remove(0);
+    assert!(matches!(output.result, Err(BackendError::RateLimited)));
+    assert!(account_auth(&ctx).is_some());
+}
+
+#[test]
+fn api_error_poll_reports_kind() {
+    let mut ctx = TestCtx::new();
+    let event_id0 = event_id(0);
+    create_authenticated_account(&ctx, Some(TaskState::with_event_id(event_id0.clone())));
+
+    let url = format!("/{}", http::Request::url(&GetEventRequest::new(&event_id0)));
+    let _event_mock = ctx
+        .server
+        .mock("GET", url.as_str())
+        .with_status(422)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"Code":2001,"Error":"Invalid value"}"#)
+        .create();
+
+    let output = ctx.yhm.poll().unwrap().remove(0);
+    let err = output.result.unwrap_err();
+    assert!(matches!(&err, BackendError::Api(e) if e.code == 2001));
+    assert_eq!(err.kind(), Some(ErrorKind::InvalidValue));
+    assert!(account_auth(&ctx).is_some());
+}
+
+#[test]
+fn undecryptable_secret_logs_out_account() {
+    let ctx = TestCtx::new();
+    create_authenticated_account(&ctx, None);
+    ctx.state
+        .db_write(|tx| -> rusqlite::Result<_> {
+            tx.execute(
+                "UPDATE yhm SET secret=? WHERE email=?",
+                (b"garbage".as_slice(), ACCOUNT_EMAIL),
+            )
+        })
+        .unwrap();
+
+    let output = ctx.yhm.poll().unwrap().remove(0);
+    assert!(matches!(
+        output.result,
+        Err(BackendError::SessionExpired(
+            LogoutReason::SecretUnavailable
+        ))
+    ));
+    assert!(account_auth(&ctx).is_none());
+    assert_eq!(
+        account_event(&ctx),
+        Some(Event::LoggedOut {
+            email: ACCOUNT_EMAIL.to_owned(),
+            reason: LogoutReason::SecretUnavailable,
+        })
+    );
+}
+
+#[test]
+fn no_poll_after_logout() {
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, None);
+
+    {
+        let _mock = proton_api::mocks::auth::logout(&mut ctx.server);
+        ctx.yhm.logout(ACCOUNT_EMAIL).unwrap();
+    }
+
+    assert_eq!(ctx.yhm.account_count().unwrap(), 1);
+    assert!(ctx.yhm.poll().unwrap().is_empty());
+    assert_eq!(
+        ctx.yhm
+            .account(ACCOUNT_EMAIL)
+            .unwrap()
+            .unwrap()
+            .last_event()
+            .unwrap(),
+        None,
+    );
+}
+
+#[test]
+fn no_poll_after_delete() {
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, None);
+
+    {
+        let _mock = proton_api::mocks::auth::logout(&mut ctx.server);
+        ctx.yhm.delete(ACCOUNT_EMAIL).unwrap();
+    }
+
+    assert!(ctx.yhm.poll().unwrap().is_empty());
+    assert_eq!(ctx.yhm.account_count().unwrap(), 0);
+}
+
+#[test]
+fn list_and_revoke_sessions() {
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, None);
+
+    {
+        let _mock = proton_api::mocks::auth::auth_sessions(&mut ctx.server);
+        let sessions = ctx.yhm.sessions(ACCOUNT_EMAIL).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions[0].current);
+        assert_eq!(sessions[0].id, proton_api::mocks::auth::SESSION_UID);
+        assert!(!sessions[1].current);
+        assert!(sessions[1].revocable);
+        assert_eq!(sessions[1].id, proton_api::mocks::auth::OTHER_SESSION_UID);
+        assert_eq!(sessions[1].client_name, "Proton Mail web");
+    }
+
+    {
+        let mock = proton_api::mocks::auth::revoke_auth_session(
+            &mut ctx.server,
+            proton_api::mocks::auth::OTHER_SESSION_UID,
+        );
+        ctx.yhm
+            .revoke_session(ACCOUNT_EMAIL, proton_api::mocks::auth::OTHER_SESSION_UID)
+            .unwrap();
+        mock.assert();
+    }
+
+    assert!(account_auth(&ctx).is_some());
+    assert_eq!(ctx.yhm.account_count().unwrap(), 1);
+}
+
+#[test]
+fn revoke_current_session_logs_out_account() {
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, None);
+
+    {
+        let _mock = proton_api::mocks::auth::logout(&mut ctx.server);
+        ctx.yhm
+            .revoke_session(ACCOUNT_EMAIL, proton_api::mocks::auth::SESSION_UID)
+            .unwrap();
+    }
+
+    assert!(account_auth(&ctx).is_none());
+    assert_eq!(ctx.yhm.account_count().unwrap(), 1);
+    assert!(ctx.yhm.poll().unwrap().is_empty());
+}
+
+#[test]
+fn mark_message_read_action() {
+    // check event loop logic,
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, Some(TaskState::new()));
+
+    let id = message::Id("message".to_owned());
+
+    let action = AccountAction::MarkMessageRead(id.clone()).to_action();
+
+    let _mock = proton_api::mocks::message::mark_message_read(
+        &mut ctx.server,
+        vec![id.clone()],
+        &PutMarkMessageReadResponse {
+            responses: vec![OperationResponse::ok(id.clone())],
+        },
+    );
+
+    ctx.yhm.apply_actions(ACCOUNT_EMAIL, [action]).unwrap()
+}
+
+#[test]
+fn move_to_trash_action() {
+    // check event loop logic,
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, Some(TaskState::new()));
+
+    let id = message::Id("message".to_owned());
+
+    let action = AccountAction::MoveMessageToTrash(id.clone()).to_action();
+
+    let _mock = proton_api::mocks::message::label_message(
+        &mut ctx.server,
+        label::Id::trash(),
+        vec![id.clone()],
+        &PutLabelMessageResponse {
+            responses: vec![OperationResponse::ok(id.clone())],
+        },
+    );
+
+    ctx.yhm.apply_actions(ACCOUNT_EMAIL, [action]).unwrap()
+}
+
+#[test]
+fn move_to_spam_action() {
+    // check event loop logic,
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, Some(TaskState::new()));
+
+    let id = message::Id("message".to_owned());
+
+    let action = AccountAction::MoveMessageToSpam(id.clone()).to_action();
+
+    let _mock = proton_api::mocks::message::label_message(
+        &mut ctx.server,
+        label::Id::spam(),
+        vec![id.clone()],
+        &PutLabelMessageResponse {
+            responses: vec![OperationResponse::ok(id.clone())],
+        },
+    );
+
+    ctx.yhm.apply_actions(ACCOUNT_EMAIL, [action]).unwrap()
+}
+
+#[test]
+fn mark_conversation_read_action() {
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, Some(TaskState::new()));
+
+    let id = conversation::Id("conversation".to_owned());
+
+    let action = AccountAction::MarkConversationRead(id.clone()).to_action();
+
+    let _mock = proton_api::mocks::message::mark_conversation_read(
+        &mut ctx.server,
+        vec![id.clone()],
+        &PutMarkConversationReadResponse {
+            responses: vec![OperationResponse::ok(id.clone())],
+        },
+    );
+
+    ctx.yhm.apply_actions(ACCOUNT_EMAIL, [action]).unwrap();
+}
+
+#[test]
+fn move_conversation_to_trash_action() {
+    let mut ctx = TestCtx::new();
+    create_authenticated_account(&ctx, Some(TaskState::new()));
+
+    let id = conversation::Id("conversation".to_owned());
+
+    let action = AccountAction::MoveConversationToTrash(id.clone()).to_action();
+
+    let _mock = proton_api::mocks::message::label_conversation(
+        &mut ctx.server,
+        label::Id::trash(),
+        vec![id.clone()],
+        &PutLabelConversationResponse {
+            responses: vec![OperationResponse::ok(id.clone())],
+        },
+    );
+
+    ctx.yhm.apply_actions(ACCOUNT_EMAIL, [action]).unwrap();
+}
+
+#[test]
+fn test_proxy_diagnosis() {
+    let mut ctx = TestCtx::new();
+    let proxy_port = ctx.server.socket_address().port();
+    let proxy = |host: &str, port: u16| http::Proxy {
+        protocol: http::ProxyProtocol::Http,
+        auth: None,
+        host: host.to_owned(),
+        port,
+    };
+
+    // The mock server acts as both the http proxy and the api server.
+    {
+        let _ping_mock = ctx
+            .server
+            .mock("GET", "/tests/ping")
+            .with_status(200)
+            .create();
+        let diagnosis = ctx
+            .yhm
+            .test_proxy(
+                you_have_mail_common::backend::proton::NAME,
+                proxy("127.0.0.1", proxy_port),
+            )
+            .unwrap();
+        assert!(matches!(diagnosis, ConnectionDiagnosis::Ok { .. }));
+    }
+
+    {
+        let _ping_mock = ctx
+            .server
+            .mock("GET", "/tests/ping")
+            .with_status(407)
+            .create();
+        let diagnosis = ctx
+            .yhm
+            .test_proxy(
+                you_have_mail_common::backend::proton::NAME,
+                proxy("127.0.0.1", proxy_port),
+            )
+            .unwrap();
+        assert!(matches!(diagnosis, ConnectionDiagnosis::AuthRejected(_)));
+    }
+
+    {
+        let _ping_mock = ctx
+            .server
+            .mock("GET", "/tests/ping")
+            .with_status(503)
+            .create();
+        let diagnosis = ctx
+            .yhm
+            .test_proxy(
+                you_have_mail_common::backend::proton::NAME,
+                proxy("127.0.0.1", proxy_port),
+            )
+            .unwrap();
+        assert!(matches!(diagnosis, ConnectionDiagnosis::Status(503, _)));
+    }
+
+    let diagnosis = ctx
+        .yhm
+        .test_proxy(
+            you_have_mail_common::backend::proton::NAME,
+            proxy("127.0.0.1", 1),
+        )
+        .unwrap();
+    assert!(matches!(diagnosis, ConnectionDiagnosis::ProxyConnect(_)));
+
+    let diagnosis = ctx
+        .yhm
+        .test_proxy(
+            you_have_mail_common::backend::proton::NAME,
+            proxy("proxy.invalid", 8080),
+        )
+        .unwrap();
+    assert!(matches!(diagnosis, ConnectionDiagnosis::Dns(_)));
+
+    assert!(
+        ctx.yhm
+            .test_proxy("Unknown", proxy("127.0.0.1", 1))
+            .is_err()
+    );
+}
+
+fn always_quiet(mode: QuietMode) -> QuietHours {
+    let midnight = chrono::NaiveTime::MIN;
+    QuietHours {
+        timezone: Timezone::Utc,
+        mode,
+        windows: [
+            chrono::Weekday::Mon,
+            chrono::Weekday::Tue,
+            chrono::Weekday::Wed,
+            chrono::Weekday::Thu,
+            chrono::Weekday::Fri,
+            chrono::Weekday::Sat,
+            chrono::Weekday::Sun,
+        ]
+        .into_iter()
+        .map(|weekday| Window {
+            weekday,
+            start: midnight,
+            end: midnight,
+        })
+        .collect(),
+    }
+}
+
+fn create_authenticated_account(ctx: &TestCtx, state: Option<TaskState>) {
+    let account = ctx
+        .yhm
+        .new_account(ACCOUNT_EMAIL, you_have_mail_common::backend::proton::NAME)
+        .expect("Failed to create account");
+    let auth = proton_api::auth::Auth {
+        uid: Uid(proton_api::mocks::session_id().to_owned()),
+        auth_token: Token(SecretString::new(
+            proton_api::mocks::auth::ACCESS_TOKEN.to_owned().into(),
+        )),
+        refresh_token: RefreshToken(SecretString::new(
+            proton_api::mocks::auth::REFRESH_TOKEN.to_owned().into(),
+        )),
+        expires_at: None,
+        refresh_rejected: false,
+    };
+
+    account.set_state(state.as_ref()).unwrap();
+    account.set_secret(Some(&auth)).unwrap();
+}
+
+/// Authentication data after the session was refreshed.
+fn post_refresh_auth() -> Auth {
+    Auth {
+        uid: Uid(proton_api::mocks::session_id().to_owned()),
+        auth_token: Token(SecretString::new(
+            proton_api::mocks::auth::POST_REFRESH_ACCESS_TOKEN
+                .to_owned()
+                .into(),
+        )),
+        refresh_token: RefreshToken(SecretString::new(
+            proton_api::mocks::auth::POST_REFRESH_REFRESH_TOKEN
+                .to_owned()
+                .into(),
+        )),
+        expires_at: None,
+        refresh_rejected: false,
+    }
+}
+
+fn create_v1_suffixless_account(ctx: &TestCtx) {
+    ctx.yhm
+        .new_account(
+            ACCOUNT_EMAIL_NO_SUFFIX,
+            you_have_mail_common::backend::proton::NAME,
+        )
+        .expect("Failed to create account");
+}
+
+fn account_state(ctx: &TestCtx) -> Option<TaskState> {
+    let account = ctx
+        .state
+        .account(ACCOUNT_EMAIL)
+        .unwrap()
+        .expect("failed to find account");
+    account.state::<TaskState>().unwrap()
+}
+
+fn account_auth(ctx: &TestCtx) -> Option<Auth> {
+    let account = ctx
+        .state
+        .account(ACCOUNT_EMAIL)
         .unwrap()
         .expect("failed to find account");
     account.secret::<Auth>().unwrap()
@@ -511,5 +1779,63 @@ fn event_id(id: u32) -> event::Id {
     event::Id(id.to_string())
 }
 
+/// Event `id` without any changes.
+fn empty_event(id: &event::Id) -> event::Event {
+    event::Event {
+        event_id: id.clone(),
+        more: MoreEvents::No,
+        refresh: RefreshFlags::NONE,
+        messages: None,
+        labels: None,
+        message_counts: None,
+        user: None,
+        addresses: None,
+        used_space: None,
+    }
+}
+
+/// Event `id` which only contains `messages`.
+fn message_event(id: &event::Id, messages: Vec<event::Message>) -> event::Event {
+    event::Event {
+        messages: Some(messages),
+        ..empty_event(id)
+    }
+}
+
+/// Creation of an unread message in the inbox.
+fn new_message(id: &str, sender_address: &str, conversation_id: Option<&str>) -> event::Message {
+    let id = message::Id(id.to_owned());
+    event::Message {
+        id: id.clone(),
+        action: event::Action::Create,
+        message: Some(message::Message {
+            id,
+            labels: vec![label::Id::inbox()],
+            subject: "hello world!".to_owned(),
+            sender_address: sender_address.to_owned(),
+            sender_name: None,
+            unread: Boolean::True,
+            conversation_id: conversation_id.map(|id| conversation::Id(id.to_owned())),
+            time: 0,
+        }),
+    }
+}
+
+/// Poll the account while the server returns `event` after `last_event_id` and no further
+/// changes afterwards.
+fn poll_with_event(
+    ctx: &mut TestCtx,
+    last_event_id: &event::Id,
+    event: &event::Event,
+) -> PollOutput {
+    let _event_mock = proton_api::mocks::events::get_event(&mut ctx.server, last_event_id, event);
+    let _exit_mock = proton_api::mocks::events::get_event(
+        &mut ctx.server,
+        &event.event_id,
+        &empty_event(&event.event_id),
+    );
+    ctx.yhm.poll().unwrap().remove(0)
+}
+
 const ACCOUNT_EMAIL: &str = proton_api::mocks::DEFAULT_USER_EMAIL;
 const ACCOUNT_EMAIL_NO_SUFFIX: &str = "foo";